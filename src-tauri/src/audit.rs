@@ -0,0 +1,169 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AuditError {
+    #[error("Failed to read audit log: {0}")]
+    ReadError(String),
+    #[error("Failed to write audit log: {0}")]
+    WriteError(String),
+}
+
+// Something that touched the user's data, recorded so report numbers can be traced back
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind")]
+pub enum AuditEvent {
+    FileOpened {
+        path: String,
+    },
+    DatabaseQueried {
+        // Connection strings can carry passwords, so only the host part is kept
+        source: String,
+        query: String,
+        extract_path: String,
+    },
+    PivotRun {
+        request: serde_json::Value,
+        rows: usize,
+    },
+    ExportWritten {
+        pivot: String,
+        request: serde_json::Value,
+        destination: String,
+    },
+}
+
+impl AuditEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            AuditEvent::FileOpened { .. } => "FileOpened",
+            AuditEvent::DatabaseQueried { .. } => "DatabaseQueried",
+            AuditEvent::PivotRun { .. } => "PivotRun",
+            AuditEvent::ExportWritten { .. } => "ExportWritten",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditEntry {
+    // RFC 3339, UTC
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AuditQuery {
+    // RFC 3339 bounds, both inclusive
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub kinds: Option<Vec<String>>,
+    // Substring matched against the entry's JSON, e.g. a file path
+    pub search: Option<String>,
+    // Most recent entries win when the limit cuts the result
+    pub limit: Option<usize>,
+}
+
+// Append-only JSON Lines log. Entries are never rewritten or removed by the app.
+pub struct AuditLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        AuditLog {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn record(&self, event: AuditEvent) -> Result<(), AuditError> {
+        let entry = AuditEntry {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            event,
+        };
+        let line = serde_json::to_string(&entry)
+            .map_err(|e| AuditError::WriteError(e.to_string()))?;
+
+        let _guard = self.lock.lock().unwrap();
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|e| AuditError::WriteError(e.to_string()))?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| AuditError::WriteError(e.to_string()))?;
+        writeln!(file, "{}", line).map_err(|e| AuditError::WriteError(e.to_string()))
+    }
+
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, AuditError> {
+        let since = query.since.as_deref().map(parse_timestamp).transpose()?;
+        let until = query.until.as_deref().map(parse_timestamp).transpose()?;
+
+        let _guard = self.lock.lock().unwrap();
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let file = File::open(&self.path).map_err(|e| AuditError::ReadError(e.to_string()))?;
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| AuditError::ReadError(e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(search) = &query.search {
+                if !line.contains(search.as_str()) {
+                    continue;
+                }
+            }
+
+            let entry: AuditEntry = serde_json::from_str(&line)
+                .map_err(|e| AuditError::ReadError(e.to_string()))?;
+            let timestamp = parse_timestamp(&entry.timestamp)?;
+            if since.is_some_and(|since| timestamp < since) || until.is_some_and(|until| timestamp > until) {
+                continue;
+            }
+            if let Some(kinds) = &query.kinds {
+                if !kinds.iter().any(|kind| kind == entry.event.kind()) {
+                    continue;
+                }
+            }
+
+            entries.push(entry);
+        }
+
+        if let Some(limit) = query.limit {
+            let skip = entries.len().saturating_sub(limit);
+            entries.drain(..skip);
+        }
+
+        Ok(entries)
+    }
+}
+
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, AuditError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|timestamp| timestamp.with_timezone(&Utc))
+        .map_err(|e| AuditError::ReadError(format!("{}: {}", value, e)))
+}
+
+// Strip credentials from a connection string before it is written to the log
+pub fn redact_connection_string(connection_string: &str) -> String {
+    match connection_string.split_once("://") {
+        Some((scheme, rest)) => {
+            let host = rest.rsplit_once('@').map(|(_, host)| host).unwrap_or(rest);
+            let host = host.split(['?', ' ']).next().unwrap_or(host);
+            format!("{}://{}", scheme, host)
+        },
+        None => "<connection string>".to_string(),
+    }
+}
@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CatalogError {
+    #[error("Failed to read catalog: {0}")]
+    ReadError(String),
+    #[error("Failed to write catalog: {0}")]
+    WriteError(String),
+}
+
+// What an analyst knows about a column that the file itself doesn't say
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ColumnMetadata {
+    pub description: Option<String>,
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl ColumnMetadata {
+    fn is_empty(&self) -> bool {
+        self.description.is_none() && self.unit.is_none() && self.tags.is_empty()
+    }
+}
+
+// Dataset path -> column name -> metadata
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Catalog {
+    datasets: HashMap<String, HashMap<String, ColumnMetadata>>,
+}
+
+impl Catalog {
+    pub fn load(path: &Path) -> Result<Self, CatalogError> {
+        if !path.exists() {
+            return Ok(Catalog::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .map_err(|e| CatalogError::ReadError(e.to_string()))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| CatalogError::ReadError(e.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CatalogError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| CatalogError::WriteError(e.to_string()))?;
+        }
+
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| CatalogError::WriteError(e.to_string()))?;
+        fs::write(path, contents).map_err(|e| CatalogError::WriteError(e.to_string()))
+    }
+
+    pub fn dataset(&self, dataset_path: &str) -> HashMap<String, ColumnMetadata> {
+        self.datasets.get(dataset_path).cloned().unwrap_or_default()
+    }
+
+    // Empty metadata removes the column's entry
+    pub fn set_column(&mut self, dataset_path: &str, column: &str, metadata: ColumnMetadata) {
+        let columns = self.datasets.entry(dataset_path.to_string()).or_default();
+        if metadata.is_empty() {
+            columns.remove(column);
+        } else {
+            columns.insert(column.to_string(), metadata);
+        }
+
+        if columns.is_empty() {
+            self.datasets.remove(dataset_path);
+        }
+    }
+}
+
+// Catalog held in Tauri state, written back to disk on every change
+pub struct CatalogState {
+    path: PathBuf,
+    catalog: Mutex<Catalog>,
+}
+
+impl CatalogState {
+    pub fn load(path: PathBuf) -> Self {
+        // An unreadable catalog shouldn't keep the app from starting
+        let catalog = Catalog::load(&path).unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            Catalog::default()
+        });
+
+        CatalogState {
+            path,
+            catalog: Mutex::new(catalog),
+        }
+    }
+
+    pub fn dataset(&self, dataset_path: &str) -> HashMap<String, ColumnMetadata> {
+        self.catalog.lock().unwrap().dataset(dataset_path)
+    }
+
+    pub fn set_column(
+        &self,
+        dataset_path: &str,
+        column: &str,
+        metadata: ColumnMetadata,
+    ) -> Result<(), CatalogError> {
+        let mut catalog = self.catalog.lock().unwrap();
+        catalog.set_column(dataset_path, column, metadata);
+        catalog.save(&self.path)
+    }
+}
@@ -0,0 +1,239 @@
+use polars::io::cloud::{AmazonS3ConfigKey, AzureConfigKey, CloudOptions, GoogleConfigKey};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CloudError {
+    #[error("Failed to read cloud storage settings: {0}")]
+    ReadError(String),
+    #[error("Failed to write cloud storage settings: {0}")]
+    WriteError(String),
+}
+
+// Credentials for s3:// data paths. Anything left unset here (for every provider)
+// falls back to the provider's usual environment variables and config files.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct S3Config {
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    pub region: Option<String>,
+    // For S3-compatible stores such as MinIO
+    pub endpoint_url: Option<String>,
+    // Set to forget the saved secrets; otherwise a secret left unset keeps its saved value
+    #[serde(default, skip_serializing)]
+    pub clear_secrets: bool,
+}
+
+impl S3Config {
+    fn keys(&self) -> Vec<(AmazonS3ConfigKey, String)> {
+        set_keys([
+            (AmazonS3ConfigKey::AccessKeyId, &self.access_key_id),
+            (AmazonS3ConfigKey::SecretAccessKey, &self.secret_access_key),
+            (AmazonS3ConfigKey::Token, &self.session_token),
+            (AmazonS3ConfigKey::Region, &self.region),
+            (AmazonS3ConfigKey::Endpoint, &self.endpoint_url),
+        ])
+    }
+
+    // What the frontend gets to see: secrets are never sent back
+    pub fn redacted(&self) -> Self {
+        S3Config {
+            secret_access_key: None,
+            session_token: None,
+            ..self.clone()
+        }
+    }
+
+    fn keep_secrets(self, current: &S3Config) -> Self {
+        if self.clear_secrets {
+            return S3Config {
+                clear_secrets: false,
+                ..self
+            };
+        }
+        S3Config {
+            secret_access_key: self.secret_access_key.or(current.secret_access_key.clone()),
+            session_token: self.session_token.or(current.session_token.clone()),
+            ..self
+        }
+    }
+}
+
+// Credentials for az:// and abfs:// data paths
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AzureConfig {
+    pub account_name: Option<String>,
+    pub account_key: Option<String>,
+    pub sas_token: Option<String>,
+    #[serde(default, skip_serializing)]
+    pub clear_secrets: bool,
+}
+
+impl AzureConfig {
+    fn keys(&self) -> Vec<(AzureConfigKey, String)> {
+        set_keys([
+            (AzureConfigKey::AccountName, &self.account_name),
+            (AzureConfigKey::AccessKey, &self.account_key),
+            (AzureConfigKey::SasKey, &self.sas_token),
+        ])
+    }
+
+    pub fn redacted(&self) -> Self {
+        AzureConfig {
+            account_key: None,
+            sas_token: None,
+            ..self.clone()
+        }
+    }
+
+    fn keep_secrets(self, current: &AzureConfig) -> Self {
+        if self.clear_secrets {
+            return AzureConfig {
+                clear_secrets: false,
+                ..self
+            };
+        }
+        AzureConfig {
+            account_key: self.account_key.or(current.account_key.clone()),
+            sas_token: self.sas_token.or(current.sas_token.clone()),
+            ..self
+        }
+    }
+}
+
+// Credentials for gs:// data paths
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GcsConfig {
+    // Path to a service account JSON key file
+    pub service_account_path: Option<String>,
+}
+
+impl GcsConfig {
+    fn keys(&self) -> Vec<(GoogleConfigKey, String)> {
+        set_keys([(GoogleConfigKey::ServiceAccount, &self.service_account_path)])
+    }
+}
+
+// Every provider's settings, stored together in one file
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CloudConfig {
+    #[serde(default)]
+    pub s3: S3Config,
+    #[serde(default)]
+    pub azure: AzureConfig,
+    #[serde(default)]
+    pub gcs: GcsConfig,
+}
+
+impl CloudConfig {
+    pub fn load(path: &Path) -> Result<Self, CloudError> {
+        if !path.exists() {
+            return Ok(CloudConfig::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .map_err(|e| CloudError::ReadError(e.to_string()))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| CloudError::ReadError(e.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CloudError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| CloudError::WriteError(e.to_string()))?;
+        }
+
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| CloudError::WriteError(e.to_string()))?;
+        fs::write(path, contents).map_err(|e| CloudError::WriteError(e.to_string()))
+    }
+
+    // Makes these the settings every later scan of cloud data uses
+    fn apply(&self) {
+        *ACTIVE.write().unwrap() = Some(self.clone());
+    }
+}
+
+// The settings in use, read by every scan. Kept here rather than in environment
+// variables, which can't be changed safely while other threads are scanning.
+static ACTIVE: RwLock<Option<CloudConfig>> = RwLock::new(None);
+
+// Credentials for scanning `file_path`, built from the settings in use. Polars
+// starts from the provider's environment variables and config files and
+// overrides them with these.
+pub fn cloud_options(file_path: &str) -> CloudOptions {
+    let options = CloudOptions::default();
+    let active = ACTIVE.read().unwrap();
+    let Some(config) = active.as_ref() else {
+        return options;
+    };
+
+    let lower = file_path.to_lowercase();
+    if lower.starts_with("s3://") {
+        options.with_aws(config.s3.keys())
+    } else if lower.starts_with("gs://") || lower.starts_with("gcs://") {
+        options.with_gcp(config.gcs.keys())
+    } else {
+        options.with_azure(config.azure.keys())
+    }
+}
+
+fn set_keys<K, const N: usize>(keys: [(K, &Option<String>); N]) -> Vec<(K, String)> {
+    keys.into_iter()
+        .filter_map(|(key, value)| Some((key, value.clone()?)))
+        .collect()
+}
+
+// Cloud settings held in Tauri state, written back to disk on every change.
+// The frontend only ever sees redacted settings, so missing secrets keep their
+// saved value unless it asks for them to be cleared.
+pub struct CloudState {
+    path: PathBuf,
+    config: Mutex<CloudConfig>,
+}
+
+impl CloudState {
+    pub fn load(path: PathBuf) -> Self {
+        // Missing credentials only matter once a cloud path is opened
+        let config = CloudConfig::load(&path).unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            CloudConfig::default()
+        });
+        config.apply();
+
+        CloudState {
+            path,
+            config: Mutex::new(config),
+        }
+    }
+
+    pub fn get(&self) -> CloudConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    fn update(&self, change: impl FnOnce(&mut CloudConfig)) -> Result<(), CloudError> {
+        let mut config = self.config.lock().unwrap();
+        let mut updated = config.clone();
+        change(&mut updated);
+
+        updated.save(&self.path)?;
+        updated.apply();
+        *config = updated;
+        Ok(())
+    }
+
+    pub fn set_s3(&self, s3: S3Config) -> Result<(), CloudError> {
+        self.update(|config| config.s3 = s3.keep_secrets(&config.s3))
+    }
+
+    pub fn set_azure(&self, azure: AzureConfig) -> Result<(), CloudError> {
+        self.update(|config| config.azure = azure.keep_secrets(&config.azure))
+    }
+
+    pub fn set_gcs(&self, gcs: GcsConfig) -> Result<(), CloudError> {
+        self.update(|config| config.gcs = gcs)
+    }
+}
@@ -0,0 +1,373 @@
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Instant, SystemTime};
+
+use crate::polars_bridge::{self, DataError, PivotRequest, ReadOptions};
+
+// Datasets up to this estimated size are loaded into memory when opened; larger
+// ones are kept as scans and read again by each pivot
+const LOAD_LIMIT_BYTES: u64 = 512 * 1024 * 1024;
+// Memory all open datasets may hold between them, loaded and working frames
+// alike; the least recently used give theirs up first
+const HELD_LIMIT_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+enum DatasetFrame {
+    Scanned(LazyFrame),
+    Loaded(DataFrame),
+}
+
+struct Dataset {
+    // Modification time of the files when the dataset was opened
+    modified: SystemTime,
+    frame: DatasetFrame,
+    // Columns of a scanned dataset loaded for the pivots run on it so far. These
+    // are the raw rows: no filtered or aggregated frame is kept between pivots.
+    working: Option<DataFrame>,
+    last_used: Instant,
+}
+
+impl Dataset {
+    // Memory taken by the rows held for the dataset
+    fn held_bytes(&self) -> u64 {
+        let loaded = match &self.frame {
+            DatasetFrame::Scanned(_) => 0,
+            DatasetFrame::Loaded(df) => df.estimated_size(),
+        };
+        (loaded + self.working.as_ref().map_or(0, |df| df.estimated_size())) as u64
+    }
+
+    fn lazy(&self) -> LazyFrame {
+        match &self.frame {
+            DatasetFrame::Scanned(lf) => lf.clone(),
+            DatasetFrame::Loaded(df) => df.clone().lazy(),
+        }
+    }
+
+    fn info(&self, dataset_id: u64) -> DatasetInfo {
+        match &self.frame {
+            DatasetFrame::Scanned(_) => DatasetInfo { dataset_id, loaded: false, rows: None },
+            DatasetFrame::Loaded(df) => DatasetInfo { dataset_id, loaded: true, rows: Some(df.height()) },
+        }
+    }
+}
+
+// What a dataset id stands for
+struct DatasetHandle {
+    path: String,
+    options: ReadOptions,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DatasetInfo {
+    // Handle that pivot requests name the dataset by, in place of its path
+    pub dataset_id: u64,
+    // Whether the rows are held in memory rather than scanned from the file
+    pub loaded: bool,
+    pub rows: Option<usize>,
+}
+
+// Open datasets held in Tauri state by path and read options, so repeated pivots
+// on a file don't read it again. An entry is dropped once its files change on
+// disk, or when its rows are the least recently used over HELD_LIMIT_BYTES.
+// Each path and read options opened gets an id, so several datasets can be open
+// at once and requests refer to them without repeating their paths.
+#[derive(Default)]
+pub struct DatasetManager {
+    next_id: AtomicU64,
+    handles: Mutex<HashMap<u64, DatasetHandle>>,
+    datasets: Mutex<HashMap<String, Dataset>>,
+    // Datasets being read by `prewarm`, which pivots on them wait for
+    loading: Mutex<HashSet<String>>,
+    loaded: Condvar,
+}
+
+impl DatasetManager {
+    // The id of the dataset, straight away; what it holds so far when it was
+    // opened before. `prewarm` reads it.
+    pub fn open(&self, file_path: &str, options: &ReadOptions) -> DatasetInfo {
+        let dataset_id = self.handle(file_path, options);
+        let key = dataset_key(file_path, options);
+        match self.datasets.lock().unwrap().get(&key) {
+            Some(dataset) if Some(dataset.modified) == polars_bridge::data_modified(file_path) => dataset.info(dataset_id),
+            _ => DatasetInfo { dataset_id, loaded: false, rows: None },
+        }
+    }
+
+    // Reads an opened dataset ahead of its first pivot: infers its schema (from
+    // the footer of Parquet files), and loads it into memory when it is small.
+    // None when there was nothing to do: the dataset is ready or being read
+    // already, or is cloud data, which can't be checked for changes and so is
+    // read on every use.
+    pub fn prewarm(&self, dataset_id: u64) -> Result<Option<DatasetInfo>, DataError> {
+        let (file_path, options) = match self.handles.lock().unwrap().get(&dataset_id) {
+            Some(handle) => (handle.path.clone(), handle.options.clone()),
+            None => return Err(DataError::DatasetNotOpen { dataset_id }),
+        };
+        let Some(modified) = polars_bridge::data_modified(&file_path) else {
+            return Ok(None);
+        };
+
+        let key = dataset_key(&file_path, &options);
+        if self.datasets.lock().unwrap().get(&key).is_some_and(|dataset| dataset.modified == modified) {
+            return Ok(None);
+        }
+        if !self.loading.lock().unwrap().insert(key.clone()) {
+            return Ok(None);
+        }
+        let _loading = Loading { manager: self, key: &key };
+
+        // Read without holding the lock, so other datasets stay usable meanwhile
+        let read = || -> Result<DatasetFrame, DataError> {
+            let mut lf = polars_bridge::read_data(&file_path, &options)?;
+            lf.schema().map_err(|e| DataError::ReadError(e.to_string()))?;
+            let small = polars_bridge::estimated_size(&file_path).is_some_and(|size| size <= LOAD_LIMIT_BYTES);
+            if small {
+                Ok(DatasetFrame::Loaded(lf.collect().map_err(|e| DataError::ReadError(e.to_string()))?))
+            } else {
+                Ok(DatasetFrame::Scanned(lf))
+            }
+        };
+        let frame = read();
+
+        let info = frame.map(|frame| {
+            let dataset = Dataset {
+                modified,
+                frame,
+                working: None,
+                last_used: Instant::now(),
+            };
+            let info = dataset.info(dataset_id);
+            let mut datasets = self.datasets.lock().unwrap();
+            datasets.insert(key.clone(), dataset);
+            release_memory(&mut datasets);
+            info
+        });
+        info.map(Some)
+    }
+
+    // The id of the path and read options, the one given before if they were
+    // opened already
+    fn handle(&self, file_path: &str, options: &ReadOptions) -> u64 {
+        let key = dataset_key(file_path, options);
+        let mut handles = self.handles.lock().unwrap();
+        let existing = handles.iter()
+            .find(|(_, handle)| dataset_key(&handle.path, &handle.options) == key)
+            .map(|(&dataset_id, _)| dataset_id);
+        if let Some(dataset_id) = existing {
+            return dataset_id;
+        }
+
+        let dataset_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        handles.insert(dataset_id, DatasetHandle { path: file_path.to_string(), options: options.clone() });
+        dataset_id
+    }
+
+    pub fn close(&self, dataset_id: u64) {
+        if let Some(handle) = self.handles.lock().unwrap().remove(&dataset_id) {
+            self.datasets.lock().unwrap().remove(&dataset_key(&handle.path, &handle.options));
+        }
+    }
+
+    // Fills in the path and read options of the dataset a request names by id
+    pub fn resolve(&self, request: &mut PivotRequest) -> Result<(), DataError> {
+        let Some(dataset_id) = request.dataset_id else {
+            return Ok(());
+        };
+        let handles = self.handles.lock().unwrap();
+        let handle = handles.get(&dataset_id).ok_or(DataError::DatasetNotOpen { dataset_id })?;
+        request.data_path = handle.path.clone();
+        request.read_options = Some(handle.options.clone());
+        Ok(())
+    }
+
+    // The data at `file_path`, from the open dataset while its files are unchanged
+    pub fn frame(&self, file_path: &str, options: &ReadOptions) -> Result<LazyFrame, DataError> {
+        let key = dataset_key(file_path, options);
+        {
+            let mut datasets = self.datasets.lock().unwrap();
+            match datasets.get_mut(&key) {
+                Some(dataset) if Some(dataset.modified) == polars_bridge::data_modified(file_path) => {
+                    dataset.last_used = Instant::now();
+                    return Ok(dataset.lazy());
+                },
+                Some(_) => {
+                    datasets.remove(&key);
+                },
+                None => {},
+            }
+        }
+
+        polars_bridge::read_data(file_path, options)
+    }
+
+    // The data a pivot reads. On an open dataset too large to load whole, the
+    // columns the pivot uses are loaded and kept, so later pivots that only change
+    // filters or measures over those columns don't scan the file again. They
+    // still filter and aggregate those rows from the start; only identical
+    // requests are answered without that, by the result cache.
+    pub fn frame_for(&self, request: &PivotRequest) -> Result<LazyFrame, DataError> {
+        let options = request.read_options.clone().unwrap_or_default();
+        self.frame_with_columns(&request.data_path, &options, request.referenced_columns())
+    }
+
+    // The data a batch of pivots on one dataset reads, read once for all of them.
+    // Data that isn't open is loaded here with the columns the pivots use, when
+    // those fit in memory, so each pivot doesn't scan the file again.
+    pub fn frame_for_batch(&self, requests: &[&PivotRequest]) -> Result<LazyFrame, DataError> {
+        let Some(first) = requests.first() else {
+            return Err(DataError::ProcessingError("No pivots to run".to_string()));
+        };
+        let options = first.read_options.clone().unwrap_or_default();
+        let referenced = requests.iter()
+            .map(|request| request.referenced_columns())
+            .try_fold(HashSet::new(), |mut all, columns| {
+                all.extend(columns?);
+                Some(all)
+            });
+        let mut lf = self.frame_with_columns(&first.data_path, &options, referenced.clone())?;
+
+        let open = self.datasets.lock().unwrap().contains_key(&dataset_key(&first.data_path, &options));
+        let Some(referenced) = referenced.filter(|_| !open && requests.len() > 1) else {
+            return Ok(lf);
+        };
+        let schema = lf.schema().map_err(|e| DataError::ReadError(e.to_string()))?;
+        let columns: Vec<Expr> = schema.iter_names()
+            .filter(|name| referenced.contains(name.as_str()))
+            .map(|name| col(name))
+            .collect();
+        let fits = polars_bridge::estimated_size(&first.data_path)
+            .is_some_and(|size| size / schema.len().max(1) as u64 * columns.len() as u64 <= LOAD_LIMIT_BYTES);
+        if !fits {
+            return Ok(lf);
+        }
+
+        let df = lf.select(columns)
+            .collect()
+            .map_err(|e| DataError::ReadError(e.to_string()))?;
+        Ok(df.lazy())
+    }
+
+    fn frame_with_columns(
+        &self,
+        file_path: &str,
+        options: &ReadOptions,
+        referenced: Option<HashSet<String>>,
+    ) -> Result<LazyFrame, DataError> {
+        let key = dataset_key(file_path, options);
+        // A dataset still being read is worth waiting for rather than read twice
+        let mut loading = self.loading.lock().unwrap();
+        while loading.contains(&key) {
+            loading = self.loaded.wait(loading).unwrap();
+        }
+        drop(loading);
+
+        let lf = self.frame(file_path, options)?;
+        let Some(referenced) = referenced else {
+            return Ok(lf);
+        };
+
+        let (mut scan, working) = match self.datasets.lock().unwrap().get(&key) {
+            Some(Dataset { frame: DatasetFrame::Scanned(scan), working, .. }) => (scan.clone(), working.clone()),
+            _ => return Ok(lf),
+        };
+
+        let schema = scan.schema().map_err(|e| DataError::ReadError(e.to_string()))?;
+        let mut columns: Vec<String> = schema.iter_names()
+            .filter(|name| referenced.contains(name.as_str()))
+            .map(|name| name.to_string())
+            .collect();
+        let size = polars_bridge::estimated_size(file_path);
+        let fits = |count: usize| {
+            size.is_some_and(|size| size / schema.len().max(1) as u64 * count as u64 <= LOAD_LIMIT_BYTES)
+        };
+
+        if let Some(working) = &working {
+            if columns.iter().all(|name| working.get_column_index(name).is_some()) {
+                return Ok(working.clone().lazy());
+            }
+            // Keep the columns loaded before too while they fit, for pivots going back to them
+            let kept: Vec<String> = working.get_column_names().iter()
+                .map(|name| name.to_string())
+                .filter(|name| !columns.contains(name))
+                .collect();
+            if fits(columns.len() + kept.len()) {
+                columns.extend(kept);
+            }
+        }
+        if !fits(columns.len()) {
+            return Ok(lf);
+        }
+
+        let df = scan.select(columns.iter().map(|name| col(name)).collect::<Vec<Expr>>())
+            .collect()
+            .map_err(|e| DataError::ReadError(e.to_string()))?;
+        let mut datasets = self.datasets.lock().unwrap();
+        if let Some(dataset) = datasets.get_mut(&key) {
+            dataset.working = Some(df.clone());
+            release_memory(&mut datasets);
+        }
+        Ok(df.lazy())
+    }
+}
+
+// Drops the rows held for the least recently used datasets until all of them
+// fit in HELD_LIMIT_BYTES. A loaded dataset is removed, so its pivots read the
+// files again, as they do for data that isn't open.
+fn release_memory(datasets: &mut HashMap<String, Dataset>) {
+    let mut held: u64 = datasets.values().map(Dataset::held_bytes).sum();
+    while held > HELD_LIMIT_BYTES {
+        let Some((key, bytes)) = datasets.iter()
+            .filter(|(_, dataset)| dataset.held_bytes() > 0)
+            .min_by_key(|(_, dataset)| dataset.last_used)
+            .map(|(key, dataset)| (key.clone(), dataset.held_bytes()))
+        else {
+            return;
+        };
+        tracing::debug!(bytes, "Released the rows held for a dataset");
+
+        held -= bytes;
+        if matches!(datasets[&key].frame, DatasetFrame::Loaded(_)) {
+            datasets.remove(&key);
+        } else if let Some(dataset) = datasets.get_mut(&key) {
+            dataset.working = None;
+        }
+    }
+}
+
+// A dataset being read by `prewarm`. Dropping it, even while unwinding from a
+// panic in Polars, lets the pivots waiting for the dataset go on.
+struct Loading<'a> {
+    manager: &'a DatasetManager,
+    key: &'a str,
+}
+
+impl Drop for Loading<'_> {
+    fn drop(&mut self) {
+        // Not poisoned by the panic, which happens outside the lock
+        if let Ok(mut loading) = self.manager.loading.lock() {
+            loading.remove(self.key);
+        }
+        self.manager.loaded.notify_all();
+    }
+}
+
+// Indices of the requests on each dataset, in the order the datasets first appear
+pub fn group_by_dataset(requests: &[PivotRequest]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    for (index, request) in requests.iter().enumerate() {
+        let key = dataset_key(&request.data_path, &request.read_options.clone().unwrap_or_default());
+        match groups.iter_mut().find(|(group_key, _)| *group_key == key) {
+            Some((_, indices)) => indices.push(index),
+            None => groups.push((key, vec![index])),
+        }
+    }
+    groups.into_iter().map(|(_, indices)| indices).collect()
+}
+
+// The maps in read options are BTreeMaps, so equal options make the same key
+fn dataset_key(file_path: &str, options: &ReadOptions) -> String {
+    format!("{}\n{}", file_path, serde_json::to_string(options).unwrap_or_default())
+}
@@ -0,0 +1,108 @@
+use serde::Serialize;
+
+use crate::audit::AuditError;
+use crate::catalog::CatalogError;
+use crate::cloud::CloudError;
+use crate::polars_bridge::DataError;
+use crate::settings::SettingsError;
+use crate::views::ViewsError;
+
+// What went wrong, for the UI to react to without parsing messages
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    FileNotFound,
+    ColumnNotFound,
+    DatasetNotOpen,
+    ReadFailed,
+    UnsupportedFormat,
+    MemoryBudgetExceeded,
+    TooManyColumns,
+    ResultTooLarge,
+    Cancelled,
+    ProcessingFailed,
+    // A paged result that was dropped to make room for newer ones
+    ResultUnavailable,
+    // The app's own files: settings, saved views, the catalog, the audit log
+    StorageFailed,
+    Internal,
+}
+
+// Error returned by every command, serialized as
+// { code, message, column?, path? }
+#[derive(Serialize, Debug, Clone)]
+pub struct CommandError {
+    pub code: ErrorCode,
+    pub message: String,
+    // The column the error is about, e.g. a row field missing from the data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<String>,
+    // The file or data path the error is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl CommandError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        CommandError {
+            code,
+            message: message.into(),
+            column: None,
+            path: None,
+        }
+    }
+}
+
+impl From<DataError> for CommandError {
+    fn from(error: DataError) -> Self {
+        let code = match &error {
+            DataError::FileNotFound { .. } => ErrorCode::FileNotFound,
+            DataError::ColumnNotFound { .. } => ErrorCode::ColumnNotFound,
+            DataError::DatasetNotOpen { .. } => ErrorCode::DatasetNotOpen,
+            DataError::ReadError(_) => ErrorCode::ReadFailed,
+            DataError::UnsupportedFormat(_) => ErrorCode::UnsupportedFormat,
+            DataError::MemoryBudgetExceeded { .. } => ErrorCode::MemoryBudgetExceeded,
+            DataError::TooManyColumns { .. } => ErrorCode::TooManyColumns,
+            DataError::ResultTooLarge { .. } => ErrorCode::ResultTooLarge,
+            DataError::Cancelled => ErrorCode::Cancelled,
+            DataError::ProcessingError(_) => ErrorCode::ProcessingFailed,
+        };
+        let mut command_error = CommandError::new(code, error.to_string());
+        match error {
+            DataError::FileNotFound { path } => command_error.path = Some(path),
+            DataError::ColumnNotFound { column } => command_error.column = Some(column),
+            _ => {},
+        }
+        command_error
+    }
+}
+
+macro_rules! storage_errors {
+    ($($error:ty),*) => {
+        $(impl From<$error> for CommandError {
+            fn from(error: $error) -> Self {
+                CommandError::new(ErrorCode::StorageFailed, error.to_string())
+            }
+        })*
+    };
+}
+
+storage_errors!(AuditError, CatalogError, CloudError, SettingsError, ViewsError);
+
+impl From<tauri::Error> for CommandError {
+    fn from(error: tauri::Error) -> Self {
+        CommandError::new(ErrorCode::Internal, error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for CommandError {
+    fn from(error: serde_json::Error) -> Self {
+        CommandError::new(ErrorCode::Internal, error.to_string())
+    }
+}
+
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        CommandError::new(ErrorCode::Internal, message)
+    }
+}
@@ -0,0 +1,84 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::errors::CommandError;
+use crate::polars_bridge::{DataError, PivotPage, PivotPages, PivotProgress, PivotResult};
+
+// Paged results kept for get_pivot_page; older ones are dropped first
+const MAX_KEPT_RESULTS: usize = 8;
+
+// Pivots running in the background, each with the flag that cancels it, and the
+// rows of the latest paged results
+#[derive(Default)]
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    jobs: Mutex<HashMap<u64, Arc<AtomicBool>>>,
+    results: Mutex<HashMap<u64, PivotPages>>,
+}
+
+impl JobRegistry {
+    pub fn start(&self) -> (u64, Arc<AtomicBool>) {
+        let job_id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let flag = Arc::new(AtomicBool::new(false));
+        self.jobs.lock().unwrap().insert(job_id, flag.clone());
+        (job_id, flag)
+    }
+
+    // False when the job has already finished
+    pub fn cancel(&self, job_id: u64) -> bool {
+        match self.jobs.lock().unwrap().get(&job_id) {
+            Some(flag) => {
+                flag.store(true, Ordering::Relaxed);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn finish(&self, job_id: u64) {
+        self.jobs.lock().unwrap().remove(&job_id);
+    }
+
+    pub fn keep_pages(&self, job_id: u64, pages: PivotPages) {
+        let mut results = self.results.lock().unwrap();
+        results.insert(job_id, pages);
+        while results.len() > MAX_KEPT_RESULTS {
+            let oldest = *results.keys().min().expect("there are results");
+            results.remove(&oldest);
+        }
+    }
+
+    // None when the job's result was paged out or never kept
+    pub fn page(&self, job_id: u64, offset: usize, limit: usize) -> Option<Result<PivotPage, DataError>> {
+        self.results.lock().unwrap().get(&job_id).map(|pages| pages.page(offset, limit))
+    }
+}
+
+// Sent with the "pivot://progress" events of a job
+#[derive(Serialize, Debug, Clone)]
+pub struct PivotJobProgress {
+    pub job_id: u64,
+    #[serde(flatten)]
+    pub progress: PivotProgress,
+}
+
+// Sent with the "pivot://finished" event once a job is done
+#[derive(Serialize, Debug, Clone)]
+pub struct PivotJobOutcome {
+    pub job_id: u64,
+    pub result: Option<PivotResult>,
+    pub error: Option<CommandError>,
+    pub cancelled: bool,
+}
+
+impl PivotJobOutcome {
+    pub fn new(job_id: u64, result: Result<PivotResult, DataError>) -> Self {
+        match result {
+            Ok(result) => PivotJobOutcome { job_id, result: Some(result), error: None, cancelled: false },
+            Err(DataError::Cancelled) => PivotJobOutcome { job_id, result: None, error: None, cancelled: true },
+            Err(e) => PivotJobOutcome { job_id, result: None, error: Some(e.into()), cancelled: false },
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{Builder, Rotation};
+use tracing_subscriber::EnvFilter;
+
+const LOG_FILE_PREFIX: &str = "turbopivot";
+const LOG_FILE_SUFFIX: &str = "log";
+// Days of log files kept before the oldest are deleted
+const MAX_LOG_FILES: usize = 7;
+const DEFAULT_LOG_LEVEL: &str = "info";
+
+// Log files in the app data dir, one per day. Held in Tauri state: dropping the
+// guard stops the background writer.
+pub struct LogState {
+    dir: PathBuf,
+    _guard: WorkerGuard,
+}
+
+impl LogState {
+    // Starts writing log messages at `level` and above; an unreadable level
+    // falls back to "info"
+    pub fn init(dir: PathBuf, level: Option<&str>) -> Result<Self, String> {
+        let appender = Builder::new()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(LOG_FILE_PREFIX)
+            .filename_suffix(LOG_FILE_SUFFIX)
+            .max_log_files(MAX_LOG_FILES)
+            .build(&dir)
+            .map_err(|e| e.to_string())?;
+        let (writer, guard) = tracing_appender::non_blocking(appender);
+
+        let filter = EnvFilter::try_new(level.unwrap_or(DEFAULT_LOG_LEVEL))
+            .unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_LEVEL));
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(writer)
+            .with_ansi(false)
+            .try_init()
+            .map_err(|e| e.to_string())?;
+
+        Ok(LogState { dir, _guard: guard })
+    }
+
+    // The last `lines` lines of the newest log file, oldest first
+    pub fn recent(&self, lines: usize) -> Result<Vec<String>, String> {
+        let newest = fs::read_dir(&self.dir)
+            .map_err(|e| e.to_string())?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(LOG_FILE_PREFIX))
+            })
+            // Files are named by date, so the newest sorts last
+            .max();
+        let Some(path) = newest else {
+            return Ok(Vec::new());
+        };
+
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let all: Vec<&str> = contents.lines().collect();
+        Ok(all[all.len().saturating_sub(lines)..].iter().map(|line| line.to_string()).collect())
+    }
+}
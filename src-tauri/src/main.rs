@@ -3,6 +3,21 @@
 
 // Import the polars_bridge module
 mod polars_bridge;
+mod catalog;
+mod settings;
+mod views;
+mod audit;
+mod cloud;
+mod datasets;
+mod jobs;
+mod logging;
+mod errors;
+mod result_cache;
+
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use errors::{CommandError, ErrorCode};
 
 // Greet command from the original lib.rs
 #[tauri::command]
@@ -11,27 +26,739 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-fn get_csv_columns(file_path: String) -> Result<Vec<String>, String> {
-    polars_bridge::get_column_names(&file_path)
-        .map_err(|e| e.to_string())
+fn get_csv_columns(
+    file_path: String,
+    read_options: Option<polars_bridge::ReadOptions>,
+    datasets: State<'_, datasets::DatasetManager>,
+    audit: State<'_, audit::AuditLog>,
+) -> Result<Vec<String>, CommandError> {
+    let schema = datasets.frame(&file_path, &read_options.unwrap_or_default())
+        .and_then(|mut lf| lf.schema().map_err(|e| polars_bridge::DataError::ProcessingError(e.to_string())))
+        .map_err(CommandError::from)?;
+    let columns = schema.iter_names().map(|name| name.to_string()).collect();
+
+    audit.record(audit::AuditEvent::FileOpened { path: file_path })
+        .map_err(CommandError::from)?;
+    Ok(columns)
+}
+
+// Keeps the file's data at hand so later pivots on it skip reading it again.
+// Returns the dataset's id right away and reads it in the background, sending a
+// "dataset://ready" event once it is loaded.
+#[tauri::command]
+fn open_dataset(
+    app: AppHandle,
+    file_path: String,
+    read_options: Option<polars_bridge::ReadOptions>,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> datasets::DatasetInfo {
+    let info = datasets.open(&file_path, &read_options.unwrap_or_default());
+    if info.loaded {
+        return info;
+    }
+
+    let dataset_id = info.dataset_id;
+    tauri::async_runtime::spawn_blocking(move || {
+        match app.state::<datasets::DatasetManager>().prewarm(dataset_id) {
+            Ok(Some(ready)) => {
+                tracing::debug!(dataset_id, rows = ?ready.rows, "Dataset ready");
+                let _ = app.emit("dataset://ready", ready);
+            },
+            Ok(None) => {},
+            Err(e) => tracing::warn!(dataset_id, "Failed to read dataset ahead: {}", e),
+        }
+    });
+    info
+}
+
+#[tauri::command]
+fn close_dataset(dataset_id: u64, datasets: State<'_, datasets::DatasetManager>) {
+    datasets.close(dataset_id);
 }
 
 #[tauri::command]
-fn run_pivot(request: polars_bridge::PivotRequest) -> Result<polars_bridge::PivotResult, String> {
-    polars_bridge::generate_pivot(request)
-        .map_err(|e| e.to_string())
+fn get_schema(
+    file_path: String,
+    read_options: Option<polars_bridge::ReadOptions>,
+    catalog: State<'_, catalog::CatalogState>,
+    audit: State<'_, audit::AuditLog>,
+) -> Result<polars_bridge::DatasetSchema, CommandError> {
+    let mut schema = polars_bridge::get_schema(&file_path, &read_options.unwrap_or_default())
+        .map_err(CommandError::from)?;
+    audit.record(audit::AuditEvent::FileOpened { path: file_path.clone() })
+        .map_err(CommandError::from)?;
+
+    let mut metadata = catalog.dataset(&file_path);
+    for column in &mut schema.columns {
+        column.metadata = metadata.remove(&column.name);
+    }
+
+    Ok(schema)
+}
+
+#[tauri::command]
+async fn get_file_stats(
+    file_path: String,
+    read_options: Option<polars_bridge::ReadOptions>,
+) -> Result<polars_bridge::FileStats, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        polars_bridge::file_stats(&file_path, &read_options.unwrap_or_default())
+            .map_err(CommandError::from)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+async fn get_value_counts(
+    file_path: String,
+    column: String,
+    read_options: Option<polars_bridge::ReadOptions>,
+    limit: Option<usize>,
+) -> Result<Vec<polars_bridge::ValueCount>, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        polars_bridge::value_counts(&file_path, &read_options.unwrap_or_default(), &column, limit)
+            .map_err(CommandError::from)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+fn get_column_metadata(
+    file_path: String,
+    catalog: State<'_, catalog::CatalogState>,
+) -> HashMap<String, catalog::ColumnMetadata> {
+    catalog.dataset(&file_path)
+}
+
+#[tauri::command]
+fn set_column_metadata(
+    file_path: String,
+    column: String,
+    metadata: catalog::ColumnMetadata,
+    catalog: State<'_, catalog::CatalogState>,
+) -> Result<(), CommandError> {
+    catalog.set_column(&file_path, &column, metadata)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+fn list_sqlite_tables(file_path: String) -> Result<Vec<String>, CommandError> {
+    polars_bridge::list_sqlite_tables(&file_path)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+fn list_excel_sheets(file_path: String) -> Result<Vec<String>, CommandError> {
+    polars_bridge::list_excel_sheets(&file_path)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn convert_to_parquet(
+    file_path: String,
+    read_options: Option<polars_bridge::ReadOptions>,
+) -> Result<polars_bridge::ParquetCache, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        polars_bridge::convert_to_parquet(&file_path, &read_options.unwrap_or_default())
+            .map_err(CommandError::from)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+async fn download_data(app: AppHandle, url: String) -> Result<String, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = polars_bridge::download_url(&url, true, |progress| {
+            let _ = app.emit("download://progress", progress);
+        })
+        .map_err(CommandError::from)?;
+
+        app.state::<audit::AuditLog>()
+            .record(audit::AuditEvent::FileOpened { path: url })
+            .map_err(CommandError::from)?;
+        Ok(path.display().to_string())
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+async fn connect_database(
+    app: AppHandle,
+    connection_string: String,
+    query: Option<String>,
+    // A table from list_database_tables to load whole instead of running a query
+    table: Option<String>,
+) -> Result<polars_bridge::DatabaseExtract, CommandError> {
+    let cache_dir = app.path().app_cache_dir().map_err(CommandError::from)?.join("extracts");
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let (extract, query) = match (table, query) {
+            (Some(table), _) => (
+                polars_bridge::extract_database_table(&connection_string, &table, &cache_dir),
+                format!("table {}", table),
+            ),
+            (None, Some(query)) => (
+                polars_bridge::extract_database_query(&connection_string, &query, &cache_dir),
+                query,
+            ),
+            (None, None) => {
+                return Err(CommandError::from(polars_bridge::DataError::ProcessingError(
+                    "Give a query to run or a table to load".to_string(),
+                )))
+            },
+        };
+        let extract = extract.map_err(CommandError::from)?;
+
+        app.state::<audit::AuditLog>()
+            .record(audit::AuditEvent::DatabaseQueried {
+                source: audit::redact_connection_string(&connection_string),
+                query,
+                extract_path: extract.data_path.clone(),
+            })
+            .map_err(CommandError::from)?;
+        Ok(extract)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+async fn run_unpivot(
+    app: AppHandle,
+    request: polars_bridge::UnpivotRequest,
+) -> Result<polars_bridge::DatabaseExtract, CommandError> {
+    let cache_dir = app.path().app_cache_dir().map_err(CommandError::from)?.join("extracts");
+
+    tauri::async_runtime::spawn_blocking(move || {
+        polars_bridge::unpivot(&request, &cache_dir).map_err(CommandError::from)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+async fn list_database_tables(connection_string: String) -> Result<Vec<String>, CommandError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        polars_bridge::list_database_tables(&connection_string)
+            .map_err(CommandError::from)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+// A pivot's rows and the rest of its result, from the result cache or computed
+// on the open dataset
+fn cached_pivot(
+    app: &AppHandle,
+    request: &polars_bridge::PivotRequest,
+    options: &polars_bridge::ExecutionOptions,
+) -> Result<(polars::prelude::DataFrame, polars_bridge::PivotResult), polars_bridge::DataError> {
+    app.state::<result_cache::ResultCache>().pivot(request, options, || {
+        app.state::<datasets::DatasetManager>()
+            .frame_for(request)
+            .and_then(|lf| polars_bridge::pivot_parts(lf, request, options))
+    })
+}
+
+// Starts the pivot in the background and returns its job id right away. The
+// result comes with a "pivot://finished" event carrying the same id.
+#[tauri::command]
+fn run_pivot(
+    app: AppHandle,
+    mut request: polars_bridge::PivotRequest,
+    pivot_name: Option<String>,
+    settings: State<'_, settings::SettingsState>,
+    views: State<'_, views::ViewsState>,
+    jobs: State<'_, jobs::JobRegistry>,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> Result<u64, CommandError> {
+    datasets.resolve(&mut request).map_err(CommandError::from)?;
+    // A saved pivot reopens with the groups the user left collapsed
+    if let (None, Some(name)) = (&request.collapse, &pivot_name) {
+        request.collapse = views.collapse_state(name);
+    }
+
+    let logged_request = serde_json::to_value(&request).map_err(CommandError::from)?;
+    let options = settings.get().execution_options();
+    let (job_id, cancel) = jobs.start();
+    request.set_cancel_flag(cancel);
+    let progress_app = app.clone();
+    request.set_progress(move |progress| {
+        let _ = progress_app.emit("pivot://progress", jobs::PivotJobProgress { job_id, progress });
+    });
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let result = cached_pivot(&app, &request, &options)
+            .and_then(|(df, result)| polars_bridge::paged_response(df, result, &request));
+        let registry = app.state::<jobs::JobRegistry>();
+        let result = result.map(|(result, pages)| {
+            if let Some(pages) = pages {
+                registry.keep_pages(job_id, pages);
+            }
+            result
+        });
+        let mut outcome = jobs::PivotJobOutcome::new(job_id, result);
+
+        if let Some(result) = &outcome.result {
+            let recorded = app.state::<audit::AuditLog>().record(audit::AuditEvent::PivotRun {
+                request: logged_request,
+                rows: result.total_rows,
+            });
+            if let Err(e) = recorded {
+                outcome.result = None;
+                outcome.error = Some(e.into());
+            }
+        }
+
+        match (&outcome.result, &outcome.error) {
+            (Some(result), _) => tracing::info!(job_id, rows = result.total_rows, stats = ?result.stats, "Pivot finished"),
+            (_, Some(error)) => tracing::warn!(job_id, error = %error.message, "Pivot failed"),
+            _ => tracing::info!(job_id, "Pivot cancelled"),
+        }
+        registry.finish(job_id);
+        let _ = app.emit("pivot://finished", outcome);
+    });
+    Ok(job_id)
+}
+
+// The pivot's rows as Arrow IPC rather than JSON, for wide results whose JSON
+// takes longer to build than the pivot itself; see polars_bridge::arrow_response
+#[tauri::command]
+async fn run_pivot_arrow(
+    app: AppHandle,
+    mut request: polars_bridge::PivotRequest,
+    settings: State<'_, settings::SettingsState>,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> Result<tauri::ipc::Response, CommandError> {
+    datasets.resolve(&mut request).map_err(CommandError::from)?;
+    let logged_request = serde_json::to_value(&request).map_err(CommandError::from)?;
+    let options = settings.get().execution_options();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let (df, result) = cached_pivot(&app, &request, &options).map_err(CommandError::from)?;
+        let rows = df.height();
+        let bytes = polars_bridge::arrow_response(&result, df).map_err(CommandError::from)?;
+
+        app.state::<audit::AuditLog>()
+            .record(audit::AuditEvent::PivotRun { request: logged_request, rows })
+            .map_err(CommandError::from)?;
+        Ok(tauri::ipc::Response::new(bytes))
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+// Several pivots in one call, e.g. the panels of a dashboard, returned in the
+// order they were asked for. Pivots on the same dataset read it once between
+// them. Results come with all their rows: a page_size is ignored.
+#[tauri::command]
+async fn run_pivots(
+    app: AppHandle,
+    mut requests: Vec<polars_bridge::PivotRequest>,
+    settings: State<'_, settings::SettingsState>,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> Result<Vec<polars_bridge::PivotResult>, CommandError> {
+    for request in &mut requests {
+        datasets.resolve(request).map_err(CommandError::from)?;
+        request.page_size = None;
+    }
+    let logged_requests = requests.iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(CommandError::from)?;
+    let options = settings.get().execution_options();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let datasets = app.state::<datasets::DatasetManager>();
+        let cache = app.state::<result_cache::ResultCache>();
+        let mut results: Vec<Option<polars_bridge::PivotResult>> = vec![None; requests.len()];
+
+        for group in datasets::group_by_dataset(&requests) {
+            let batch: Vec<&polars_bridge::PivotRequest> = group.iter().map(|&index| &requests[index]).collect();
+            // Only read once a pivot of the batch isn't cached
+            let mut shared: Option<polars::prelude::LazyFrame> = None;
+            for (&index, request) in group.iter().zip(&batch) {
+                let (result, _) = cache.pivot(request, &options, || {
+                    let lf = match &shared {
+                        Some(lf) => lf.clone(),
+                        None => shared.insert(datasets.frame_for_batch(&batch)?).clone(),
+                    };
+                    polars_bridge::pivot_parts(lf, request, &options)
+                })
+                .and_then(|(df, result)| polars_bridge::paged_response(df, result, request))
+                .map_err(CommandError::from)?;
+                results[index] = Some(result);
+            }
+        }
+
+        let audit = app.state::<audit::AuditLog>();
+        for (request, result) in logged_requests.into_iter().zip(&results) {
+            let rows = result.as_ref().map_or(0, |result| result.total_rows);
+            audit.record(audit::AuditEvent::PivotRun { request, rows })
+                .map_err(CommandError::from)?;
+        }
+        Ok(results.into_iter().flatten().collect())
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+// Rows of a finished job's result that run with a page_size
+#[tauri::command]
+fn get_pivot_page(
+    job_id: u64,
+    offset: usize,
+    limit: usize,
+    jobs: State<'_, jobs::JobRegistry>,
+) -> Result<polars_bridge::PivotPage, CommandError> {
+    jobs.page(job_id, offset, limit)
+        .ok_or_else(|| CommandError::new(
+            ErrorCode::ResultUnavailable,
+            format!("The result of pivot {} is no longer available; run it again", job_id),
+        ))?
+        .map_err(CommandError::from)
+}
+
+// False when the job had already finished
+#[tauri::command]
+fn cancel_pivot(job_id: u64, jobs: State<'_, jobs::JobRegistry>) -> bool {
+    jobs.cancel(job_id)
+}
+
+// Exports the pivots given, or every saved pivot when none are
+#[tauri::command]
+async fn export_pivots(
+    app: AppHandle,
+    pivots: Option<Vec<polars_bridge::NamedPivot>>,
+    output_dir: String,
+    settings: State<'_, settings::SettingsState>,
+    views: State<'_, views::ViewsState>,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> Result<Vec<String>, CommandError> {
+    let options = settings.get().execution_options();
+    let mut pivots = pivots.unwrap_or_else(|| views.saved_pivots());
+    for named in &mut pivots {
+        datasets.resolve(&mut named.request).map_err(CommandError::from)?;
+        if named.request.collapse.is_none() {
+            named.request.collapse = views.collapse_state(&named.name);
+        }
+    }
+
+    // Run off the main thread so the progress events reach the window as they happen
+    tauri::async_runtime::spawn_blocking(move || {
+        let audit = app.state::<audit::AuditLog>();
+        let mut audit_result = Ok(());
+
+        let written = polars_bridge::export_pivots(&pivots, &output_dir, &options, |progress| {
+            let request = serde_json::to_value(&pivots[progress.index].request).unwrap_or_default();
+            if audit_result.is_ok() {
+                audit_result = audit.record(audit::AuditEvent::ExportWritten {
+                    pivot: progress.name.clone(),
+                    request,
+                    destination: progress.path.clone(),
+                });
+            }
+            let _ = app.emit("export://progress", progress);
+        })
+        .map_err(CommandError::from)?;
+
+        audit_result.map_err(CommandError::from)?;
+        Ok(written)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+fn get_collapse_state(
+    pivot_name: String,
+    views: State<'_, views::ViewsState>,
+) -> polars_bridge::CollapseState {
+    views.collapse_state(&pivot_name).unwrap_or_default()
+}
+
+#[tauri::command]
+fn set_collapse_state(
+    pivot_name: String,
+    state: polars_bridge::CollapseState,
+    views: State<'_, views::ViewsState>,
+) -> Result<(), CommandError> {
+    views.set_collapse_state(&pivot_name, state)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+fn list_saved_pivots(views: State<'_, views::ViewsState>) -> Vec<polars_bridge::NamedPivot> {
+    views.saved_pivots()
+}
+
+// Saves the pivot under its name, replacing one saved before. Its dataset is
+// kept as the path and read options, as dataset ids don't outlive the session.
+#[tauri::command]
+fn save_pivot(
+    mut pivot: polars_bridge::NamedPivot,
+    views: State<'_, views::ViewsState>,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> Result<(), CommandError> {
+    datasets.resolve(&mut pivot.request).map_err(CommandError::from)?;
+    pivot.request.dataset_id = None;
+    // Collapse state is kept apart, per name
+    pivot.request.collapse = None;
+    views.save_pivot(pivot).map_err(CommandError::from)
+}
+
+#[tauri::command]
+fn delete_saved_pivot(pivot_name: String, views: State<'_, views::ViewsState>) -> Result<(), CommandError> {
+    views.delete_pivot(&pivot_name).map_err(CommandError::from)
+}
+
+#[tauri::command]
+async fn preview_filter_count(
+    app: AppHandle,
+    mut request: polars_bridge::PivotRequest,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> Result<polars_bridge::FilterCount, CommandError> {
+    datasets.resolve(&mut request).map_err(CommandError::from)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        let options = request.read_options.clone().unwrap_or_default();
+        app.state::<datasets::DatasetManager>()
+            .frame(&request.data_path, &options)
+            .and_then(|lf| polars_bridge::filtered_row_count(lf, &request))
+            .map_err(CommandError::from)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+async fn explain_pivot(
+    app: AppHandle,
+    mut request: polars_bridge::PivotRequest,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> Result<polars_bridge::PivotPlan, CommandError> {
+    datasets.resolve(&mut request).map_err(CommandError::from)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        // Explaining a pivot only plans it, so nothing is loaded for it
+        let options = request.read_options.clone().unwrap_or_default();
+        app.state::<datasets::DatasetManager>()
+            .frame(&request.data_path, &options)
+            .and_then(|lf| polars_bridge::explain_pivot(lf, &request))
+            .map_err(CommandError::from)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+async fn expand_group(
+    app: AppHandle,
+    mut request: polars_bridge::PivotRequest,
+    path: Vec<String>,
+    settings: State<'_, settings::SettingsState>,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> Result<polars_bridge::PivotResult, CommandError> {
+    datasets.resolve(&mut request).map_err(CommandError::from)?;
+    let options = settings.get().execution_options();
+    tauri::async_runtime::spawn_blocking(move || {
+        app.state::<datasets::DatasetManager>()
+            .frame_for(&request)
+            .and_then(|lf| polars_bridge::expand_group(lf, request, path, &options))
+            .map_err(CommandError::from)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+async fn preview_pivot(
+    app: AppHandle,
+    mut request: polars_bridge::PivotRequest,
+    options: Option<polars_bridge::PreviewOptions>,
+    datasets: State<'_, datasets::DatasetManager>,
+) -> Result<polars_bridge::PivotResult, CommandError> {
+    datasets.resolve(&mut request).map_err(CommandError::from)?;
+    tauri::async_runtime::spawn_blocking(move || {
+        // A preview only reads the first rows, so nothing is loaded for it
+        let read_options = request.read_options.clone().unwrap_or_default();
+        app.state::<datasets::DatasetManager>()
+            .frame(&request.data_path, &read_options)
+            .and_then(|lf| polars_bridge::preview_pivot(lf, request, &options.unwrap_or_default()))
+            .map_err(CommandError::from)
+    })
+    .await
+    .map_err(CommandError::from)?
+}
+
+#[tauri::command]
+fn get_s3_config(cloud: State<'_, cloud::CloudState>) -> cloud::S3Config {
+    cloud.get().s3.redacted()
+}
+
+#[tauri::command]
+fn set_s3_config(
+    config: cloud::S3Config,
+    cloud: State<'_, cloud::CloudState>,
+) -> Result<(), CommandError> {
+    cloud.set_s3(config)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+fn get_azure_config(cloud: State<'_, cloud::CloudState>) -> cloud::AzureConfig {
+    cloud.get().azure.redacted()
+}
+
+#[tauri::command]
+fn set_azure_config(
+    config: cloud::AzureConfig,
+    cloud: State<'_, cloud::CloudState>,
+) -> Result<(), CommandError> {
+    cloud.set_azure(config)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+fn get_gcs_config(cloud: State<'_, cloud::CloudState>) -> cloud::GcsConfig {
+    cloud.get().gcs
+}
+
+#[tauri::command]
+fn set_gcs_config(
+    config: cloud::GcsConfig,
+    cloud: State<'_, cloud::CloudState>,
+) -> Result<(), CommandError> {
+    cloud.set_gcs(config)
+        .map_err(CommandError::from)
+}
+
+#[tauri::command]
+fn query_audit_log(
+    query: Option<audit::AuditQuery>,
+    audit: State<'_, audit::AuditLog>,
+) -> Result<Vec<audit::AuditEntry>, CommandError> {
+    audit.query(&query.unwrap_or_default())
+        .map_err(CommandError::from)
+}
+
+// Latest lines of the log, for the diagnostics panel; empty when logging
+// couldn't start
+#[tauri::command]
+fn get_recent_logs(app: AppHandle, lines: Option<usize>) -> Result<Vec<String>, CommandError> {
+    match app.try_state::<logging::LogState>() {
+        Some(logs) => logs.recent(lines.unwrap_or(200)).map_err(CommandError::from),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[tauri::command]
+fn get_settings(settings: State<'_, settings::SettingsState>) -> settings::EngineSettings {
+    settings.get()
+}
+
+#[tauri::command]
+fn update_settings(
+    new_settings: settings::EngineSettings,
+    settings: State<'_, settings::SettingsState>,
+) -> Result<(), CommandError> {
+    settings.update(new_settings)
+        .map_err(CommandError::from)
 }
 
 fn main() {
+    let context = tauri::generate_context!();
+    // The thread limit is set while main is the only thread; Tauri's setup runs
+    // after its runtime has started others. This is the directory Tauri gives
+    // as app_config_dir.
+    if let Some(config_dir) = dirs::config_dir() {
+        let settings_path = config_dir.join(&context.config().identifier).join(settings::SETTINGS_FILE);
+        if let Ok(settings) = settings::EngineSettings::load(&settings_path) {
+            settings.apply_thread_limit();
+        }
+    }
+
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_fs::init()) 
+        .setup(|app| {
+            // Logging starts first so the other states can report load failures
+            let settings_path = app.path().app_config_dir()?.join(settings::SETTINGS_FILE);
+            let log_level = settings::EngineSettings::load(&settings_path).ok().and_then(|s| s.log_level);
+            let log_dir = app.path().app_data_dir()?.join("logs");
+            match logging::LogState::init(log_dir, log_level.as_deref()) {
+                Ok(logs) => {
+                    app.manage(logs);
+                },
+                Err(e) => eprintln!("Failed to start logging: {}", e),
+            }
+
+            let catalog_path = app.path().app_data_dir()?.join("catalog.json");
+            app.manage(catalog::CatalogState::load(catalog_path));
+
+            let views_path = app.path().app_data_dir()?.join("views.json");
+            app.manage(views::ViewsState::load(views_path));
+
+            let audit_path = app.path().app_data_dir()?.join("audit.log");
+            app.manage(audit::AuditLog::new(audit_path));
+
+            app.manage(settings::SettingsState::load(settings_path));
+
+            let cloud_path = app.path().app_config_dir()?.join("cloud.json");
+            app.manage(cloud::CloudState::load(cloud_path));
+
+            app.manage(datasets::DatasetManager::default());
+            let results_dir = app.path().app_cache_dir()?.join("results");
+            app.manage(result_cache::ResultCache::new(results_dir));
+            app.manage(jobs::JobRegistry::default());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             get_csv_columns,
-            run_pivot
+            get_schema,
+            open_dataset,
+            close_dataset,
+            get_file_stats,
+            get_value_counts,
+            get_column_metadata,
+            set_column_metadata,
+            list_sqlite_tables,
+            list_excel_sheets,
+            convert_to_parquet,
+            download_data,
+            connect_database,
+            list_database_tables,
+            run_pivot,
+            cancel_pivot,
+            run_pivot_arrow,
+            run_pivots,
+            get_pivot_page,
+            run_unpivot,
+            preview_pivot,
+            expand_group,
+            preview_filter_count,
+            explain_pivot,
+            get_collapse_state,
+            set_collapse_state,
+            get_settings,
+            update_settings,
+            query_audit_log,
+            get_recent_logs,
+            get_s3_config,
+            set_s3_config,
+            get_azure_config,
+            set_azure_config,
+            get_gcs_config,
+            set_gcs_config,
+            export_pivots,
+            list_saved_pivots,
+            save_pivot,
+            delete_saved_pivot
         ])
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
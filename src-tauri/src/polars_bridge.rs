@@ -1,26 +1,80 @@
 use polars::prelude::*;
 use polars::lazy::dsl::Expr;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
 use polars_ops::pivot::{pivot, PivotAgg};
+use calamine::Reader;
+
+use crate::catalog::ColumnMetadata;
+use crate::cloud;
 
 #[derive(Error, Debug)]
 pub enum DataError {
     #[error("Failed to read file: {0}")]
     ReadError(String),
+    #[error("File not found: {path}")]
+    FileNotFound {
+        path: String,
+    },
+    #[error("Column '{column}' is not in the data")]
+    ColumnNotFound {
+        column: String,
+    },
+    #[error("Dataset {dataset_id} is not open; select the file again")]
+    DatasetNotOpen {
+        dataset_id: u64,
+    },
     #[error("Failed to process data: {0}")]
     ProcessingError(String),
     #[error("Unsupported file format: {0}")]
     UnsupportedFormat(String),
+    #[error(
+        "Dataset too large for the current memory budget: needs about {estimated_mb} MB, budget is {budget_mb} MB. {}",
+        .suggestions.join(" ")
+    )]
+    MemoryBudgetExceeded {
+        estimated_mb: u64,
+        budget_mb: u64,
+        suggestions: Vec<String>,
+    },
+    #[error("The pivot would have about {columns} columns, more than the limit of {limit}")]
+    TooManyColumns {
+        columns: usize,
+        limit: usize,
+    },
+    #[error(
+        "The result would take about {estimated_mb} MB, more than the limit of {limit_mb} MB. \
+         Narrow the pivot with filters or fewer row and column fields."
+    )]
+    ResultTooLarge {
+        estimated_mb: u64,
+        limit_mb: u64,
+    },
+    #[error("The pivot was cancelled")]
+    Cancelled,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum AggregationType {
     Sum,
     Mean,
+    // nth root of the product of n values, for growth rates and indices. Nulls
+    // are skipped; a zero or negative value leaves the cell empty, as it has no
+    // logarithm.
+    GeometricMean,
+    // Non-null values of the field in the group
     Count,
+    // Rows in the group, nulls in the field included (SQL's COUNT(*))
+    CountRows,
     Min,
     Max,
     First,
@@ -28,48 +82,1163 @@ pub enum AggregationType {
     Median,
     Std,
     Var,
+    // Number of distinct non-null values in the group
+    CountDistinct,
+    // HyperLogLog estimate of CountDistinct, much faster on very large groups.
+    // Results are named "approx_count_distinct_..." to flag the approximation.
+    ApproxCountDistinct,
+    // Quantile between 0 and 1, e.g. 0.95, estimated from a sample of at most
+    // APPROX_QUANTILE_SAMPLE rows per group; smaller groups get the exact value.
+    // Results are named e.g. "approx_quantile_0.95_latency".
+    ApproxQuantile { quantile: f64 },
+    // Number of rows in the group matching the condition (COUNTIF)
+    CountWhere { condition: FilterCondition },
+    // Sum / mean of the field over the rows matching the condition (SUMIF / AVERAGEIF)
+    SumWhere { condition: FilterCondition },
+    MeanWhere { condition: FilterCondition },
+    // Polars-SQL expression evaluated per group, e.g. "sum(revenue) / sum(qty)"
+    Custom(String),
+    // Arithmetic over the other measures of the request once they are aggregated,
+    // referring to them by output name, e.g. "sum_revenue / sum_cost". The field
+    // only names the result.
+    Calculated(String),
 }
 
-impl From<&AggregationType> for Expr {
-    fn from(agg_type: &AggregationType) -> Self {
-        match agg_type {
-            AggregationType::Sum => col("").sum(),
-            AggregationType::Mean => col("").mean(),
-            AggregationType::Count => col("").count(),
-            AggregationType::Min => col("").min(),
-            AggregationType::Max => col("").max(),
-            AggregationType::First => col("").first(),
-            AggregationType::Last => col("").last(),
-            AggregationType::Median => col("").median(),
-            AggregationType::Std => col("").std(1),
-            AggregationType::Var => col("").var(1),
+impl AggregationType {
+    // Short name used as the prefix of result columns, e.g. "sum" in "sum_revenue"
+    fn prefix(&self) -> &'static str {
+        match self {
+            AggregationType::Sum => "sum",
+            AggregationType::Mean => "mean",
+            AggregationType::GeometricMean => "geometric_mean",
+            AggregationType::Count => "count",
+            AggregationType::CountRows => "count_rows",
+            AggregationType::Min => "min",
+            AggregationType::Max => "max",
+            AggregationType::First => "first",
+            AggregationType::Last => "last",
+            AggregationType::Median => "median",
+            AggregationType::Std => "std",
+            AggregationType::Var => "var",
+            AggregationType::CountDistinct => "count_distinct",
+            AggregationType::ApproxCountDistinct => "approx_count_distinct",
+            AggregationType::ApproxQuantile { .. } => "approx_quantile",
+            AggregationType::CountWhere { .. } => "count_where",
+            AggregationType::SumWhere { .. } => "sum_where",
+            AggregationType::MeanWhere { .. } => "mean_where",
+            AggregationType::Custom(_) => "custom",
+            AggregationType::Calculated(_) => "calculated",
+        }
+    }
+
+    // Whether the streaming engine can aggregate it batch by batch; the others
+    // make Polars load the whole dataset
+    fn streams(&self) -> bool {
+        matches!(
+            self,
+            AggregationType::Sum
+                | AggregationType::Mean
+                | AggregationType::Count
+                | AggregationType::CountRows
+                | AggregationType::Min
+                | AggregationType::Max
+                | AggregationType::First
+                | AggregationType::Last
+                | AggregationType::CountWhere { .. }
+                | AggregationType::SumWhere { .. }
+                | AggregationType::MeanWhere { .. }
+                | AggregationType::Calculated(_)
+        )
+    }
+
+    // Row condition of the conditional aggregations
+    fn condition(&self) -> Option<&FilterCondition> {
+        match self {
+            AggregationType::CountWhere { condition }
+            | AggregationType::SumWhere { condition }
+            | AggregationType::MeanWhere { condition } => Some(condition),
+            _ => None,
         }
     }
 }
 
+// Rows per group an approximate quantile is computed from, and the seed that
+// keeps the sample the same between runs
+const APPROX_QUANTILE_SAMPLE: u32 = 100_000;
+const APPROX_QUANTILE_SEED: u64 = 42;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum DisplayMode {
+    // Running share of the parent row level's total, accumulated from the
+    // largest group down (Pareto-style cumulative %)
+    CumulativeShare,
+    // Change from the cell in the previous column, e.g. month over month. The
+    // first column has nothing to compare with and is left empty.
+    DifferenceFromPrevious,
+    // The same change as a fraction of the previous cell
+    PercentDifferenceFromPrevious,
+    // Position of the cell among the row's columns, 1 being the largest
+    RankInRow {
+        #[serde(default)]
+        ties: RankTies,
+    },
+}
+
+impl DisplayMode {
+    // Modes computed across the pivoted columns of a row, which need column fields
+    fn compares_columns(&self) -> bool {
+        !matches!(self, DisplayMode::CumulativeShare)
+    }
+}
+
+// Rank given to equal values, e.g. for 10, 8, 8, 5
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub enum RankTies {
+    // 1, 2, 2, 4
+    #[default]
+    Min,
+    // 1, 3, 3, 4
+    Max,
+    // 1, 2.5, 2.5, 4
+    Average,
+    // 1, 2, 2, 3
+    Dense,
+    // 1, 2, 3, 4, in column order
+    Ordinal,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ValueWithAggregation {
     pub field: String,
     pub aggregation: AggregationType,
+    // How the aggregated value is shown; the raw aggregate when unset
+    pub display_mode: Option<DisplayMode>,
+    // Only rows matching this condition feed the measure (e.g. Sum of amount
+    // where status = "paid"), leaving the other measures unfiltered
+    pub filter: Option<FilterCondition>,
+    // How null values of the field are treated; skipped when not set
+    pub nulls: Option<NullPolicy>,
+    // How the measure's cells are shown, echoed back in the result
+    pub format: Option<NumberFormat>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// Display format of a measure, e.g. 2 decimals in EUR, or a percentage
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NumberFormat {
+    pub decimals: Option<u32>,
+    // Show 0.25 as 25%
+    #[serde(default)]
+    pub percent: bool,
+    // ISO 4217 code, e.g. "EUR"
+    pub currency: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub enum NullPolicy {
+    // Leave them out: Sum and Mean cover the non-null values, Count counts them
+    #[default]
+    Skip,
+    // Count them as 0, e.g. a missing sale is no sale: Mean drops and Count
+    // covers every row
+    Zero,
+    // Any null in the group makes the cell null, flagging incomplete data
+    Propagate,
+}
+
+impl ValueWithAggregation {
+    // Adds the columns the measure reads to `names`; false when it can read any
+    fn add_referenced_columns(&self, names: &mut HashSet<String>) -> bool {
+        if matches!(self.aggregation, AggregationType::Custom(_)) {
+            return false;
+        }
+        names.insert(self.field.clone());
+        self.filter.iter()
+            .chain(self.aggregation.condition())
+            .all(|condition| condition.add_referenced_columns(names))
+    }
+
+    // Whether the measure keeps rows by a date relative to today
+    fn depends_on_today(&self) -> bool {
+        self.filter.iter()
+            .chain(self.aggregation.condition())
+            .any(FilterCondition::depends_on_today)
+    }
+
+    // Name of the aggregated column, e.g. "sum_revenue",
+    // "sum_where_revenue[channel = \"online\"]" for conditional or filtered
+    // measures, "custom_margin[sum(revenue) / sum(qty)]" for custom expressions
+    // and "calculated_margin" for calculated measures
+    pub fn output_name(&self) -> String {
+        match &self.aggregation {
+            AggregationType::Custom(expression) => {
+                return format!("{}_{}[{}]", self.aggregation.prefix(), self.field, expression);
+            },
+            AggregationType::Calculated(_) => {
+                return format!("{}_{}", self.aggregation.prefix(), self.field);
+            },
+            _ => {},
+        }
+        let conditions: Vec<String> = self
+            .aggregation
+            .condition()
+            .into_iter()
+            .chain(self.filter.as_ref())
+            .map(FilterCondition::describe)
+            .collect();
+        let name = match &self.aggregation {
+            AggregationType::ApproxQuantile { quantile } => {
+                format!("{}_{}_{}", self.aggregation.prefix(), quantile, self.field)
+            },
+            _ => format!("{}_{}", self.aggregation.prefix(), self.field),
+        };
+        if conditions.is_empty() {
+            name
+        } else {
+            format!("{}[{}]", name, conditions.join(" and "))
+        }
+    }
+
+    // Row mask combining the aggregation's own condition and the measure filter
+    fn row_mask(&self) -> Result<Option<Expr>, DataError> {
+        let mut mask = None;
+        for condition in self.aggregation.condition().into_iter().chain(self.filter.as_ref()) {
+            let expr = build_filter_expr(condition)?;
+            mask = Some(match mask {
+                Some(current) => expr.and(current),
+                None => expr,
+            });
+        }
+        Ok(mask)
+    }
+
+    fn to_expr(&self) -> Result<Expr, DataError> {
+        let mask = self.row_mask()?;
+        let nulls = self.nulls.unwrap_or_default();
+        let field_col = match &mask {
+            Some(mask) => col(&self.field).filter(mask.clone()),
+            None => col(&self.field),
+        };
+        let field_col = match nulls {
+            NullPolicy::Zero => field_col.fill_null(lit(0)),
+            NullPolicy::Skip | NullPolicy::Propagate => field_col,
+        };
+        let expr = match &self.aggregation {
+            AggregationType::Sum | AggregationType::SumWhere { .. } => field_col.sum(),
+            AggregationType::Mean | AggregationType::MeanWhere { .. } => field_col.mean(),
+            AggregationType::GeometricMean => {
+                when(field_col.clone().lt_eq(lit(0)).any(true))
+                    .then(lit(NULL))
+                    .otherwise(field_col.cast(DataType::Float64).log(std::f64::consts::E).mean().exp())
+            },
+            AggregationType::Count => field_col.count(),
+            AggregationType::CountRows => field_col.len(),
+            AggregationType::Min => field_col.min(),
+            AggregationType::Max => field_col.max(),
+            AggregationType::First => field_col.first(),
+            AggregationType::Last => field_col.last(),
+            AggregationType::Median => field_col.median(),
+            AggregationType::Std => field_col.std(1),
+            AggregationType::Var => field_col.var(1),
+            AggregationType::CountDistinct => field_col.drop_nulls().n_unique(),
+            AggregationType::ApproxCountDistinct => field_col.drop_nulls().approx_n_unique(),
+            AggregationType::ApproxQuantile { quantile } => {
+                if !(0.0..=1.0).contains(quantile) {
+                    return Err(DataError::ProcessingError(format!(
+                        "Quantile {} is not between 0 and 1",
+                        quantile
+                    )));
+                }
+                let values = field_col.drop_nulls();
+                let sample_size = when(values.clone().len().gt(lit(APPROX_QUANTILE_SAMPLE)))
+                    .then(lit(APPROX_QUANTILE_SAMPLE))
+                    .otherwise(values.clone().len());
+                values
+                    .sample_n(sample_size, false, false, Some(APPROX_QUANTILE_SEED))
+                    .quantile(lit(*quantile), QuantileInterpolOptions::Linear)
+            },
+            AggregationType::CountWhere { .. } => {
+                mask.unwrap_or_else(|| lit(true)).cast(DataType::Int64).sum()
+            },
+            AggregationType::Custom(expression) | AggregationType::Calculated(expression) => {
+                if self.filter.is_some() {
+                    return Err(DataError::ProcessingError(format!(
+                        "Measure '{}' can't take a measure filter; put the condition in the expression instead",
+                        expression
+                    )));
+                }
+                if self.nulls.is_some() {
+                    return Err(DataError::ProcessingError(format!(
+                        "Measure '{}' can't take a null policy; use fill_null or coalesce in the expression instead",
+                        expression
+                    )));
+                }
+                polars::sql::sql_expr(expression).map_err(|e| DataError::ProcessingError(format!(
+                    "Invalid expression '{}': {}", expression, e
+                )))?
+            },
+        };
+        let expr = match nulls {
+            NullPolicy::Propagate => when(field_col.null_count().gt(lit(0)))
+                .then(lit(NULL))
+                .otherwise(expr),
+            NullPolicy::Skip | NullPolicy::Zero => expr,
+        };
+
+        Ok(expr.alias(&self.output_name()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PivotRequest {
+    // Filled in from `dataset_id` when that is set
+    #[serde(default)]
     pub data_path: String,
+    // Handle of a dataset from `open_dataset`, standing for its path and read options
+    pub dataset_id: Option<u64>,
     pub rows: Vec<String>,
     pub columns: Vec<String>,
     pub values: Vec<ValueWithAggregation>,
     pub filters: Option<Vec<FilterCondition>>,
+    pub read_options: Option<ReadOptions>,
+    pub collapse: Option<CollapseState>,
+    // Add a grand total row below the groups
+    #[serde(default)]
+    pub show_row_totals: bool,
+    // Add a total column per measure, aggregated across the column fields
+    #[serde(default)]
+    pub show_column_totals: bool,
+    // With several row fields, add a subtotal row after each group of every outer one
+    #[serde(default)]
+    pub show_subtotals: bool,
+    // Date buckets added as extra fields before filtering and grouping
+    pub date_groupings: Option<Vec<DateGrouping>>,
+    // Numeric ranges added as extra fields the same way
+    pub numeric_bins: Option<Vec<NumericBinning>>,
+    // Keep the largest groups of the outermost row field and fold the rest into one
+    pub top_rows: Option<TopRows>,
+    // How the pivoted columns are ordered; natural order when not set
+    pub column_order: Option<ColumnOrder>,
+    // Give every row group a cell under every combination of the column labels,
+    // those with no data included
+    #[serde(default)]
+    pub densify: bool,
+    // Shown in the empty cells of a densified pivot instead of nothing, e.g. 0
+    pub fill_value: Option<f64>,
+    // Explicit label order per field, e.g. {"priority": ["Low", "Medium", "High"]},
+    // on rows and columns alike. Labels not listed follow in their natural order.
+    // Kept sorted, so equal requests serialize, and are cached, alike.
+    pub sort_orders: Option<BTreeMap<String, Vec<String>>>,
+    // What happens to rows whose row or column field is null; they form a group
+    // with a null label when not set
+    pub null_groups: Option<NullGroups>,
+    // Joins the labels of several column fields in a pivoted column's key; "_" when not set
+    pub column_separator: Option<String>,
+    // Key of each pivoted column, e.g. "{columns}|{measure}" for "2024|North|sum_revenue".
+    // `{columns}` is the joined column labels, `{measure}` the measure's output name
+    // and `{aggregation}` its aggregation prefix. When not set a single measure is
+    // keyed "{aggregation}_{columns}" and several measures "{measure}_{columns}".
+    pub column_key_template: Option<String>,
+    // Keep row groups in the order they first appear in the data rather than
+    // sorting them by label; fields with a fixed label order still follow it.
+    // The pivoted columns follow the column order either way.
+    #[serde(default)]
+    pub maintain_order: bool,
+    // Build the pivot even when it comes out wider than MAX_PIVOT_COLUMNS, once
+    // the user has confirmed it
+    #[serde(default)]
+    pub confirm_wide: bool,
+    // Row field combinations to aggregate on in one request, e.g. [["region"],
+    // ["region", "country"], []] for the regions, their countries and the overall
+    // total. Each set's groups follow the previous set's, in place of subtotals
+    // and the grand total.
+    pub grouping_sets: Option<Vec<Vec<String>>>,
+    // Swap the row and column axes: the column fields label the rows and the row
+    // fields the columns, along with their collapsed groups and totals
+    #[serde(default)]
+    pub transpose: bool,
+    // Drop repeated rows before anything else, keeping the first row of each key,
+    // e.g. ["order_id"] for exports that list an order twice. An empty list
+    // compares whole rows.
+    pub distinct_on: Option<Vec<String>>,
+    // Conditions on the aggregated measures, by output name, e.g. sum_revenue
+    // GreaterThan 10000 (SQL's HAVING). Groups of the row fields (the column
+    // fields without row fields) failing them are dropped along with their rows,
+    // so totals and column headers only cover the groups kept.
+    pub measure_filters: Option<Vec<FilterCondition>>,
+    // Conditions on the pivoted columns by column key, e.g. sum_revenue_2024
+    // GreaterThan 1000 or IsNotNull. Result rows failing any of them are hidden;
+    // totals are left as they are.
+    pub result_filters: Option<Vec<FilterCondition>>,
+    // Table slices to hide, or to keep alone, picked from the pivot's cells
+    pub slice_filter: Option<SliceFilter>,
+    // Rows sent with the result of a background pivot; the others are fetched a
+    // page at a time with `PivotPages::page`. All rows are sent when not set.
+    pub page_size: Option<usize>,
+    // Pivot a sample of the rows for a quick draft of a huge file; the result says
+    // which sample it was built from
+    pub sample: Option<SampleSpec>,
+    // Label orders worked out from the data while computing the pivot, e.g. the
+    // top rows ranked by their measure
+    #[serde(skip)]
+    resolved_orders: HashMap<String, Vec<String>>,
+    // Original type of fields turned into text to give null keys a label
+    #[serde(skip)]
+    resolved_dtypes: HashMap<String, DataType>,
+    // Labels of the outer row group whose next level alone is computed, set by
+    // `expand_group`
+    #[serde(skip)]
+    expand_path: Option<Vec<String>>,
+    // Set when the data is too large for memory and runs on the streaming engine
+    #[serde(skip)]
+    streaming: bool,
+    // Size of the result above which the pivot fails instead of being built
+    #[serde(skip)]
+    result_budget: Option<u64>,
+    // Raised from another thread to stop the computation at its next stage
+    #[serde(skip)]
+    cancel: Option<Arc<AtomicBool>>,
+    // Told as the computation moves from one stage to the next
+    #[serde(skip)]
+    progress: Option<ProgressReporter>,
+}
+
+// Where a pivot spent its time, and how much data went in and came out
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PivotStats {
+    // Setting up the scan, and deriving fields
+    pub read_ms: u64,
+    // Setting up the filters, and running the ones that need their own query
+    // (top rows, measure filters); the rest run as part of the aggregation
+    pub filter_ms: u64,
+    pub aggregate_ms: u64,
+    pub reshape_ms: u64,
+    pub serialize_ms: u64,
+    // From the footers of Parquet files; None for other data, which would have
+    // to be read an extra time to count
+    pub input_rows: Option<usize>,
+    pub output_rows: usize,
+    pub output_columns: usize,
+}
+
+impl PivotStats {
+    // Adds the time another level of the pivot spent aggregating and reshaping
+    fn add_stages(&mut self, level: &PivotStats) {
+        self.aggregate_ms += level.aggregate_ms;
+        self.reshape_ms += level.reshape_ms;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum PivotStage {
+    Reading,
+    Filtering,
+    Aggregating,
+    Reshaping,
+    Serializing,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PivotProgress {
+    pub stage: PivotStage,
+    // Rough share of the work done, from 0 to 100
+    pub percent: u8,
+}
+
+#[derive(Clone)]
+pub struct ProgressReporter(Arc<dyn Fn(PivotProgress) + Send + Sync>);
+
+impl std::fmt::Debug for ProgressReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressReporter")
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SampleSpec {
+    // This share of the rows, between 0 and 1, spread evenly over the file, so
+    // the same rows are picked on every run
+    Fraction(f64),
+    // The rows at the start of the file
+    FirstRows(usize),
+}
+
+const SAMPLE_ROW_INDEX: &str = "__sample_row";
+
+// The sampled rows of `lf`
+fn sample_rows(lf: LazyFrame, sample: &SampleSpec) -> Result<LazyFrame, DataError> {
+    match *sample {
+        SampleSpec::FirstRows(rows) => Ok(lf.limit(rows as IdxSize)),
+        SampleSpec::Fraction(fraction) if fraction > 0.0 && fraction <= 1.0 => {
+            // Row i is kept when (i + 1) * fraction reaches a whole number that
+            // i * fraction didn't, which keeps the exact share; every nth row
+            // would round n, sampling 0.4 as a half and 0.7 as everything
+            let index = col(SAMPLE_ROW_INDEX).cast(DataType::Float64);
+            // Truncating is flooring, as the values are never negative
+            let reached = |index: Expr| (index * lit(fraction)).cast(DataType::UInt64);
+            Ok(lf.with_row_index(SAMPLE_ROW_INDEX, None)
+                .filter(reached(index.clone() + lit(1.0)).gt(reached(index)))
+                .drop([SAMPLE_ROW_INDEX]))
+        },
+        SampleSpec::Fraction(fraction) => Err(DataError::ProcessingError(format!(
+            "Sample fraction {} is not between 0 and 1",
+            fraction
+        ))),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum NullGroups {
+    // Group them under this label, e.g. "(blank)", in the body and the totals alike
+    Label(String),
+    // Leave them out of the pivot, totals included
+    Exclude,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TopRows {
+    pub count: usize,
+    // Index into `values` of the measure the groups are ranked by; the first when not set
+    pub measure: Option<usize>,
+    // Label of the group the remaining rows are folded into; "Other" when not set
+    pub other_label: Option<String>,
+}
+
+const OTHER_LABEL: &str = "Other";
+// Output columns a pivot may have before it has to be confirmed
+const MAX_PIVOT_COLUMNS: usize = 1000;
+const DEFAULT_COLUMN_SEPARATOR: &str = "_";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub enum ColumnOrder {
+    // Numbers by value, dates chronologically and text alphabetically
+    #[default]
+    Natural,
+    // By the label text, even for numbers and dates (10 before 9)
+    Alphabetical,
+    // Natural order reversed, e.g. the latest year first
+    Descending,
+}
+
+// A grouping column derived from a date, usable as a row or column field under
+// its name, e.g. Year and Month axes both coming from `order_date`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DateGrouping {
+    // Date or datetime column the buckets come from
+    pub field: String,
+    pub granularity: DateGranularity,
+    // Field name of the buckets; `{field}_{granularity}` (e.g. "order_date_month") when not set
+    pub name: Option<String>,
+    // Month (1-12) the fiscal year starts in. Years and quarters are then fiscal,
+    // "FY2025" and "FY2025 Q1", each year named after the calendar year it ends in.
+    pub fiscal_year_start: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum DateGranularity {
+    // 2024
+    Year,
+    // "2024-Q1"
+    Quarter,
+    // "2024-03"
+    Month,
+    // The date itself, without the time of day
+    Day,
+    // ISO 8601 week, "2024-W05"; the days around New Year belong to the ISO year
+    Week,
+    // "Mon" to "Sun", ordered from Monday
+    Weekday,
+}
+
+const WEEKDAY_LABELS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+impl DateGrouping {
+    pub fn name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("{}_{}", self.field, format!("{:?}", self.granularity).to_lowercase()),
+        }
+    }
+
+    // Labels sort the same way as the periods they stand for, except weekdays,
+    // which have a fixed order instead
+    fn to_expr(&self) -> Result<Expr, DataError> {
+        let date = col(&self.field);
+        let expr = match (self.granularity, self.fiscal_year_start) {
+            (_, Some(start)) if !(1..=12).contains(&start) => {
+                return Err(DataError::ProcessingError(format!(
+                    "Fiscal year start must be a month from 1 to 12, got {}",
+                    start
+                )));
+            },
+            (DateGranularity::Year, Some(start)) => concat_str(
+                [lit("FY"), fiscal_year(date, start).cast(DataType::String)],
+                "",
+                false,
+            ),
+            (DateGranularity::Quarter, Some(start)) => {
+                // Months since the start of the fiscal year, 0 to 11
+                let month = date.clone().dt().month().cast(DataType::Int32);
+                let offset = (month + lit(12 - start as i32)) % lit(12);
+                concat_str(
+                    [
+                        lit("FY"),
+                        fiscal_year(date, start).cast(DataType::String),
+                        lit(" Q"),
+                        (offset / lit(3) + lit(1)).cast(DataType::String),
+                    ],
+                    "",
+                    false,
+                )
+            },
+            (DateGranularity::Year, None) => date.dt().year(),
+            (DateGranularity::Quarter, None) => concat_str(
+                [
+                    date.clone().dt().year().cast(DataType::String),
+                    lit("-Q"),
+                    date.dt().quarter().cast(DataType::String),
+                ],
+                "",
+                false,
+            ),
+            (DateGranularity::Month, _) => date.dt().strftime("%Y-%m"),
+            (DateGranularity::Day, _) => date.cast(DataType::Date),
+            (DateGranularity::Week, _) => date.dt().strftime("%G-W%V"),
+            (DateGranularity::Weekday, _) => date.dt().strftime("%a"),
+        };
+        Ok(expr.alias(&self.name()))
+    }
+}
+
+// Fiscal year of a date when the year starts in month `start`, named after the
+// calendar year it ends in
+fn fiscal_year(date: Expr, start: u32) -> Expr {
+    let year = date.clone().dt().year();
+    if start == 1 {
+        return year;
+    }
+    
+    let month = date.dt().month().cast(DataType::Int32);
+    year + when(month.gt_eq(lit(start as i32))).then(lit(1)).otherwise(lit(0))
+}
+
+// Numeric field bucketed into ranges, usable as a row or column field under its
+// name, e.g. order value in "0–50", "50–100", ... bands
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NumericBinning {
+    pub field: String,
+    pub bins: BinSpec,
+    // Field name of the bins; `{field}_bins` when not set
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum BinSpec {
+    // Bins of equal width, aligned on `origin` (0 when not set)
+    FixedWidth { width: f64, origin: Option<f64> },
+    // Bin boundaries in increasing order. Values below the first or above the last
+    // land in "< first" and "> last" bins.
+    Edges(Vec<f64>),
+    // This many bins holding about the same number of rows each
+    Quantiles(usize),
+}
+
+// Fixed-width bins are refused beyond this, rather than exploding the pivot
+const MAX_BINS: usize = 1000;
+
+impl NumericBinning {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| format!("{}_bins", self.field))
+    }
+
+    // The bins as explicit edges, looking at the data for the ones that depend on it
+    fn resolve(&self, lf: &LazyFrame) -> Result<NumericBinning, DataError> {
+        let value = col(&self.field).cast(DataType::Float64);
+        let (exprs, edges_of): (Vec<Expr>, fn(Vec<f64>, &BinSpec) -> Vec<f64>) = match &self.bins {
+            BinSpec::Edges(edges) if edges.windows(2).any(|pair| pair[0] >= pair[1]) => {
+                return Err(DataError::ProcessingError(format!(
+                    "Bin edges of {} must be increasing",
+                    self.field
+                )));
+            },
+            BinSpec::Edges(_) => return Ok(self.clone()),
+            BinSpec::FixedWidth { width, .. } if *width <= 0.0 => {
+                return Err(DataError::ProcessingError(format!("Bin width must be positive, got {}", width)));
+            },
+            BinSpec::FixedWidth { .. } => (vec![value.clone().min(), value.max()], fixed_width_edges),
+            BinSpec::Quantiles(0) => {
+                return Err(DataError::ProcessingError("Quantile binning needs at least one bin".to_string()));
+            },
+            BinSpec::Quantiles(count) => (
+                (0..=*count)
+                    .map(|i| value.clone().quantile(lit(i as f64 / *count as f64), QuantileInterpolOptions::Linear))
+                    .collect(),
+                |edges, _| edges,
+            ),
+        };
+        
+        let exprs: Vec<Expr> = exprs.into_iter()
+            .enumerate()
+            .map(|(i, expr)| expr.alias(&format!("edge_{}", i)))
+            .collect();
+        let stats = lf.clone()
+            .select(exprs)
+            .collect()
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+        
+        let mut values = Vec::with_capacity(stats.width());
+        for column in stats.get_columns() {
+            match column.f64().ok().and_then(|values| values.get(0)) {
+                Some(value) => values.push(value),
+                // No values at all, so nothing to bin
+                None => return Ok(NumericBinning { bins: BinSpec::Edges(Vec::new()), ..self.clone() }),
+            }
+        }
+        
+        let mut edges = edges_of(values, &self.bins);
+        edges.dedup();
+        if edges.len() > MAX_BINS + 1 {
+            return Err(DataError::ProcessingError(format!(
+                "Binning {} would make {} bins; use a wider bin",
+                self.field,
+                edges.len() - 1
+            )));
+        }
+        
+        Ok(NumericBinning {
+            bins: BinSpec::Edges(edges),
+            ..self.clone()
+        })
+    }
+
+    // Bin labels in order. Only meaningful once resolved to edges.
+    fn labels(&self) -> Vec<String> {
+        let BinSpec::Edges(edges) = &self.bins else {
+            return Vec::new();
+        };
+        let (Some(first), Some(last)) = (edges.first(), edges.last()) else {
+            return Vec::new();
+        };
+        
+        let mut labels = vec![format!("< {}", first)];
+        labels.extend(edges.windows(2).map(|pair| format!("{}–{}", pair[0], pair[1])));
+        labels.push(format!("> {}", last));
+        labels
+    }
+
+    // Each bin holds its lower edge but not its upper one, except the last, which
+    // holds both so the maximum isn't left out
+    fn to_expr(&self) -> Expr {
+        let value = col(&self.field);
+        let labels = self.labels();
+        let BinSpec::Edges(edges) = &self.bins else {
+            unreachable!("bins are resolved to edges before use");
+        };
+        if edges.is_empty() {
+            return lit(NULL).cast(DataType::String).alias(&self.name());
+        }
+        
+        let last = edges.len() - 1;
+        let mut expr = when(value.clone().gt(lit(edges[last])))
+            .then(lit(labels[last + 1].clone()))
+            .otherwise(lit(NULL).cast(DataType::String));
+        for i in (1..=last).rev() {
+            let inside = if i == last {
+                value.clone().lt_eq(lit(edges[i]))
+            } else {
+                value.clone().lt(lit(edges[i]))
+            };
+            expr = when(inside).then(lit(labels[i].clone())).otherwise(expr);
+        }
+        when(value.lt(lit(edges[0])))
+            .then(lit(labels[0].clone()))
+            .otherwise(expr)
+            .alias(&self.name())
+    }
+}
+
+fn fixed_width_edges(min_max: Vec<f64>, bins: &BinSpec) -> Vec<f64> {
+    let BinSpec::FixedWidth { width, origin } = bins else {
+        return Vec::new();
+    };
+    let origin = origin.unwrap_or(0.0);
+    let (min, max) = (min_max[0], min_max[1]);
+    
+    let first = origin + ((min - origin) / width).floor() * width;
+    let count = (((max - first) / width).floor() as usize + 1).min(MAX_BINS + 1);
+    (0..=count).map(|i| first + i as f64 * width).collect()
+}
+
+impl PivotRequest {
+    // Fixed order of a field's labels, for fields whose labels don't sort by value
+    fn label_order(&self, field: &str) -> Option<Vec<String>> {
+        if let Some(order) = self.sort_orders.as_ref().and_then(|orders| orders.get(field)) {
+            return Some(order.clone());
+        }
+        if let Some(order) = self.resolved_orders.get(field) {
+            return Some(order.clone());
+        }
+        
+        if let Some(binning) = self.numeric_bins.iter().flatten().find(|b| b.name() == field) {
+            return Some(binning.labels());
+        }
+        
+        let grouping = self.date_groupings.iter().flatten().find(|g| g.name() == field)?;
+        match grouping.granularity {
+            DateGranularity::Weekday => Some(WEEKDAY_LABELS.iter().map(|day| day.to_string()).collect()),
+            _ => None,
+        }
+    }
+
+    fn column_separator(&self) -> &str {
+        self.column_separator.as_deref().unwrap_or(DEFAULT_COLUMN_SEPARATOR)
+    }
+    
+    // Key of a measure's pivoted column for one combination of column labels
+    fn column_key(&self, val_with_agg: &ValueWithAggregation, columns: &str) -> String {
+        let template = match &self.column_key_template {
+            Some(template) => template.as_str(),
+            None if self.values.len() > 1 => "{measure}_{columns}",
+            None => "{aggregation}_{columns}",
+        };
+        template
+            .replace("{measure}", &val_with_agg.output_name())
+            .replace("{aggregation}", val_with_agg.aggregation.prefix())
+            .replace("{columns}", columns)
+    }
+    
+    // A template has to tell every pivoted column apart
+    fn check_column_key_template(&self) -> Result<(), DataError> {
+        let Some(template) = &self.column_key_template else {
+            return Ok(());
+        };
+        if !template.contains("{columns}") {
+            return Err(DataError::ProcessingError(
+                "The column key template needs a {columns} placeholder".to_string(),
+            ));
+        }
+        if self.values.len() > 1 && !template.contains("{measure}") {
+            return Err(DataError::ProcessingError(
+                "With several measures the column key template needs a {measure} placeholder".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Names of the source columns the pivot may read, a superset that can include
+    // derived fields; None when it can read any column, through SQL expressions
+    // or by deduplicating whole rows
+    pub fn referenced_columns(&self) -> Option<HashSet<String>> {
+        let mut names: HashSet<String> = self.rows.iter().chain(&self.columns).cloned().collect();
+        for val_with_agg in &self.values {
+            if !val_with_agg.add_referenced_columns(&mut names) {
+                return None;
+            }
+        }
+        for filter in self.filters.iter().flatten() {
+            if !filter.add_referenced_columns(&mut names) {
+                return None;
+            }
+        }
+        for grouping in self.date_groupings.iter().flatten() {
+            names.insert(grouping.field.clone());
+        }
+        for binning in self.numeric_bins.iter().flatten() {
+            names.insert(binning.field.clone());
+        }
+        match &self.distinct_on {
+            Some(keys) if keys.is_empty() => return None,
+            Some(keys) => names.extend(keys.iter().cloned()),
+            None => {},
+        }
+        Some(names)
+    }
+
+    // Whether the result changes with the current date, through relative date
+    // filters anchored on today
+    pub fn depends_on_today(&self) -> bool {
+        self.filters.iter().flatten()
+            .chain(self.measure_filters.iter().flatten())
+            .any(FilterCondition::depends_on_today)
+            || self.values.iter().any(ValueWithAggregation::depends_on_today)
+    }
+
+    // Makes the computation give up with DataError::Cancelled once `flag` is set
+    pub fn set_cancel_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.cancel = Some(flag);
+    }
+
+    pub fn set_progress(&mut self, report: impl Fn(PivotProgress) + Send + Sync + 'static) {
+        self.progress = Some(ProgressReporter(Arc::new(report)));
+    }
+
+    fn report(&self, stage: PivotStage, percent: u8) {
+        if let Some(ProgressReporter(report)) = &self.progress {
+            report(PivotProgress { stage, percent });
+        }
+    }
+
+    // Called between the stages of a computation, which Polars can't interrupt
+    fn check_cancelled(&self) -> Result<(), DataError> {
+        match &self.cancel {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(DataError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    // The same pivot with the row and column axes swapped
+    fn transposed(&self) -> PivotRequest {
+        PivotRequest {
+            rows: self.columns.clone(),
+            columns: self.rows.clone(),
+            collapse: self.collapse.as_ref().map(|collapse| CollapseState {
+                rows: collapse.columns.clone(),
+                columns: collapse.rows.clone(),
+            }),
+            show_row_totals: self.show_column_totals,
+            show_column_totals: self.show_row_totals,
+            transpose: false,
+            ..self.clone()
+        }
+    }
+
+    // Sort keys putting the groups in the order of the pivoted columns, with
+    // whether each one is descending
+    fn column_sort_exprs(&self) -> (Vec<Expr>, Vec<bool>) {
+        let order = self.column_order.unwrap_or_default();
+        let exprs = self.columns.iter()
+            .flat_map(|field| match order {
+                ColumnOrder::Alphabetical if self.label_order(field).is_none() => vec![col(field).cast(DataType::String)],
+                _ => self.label_sort_keys(field),
+            })
+            .collect::<Vec<Expr>>();
+        let descending = vec![matches!(order, ColumnOrder::Descending); exprs.len()];
+        (exprs, descending)
+    }
+
+    // Sort keys of a label column: its position in the field's fixed order if it
+    // has one, then the label itself, which orders the labels missing from the
+    // fixed order. Null labels stay null.
+    fn label_sort_keys(&self, field: &str) -> Vec<Expr> {
+        let Some(order) = self.label_order(field) else {
+            // The null label sorts last and the other labels by their original value
+            return match (&self.null_groups, self.resolved_dtypes.get(field)) {
+                (_, Some(dtype)) => vec![col(field).cast(dtype.clone()), col(field)],
+                (Some(NullGroups::Label(label)), None) => vec![col(field).eq(lit(label.clone())), col(field)],
+                _ => vec![col(field)],
+            };
+        };
+        
+        // Labels missing from the order go after the listed ones
+        let unlisted = when(col(field).is_not_null())
+            .then(lit(order.len() as u32))
+            .otherwise(lit(NULL));
+        let position = order.iter()
+            .enumerate()
+            .rev()
+            .fold(unlisted, |key, (position, label)| {
+                when(col(field).cast(DataType::String).eq(lit(label.clone())))
+                    .then(lit(position as u32))
+                    .otherwise(key)
+            });
+        vec![position, col(field)]
+    }
+}
+
+// Expand/collapse state of a hierarchical result. Each path holds the labels of
+// a group from the outermost field inwards, e.g. ["Europe"] or ["Europe", "France"];
+// a collapsed group is returned as one subtotal with its inner labels left null.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CollapseState {
+    #[serde(default)]
+    pub rows: Vec<Vec<String>>,
+    #[serde(default)]
+    pub columns: Vec<Vec<String>>,
+}
+
+// A slice of the table picked from a cell: the labels of its row and column as
+// shown, from the outermost field inwards. Shorter paths pick a subtotal's whole
+// group, and an empty path any row or column.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CellSelection {
+    #[serde(default)]
+    pub row: Vec<String>,
+    #[serde(default)]
+    pub column: Vec<String>,
+}
+
+// Source rows falling in any of `cells` are dropped, or only those are kept
+// when `keep` is set
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SliceFilter {
+    pub cells: Vec<CellSelection>,
+    #[serde(default)]
+    pub keep: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ReadOptions {
+    // strptime-style format per column, e.g. {"order_date": "%d/%m/%Y"}; sorted
+    // like `PivotRequest::sort_orders`
+    pub date_formats: Option<BTreeMap<String, String>>,
+    // Convert string columns that look like dates using the detected format
+    #[serde(default)]
+    pub auto_parse_dates: bool,
+    // How many CSV rows to look at when inferring column types
+    pub infer_schema_length: Option<InferSchemaLength>,
+    // Table to load when the source is a database
+    pub table: Option<String>,
+    // Clean up column headers on load: strip BOMs, trim whitespace, turn line
+    // breaks into spaces and deduplicate
+    #[serde(default)]
+    pub sanitize_headers: bool,
+    // Worksheet to load from a workbook; the first one when not set
+    pub sheet: Option<String>,
+    // Stack every worksheet of a workbook into one dataset, with a `sheet` column
+    // saying where each row came from. Takes precedence over `sheet`.
+    #[serde(default)]
+    pub append_sheets: bool,
+    // CSV character that wraps fields containing separators; `"` when not set
+    pub quote_char: Option<char>,
+    // CSV character that escapes a quote inside a quoted field, e.g. `\` for \"
+    pub escape_char: Option<char>,
+    // CSV lines starting with this are skipped, e.g. `#` for metadata lines
+    pub comment_prefix: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum InferSchemaLength {
+    Rows(usize),
+    // Scan the whole file; slower, but sparse columns get the right type
+    Full,
+}
+
+// Polars' own default when no inference length is given
+const DEFAULT_INFER_SCHEMA_LENGTH: usize = 100;
+
+// Formats tried, in order, when detecting date columns
+const DATE_FORMAT_CANDIDATES: [&str; 12] = [
+    "%Y-%m-%d",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y/%m/%d",
+    "%d/%m/%Y",
+    "%m/%d/%Y",
+    "%d/%m/%Y %H:%M",
+    "%m/%d/%Y %H:%M",
+    "%d.%m.%Y",
+    "%d-%m-%Y",
+    "%d %b %Y",
+    "%b %d, %Y",
+];
+
+const DATE_DETECTION_SAMPLE_ROWS: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ColumnInfo {
+    pub name: String,
+    pub dtype: String,
+    // Inferred strptime format for string columns that look like dates
+    pub detected_date_format: Option<String>,
+    // Header as written in the file, when it had to be renamed on load
+    pub original_name: Option<String>,
+    // Description, unit and tags from the column catalog
+    pub metadata: Option<ColumnMetadata>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+pub struct DatasetSchema {
+    pub columns: Vec<ColumnInfo>,
+    // How the file was read; sniffed from its contents when the extension didn't say
+    pub format: Option<FileFormat>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FilterCondition {
     pub column: String,
     pub operator: FilterOperator,
     pub value: serde_json::Value,
+    // Compare text ignoring case, so "north" matches "North"
+    #[serde(default)]
+    pub case_insensitive: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl FilterCondition {
+    // Adds the columns the condition reads to `names`; false when it can read any
+    fn add_referenced_columns(&self, names: &mut HashSet<String>) -> bool {
+        match &self.operator {
+            FilterOperator::Expression(_) => false,
+            FilterOperator::Top { measure, .. } => {
+                names.insert(self.column.clone());
+                measure.add_referenced_columns(names)
+            },
+            _ => {
+                names.insert(self.column.clone());
+                true
+            },
+        }
+    }
+
+    // Whether the condition is a relative date filter without an anchor date
+    fn depends_on_today(&self) -> bool {
+        match &self.operator {
+            FilterOperator::InLastDays { anchor, .. }
+            | FilterOperator::MonthToDate { anchor }
+            | FilterOperator::YearToDate { anchor } => anchor.is_none(),
+            FilterOperator::Top { measure, .. } => measure.depends_on_today(),
+            _ => false,
+        }
+    }
+
+    // Human readable form used when naming conditional measures, e.g.
+    // `channel = "online"` or `amount between 1 and 5`
+    fn describe(&self) -> String {
+        if let FilterOperator::Expression(expression) = &self.operator {
+            return expression.clone();
+        }
+        let case = if self.case_insensitive { " ignoring case" } else { "" };
+        format!("{} {}{}", self.column, self.operator.label(&self.value), case)
+    }
+}
+
+impl FilterOperator {
+    // The operator with its value as the user would write it
+    fn label(&self, value: &serde_json::Value) -> String {
+        let compare = |symbol: &str| format!("{} {}", symbol, value);
+        let anchored = |text: String, anchor: &Option<String>| match anchor {
+            Some(anchor) => format!("{} to {}", text, anchor),
+            None => text,
+        };
+        match self {
+            FilterOperator::Equal => compare("="),
+            FilterOperator::NotEqual => compare("!="),
+            FilterOperator::GreaterThan => compare(">"),
+            FilterOperator::LessThan => compare("<"),
+            FilterOperator::GreaterThanOrEqual => compare(">="),
+            FilterOperator::LessThanOrEqual => compare("<="),
+            FilterOperator::In => compare("in"),
+            FilterOperator::Contains => compare("contains"),
+            FilterOperator::StartsWith => compare("starts with"),
+            FilterOperator::EndsWith => compare("ends with"),
+            FilterOperator::Matches => compare("matches"),
+            FilterOperator::IsNull => "is empty".to_string(),
+            FilterOperator::IsNotNull => "is not empty".to_string(),
+            FilterOperator::Between { low, high, inclusive: true } => format!("between {} and {}", low, high),
+            FilterOperator::Between { low, high, inclusive: false } => format!("strictly between {} and {}", low, high),
+            FilterOperator::InLastDays { days, anchor } => anchored(format!("in the last {} days", days), anchor),
+            FilterOperator::MonthToDate { anchor } => anchored("month to date".to_string(), anchor),
+            FilterOperator::YearToDate { anchor } => anchored("year to date".to_string(), anchor),
+            FilterOperator::Top { count, measure, bottom } => format!(
+                "{} {} by {}",
+                if *bottom { "bottom" } else { "top" },
+                count,
+                measure.output_name()
+            ),
+            FilterOperator::Expression(expression) => expression.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum FilterOperator {
     Equal,
     NotEqual,
@@ -78,449 +1247,4077 @@ pub enum FilterOperator {
     GreaterThanOrEqual,
     LessThanOrEqual,
     In,
+    // Substring, prefix and suffix matches on the value as text
+    Contains,
+    StartsWith,
+    EndsWith,
+    // Regular expression searched for in the value as text, e.g. ^ERR-\d+
+    Matches,
+    // Missing values; the condition's value is ignored
+    IsNull,
+    IsNotNull,
+    // Values from `low` to `high`, the bounds included unless `inclusive` is
+    // false; the condition's value is ignored
+    Between {
+        low: serde_json::Value,
+        high: serde_json::Value,
+        #[serde(default = "default_inclusive")]
+        inclusive: bool,
+    },
+    // Dates relative to `anchor` ("2024-06-30"), today when not set, so saved
+    // reports stay current. The condition's value is ignored.
+    // The `days` days up to and including the anchor
+    InLastDays {
+        days: u32,
+        #[serde(default)]
+        anchor: Option<String>,
+    },
+    // From the first of the anchor's month up to and including the anchor
+    MonthToDate {
+        #[serde(default)]
+        anchor: Option<String>,
+    },
+    // From January 1st of the anchor's year up to and including the anchor
+    YearToDate {
+        #[serde(default)]
+        anchor: Option<String>,
+    },
+    // The `count` values of the column with the largest aggregate of `measure`,
+    // e.g. the top 20 customers by Sum of revenue, or the smallest with `bottom`.
+    // Ranked on the rows left by the filters before it; the condition's value is
+    // ignored.
+    Top {
+        count: usize,
+        measure: Box<ValueWithAggregation>,
+        #[serde(default)]
+        bottom: bool,
+    },
+    // Polars-SQL condition over any columns, e.g.
+    // "amount * quantity > 1000 AND region != 'EU'"; the condition's column and
+    // value are ignored
+    Expression(String),
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct PivotResult {
-    pub data: Vec<HashMap<String, serde_json::Value>>,
-    pub column_headers: Vec<Vec<String>>, // Multi-level column headers
-    pub row_headers: Vec<String>,
+fn default_inclusive() -> bool {
+    true
 }
 
-pub fn read_data(file_path: &str) -> Result<LazyFrame, DataError> {
-    let path = Path::new(file_path);
-    let extension = path.extension()
-        .and_then(|ext| ext.to_str())
-        .ok_or_else(|| DataError::UnsupportedFormat("File has no extension".to_string()))?;
-
-    match extension.to_lowercase().as_str() {
-        "csv" => {
-            // LazyCsvReader is in the prelude
-            LazyCsvReader::new(file_path)
-                .with_has_header(true)
-                .finish()
-                .map_err(|e| DataError::ReadError(e.to_string()))
-        },
-        "parquet" => {
-            LazyFrame::scan_parquet(file_path, Default::default())
-                .map_err(|e| DataError::ReadError(e.to_string()))
+// First and last day a relative date filter keeps
+fn relative_date_range(operator: &FilterOperator) -> Result<(chrono::NaiveDate, chrono::NaiveDate), DataError> {
+    use chrono::Datelike;
+    
+    let anchor = match operator {
+        FilterOperator::InLastDays { anchor, .. }
+        | FilterOperator::MonthToDate { anchor }
+        | FilterOperator::YearToDate { anchor } => anchor,
+        _ => return Err(DataError::ProcessingError("Not a relative date filter".to_string())),
+    };
+    let anchor = match anchor {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| {
+            DataError::ProcessingError(format!("Invalid anchor date '{}': {}", date, e))
+        })?,
+        None => chrono::Local::now().date_naive(),
+    };
+    
+    let start = match operator {
+        FilterOperator::InLastDays { days: 0, .. } => {
+            return Err(DataError::ProcessingError("The last 0 days hold no dates".to_string()));
         },
-        _ => Err(DataError::UnsupportedFormat(format!("Unsupported file format: {}", extension))),
-    }
+        FilterOperator::InLastDays { days, .. } => anchor
+            .checked_sub_signed(chrono::Duration::days(*days as i64 - 1))
+            .ok_or_else(|| DataError::ProcessingError(format!("The last {} days reach too far back", days)))?,
+        FilterOperator::MonthToDate { .. } => anchor.with_day(1).expect("every month has a first day"),
+        _ => chrono::NaiveDate::from_ymd_opt(anchor.year(), 1, 1).expect("every year has a January 1st"),
+    };
+    Ok((start, anchor))
 }
 
-pub fn get_column_names(file_path: &str) -> Result<Vec<String>, DataError> {
-    let path = Path::new(file_path);
-    // Use underscore to ignore unused variable
-    let _extension = path.extension()
-        .and_then(|ext| ext.to_str())
-        .ok_or_else(|| DataError::UnsupportedFormat("File has no extension".to_string()))?;
+// Engine limits that come from the app settings rather than from the request
+#[derive(Debug, Clone, Default)]
+pub struct ExecutionOptions {
+    pub memory_budget_bytes: Option<u64>,
+    // Largest pivoted result to build; half the memory available when not set
+    pub result_budget_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PreviewOptions {
+    // Only the first rows of the file are aggregated
+    pub max_rows: Option<usize>,
+    pub time_budget_ms: Option<u64>,
+}
+
+const DEFAULT_PREVIEW_ROWS: usize = 50_000;
+const DEFAULT_PREVIEW_BUDGET_MS: u64 = 1_000;
+
+// A computed pivot before serialization. `df` holds the row fields followed by the
+// value columns, named the way they are keyed in `PivotResult::data`.
+pub struct PivotFrame {
+    pub df: DataFrame,
+    pub column_headers: Vec<Vec<String>>,
+    pub row_headers: Vec<String>,
+    pub column_keys: Vec<String>,
+    // The entries of `column_keys` that hold column totals
+    pub total_column_keys: Vec<String>,
+    // One-row frame with the same columns as `df`, its row fields left null
+    pub grand_total: Option<DataFrame>,
+    // Row fields each row of `df` is grouped on; empty without subtotals
+    pub row_depths: Vec<usize>,
+    // Grouping set each row of `df` belongs to; empty without grouping sets
+    pub grouping_sets: Vec<usize>,
+    // Row fields each grouping set is grouped on
+    pub grouping_set_depths: Vec<usize>,
+    // Format of the measure behind each of `column_keys`
+    pub column_formats: Vec<Option<NumberFormat>>,
+    // Things the user should know about how the request was read
+    pub warnings: Vec<String>,
+    // Sample of the rows the pivot was built from, when it wasn't the full data
+    pub sample: Option<SampleSpec>,
+    pub stats: PivotStats,
+}
+
+impl PivotFrame {
+    // The result rows with the grand total, if any, as the last one
+    pub fn stacked(self) -> Result<DataFrame, DataError> {
+        let Some(grand_total) = self.grand_total else {
+            return Ok(self.df);
+        };
         
-    // Make lf mutable
-    let mut lf = read_data(file_path)?;
+        let args = UnionArgs {
+            to_supertypes: true,
+            ..Default::default()
+        };
+        concat_lf_diagonal([self.df.lazy(), grand_total.lazy()], args)
+            .and_then(|lf| lf.collect())
+            .map_err(|e| DataError::ProcessingError(e.to_string()))
+    }
     
-    // Then fetch just the schema
-    let schema = lf.schema()
+    pub fn into_result(self) -> Result<PivotResult, DataError> {
+        let (df, mut result) = self.into_parts()?;
+        let started = Instant::now();
+        result.data = df_to_json_rows(df).map_err(|e| DataError::ProcessingError(e.to_string()))?;
+        result.stats.serialize_ms = started.elapsed().as_millis() as u64;
+        
+        tracing::debug!(rows = result.data.len(), ms = result.stats.serialize_ms, "Serialized pivot result");
+        
+        Ok(result)
+    }
+    
+    // The result rows, and everything else about the result with `data` left empty
+    pub fn into_parts(self) -> Result<(DataFrame, PivotResult), DataError> {
+        let grand_total = match self.grand_total {
+            Some(df) => df_to_json_rows(df)
+                .map_err(|e| DataError::ProcessingError(e.to_string()))?
+                .pop(),
+            None => None,
+        };
+        // Rows of grouping sets are as deep as their set
+        let depths: Vec<usize> = if self.grouping_sets.is_empty() {
+            self.row_depths.clone()
+        } else {
+            self.grouping_sets.iter().map(|&set| self.grouping_set_depths[set]).collect()
+        };
+        let column_stats = column_stats(&self.df, &self.column_keys, &depths, self.row_headers.len())?;
+        let total_rows = self.df.height();
+        let stats = PivotStats {
+            output_rows: total_rows,
+            output_columns: self.column_keys.len(),
+            ..self.stats
+        };
+        
+        Ok((self.df, PivotResult {
+            data: Vec::new(),
+            column_header_tree: header_tree(&self.column_headers, &self.column_keys),
+            column_headers: self.column_headers,
+            row_headers: self.row_headers,
+            column_keys: self.column_keys,
+            total_column_keys: self.total_column_keys,
+            grand_total,
+            row_depths: self.row_depths,
+            grouping_sets: self.grouping_sets,
+            column_formats: self.column_formats,
+            column_stats,
+            warnings: self.warnings,
+            too_wide: None,
+            sample: self.sample,
+            stats,
+            total_rows,
+            cached: false,
+        }))
+    }
+}
+
+// The result rows as an Arrow IPC stream, preceded by the rest of the result
+// as JSON with its `data` left empty: [JSON length, u32 little endian][JSON][IPC]
+pub fn arrow_response(result: &PivotResult, mut df: DataFrame) -> Result<Vec<u8>, DataError> {
+    let metadata = serde_json::to_vec(result).map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    let mut bytes = Vec::with_capacity(4 + metadata.len());
+    bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&metadata);
+    
+    IpcStreamWriter::new(&mut bytes)
+        .finish(&mut df)
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    Ok(bytes)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ColumnStats {
+    // None for columns without numeric values
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+// Min and max of each value column over the innermost rows, leaving out
+// subtotals and the rows of grouping sets on fewer than all the row fields
+fn column_stats(
+    df: &DataFrame,
+    column_keys: &[String],
+    row_depths: &[usize],
+    depth: usize,
+) -> Result<Vec<ColumnStats>, DataError> {
+    let mut lf = df.clone().lazy();
+    if !row_depths.is_empty() {
+        let leaves: BooleanChunked = row_depths.iter().map(|&d| d == depth).collect();
+        lf = df.filter(&leaves)
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?
+            .lazy();
+    }
+    
+    let exprs: Vec<Expr> = column_keys.iter()
+        .enumerate()
+        .flat_map(|(i, key)| {
+            let values = col(key).cast(DataType::Float64);
+            [values.clone().min().alias(&format!("min_{}", i)), values.max().alias(&format!("max_{}", i))]
+        })
+        .collect();
+    let stats = lf.select(exprs)
+        .collect()
         .map_err(|e| DataError::ProcessingError(e.to_string()))?;
     
-    // Extract field names from the schema
-    Ok(schema.iter_names().map(|name| name.to_string()).collect())
+    let value = |name: String| -> Result<Option<f64>, DataError> {
+        stats.column(&name)
+            .and_then(|column| column.f64().map(|values| values.get(0)))
+            .map_err(|e| DataError::ProcessingError(e.to_string()))
+    };
+    (0..column_keys.len())
+        .map(|i| Ok(ColumnStats {
+            min: value(format!("min_{}", i))?,
+            max: value(format!("max_{}", i))?,
+        }))
+        .collect()
 }
 
-fn apply_filter(lf: LazyFrame, filter: &FilterCondition) -> Result<LazyFrame, DataError> {
-    let col_expr = col(&filter.column);
+// A column header cell, spanning the leaf columns below it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HeaderNode {
+    pub label: String,
+    pub children: Vec<HeaderNode>,
+    // Key of the leaf column in the data rows; None above the leaves
+    pub key: Option<String>,
+}
+
+// Merges the header levels into a tree: a header cell covers the neighbouring
+// leaves that share its label and all the labels above it
+fn header_tree(levels: &[Vec<String>], keys: &[String]) -> Vec<HeaderNode> {
+    let mut roots: Vec<HeaderNode> = Vec::new();
+    for (leaf, key) in keys.iter().enumerate() {
+        let mut siblings = &mut roots;
+        for (depth, level) in levels.iter().enumerate() {
+            let is_leaf = depth == levels.len() - 1;
+            let merges = !is_leaf && siblings.last().is_some_and(|node| node.label == level[leaf]);
+            if !merges {
+                siblings.push(HeaderNode {
+                    label: level[leaf].clone(),
+                    children: Vec::new(),
+                    key: is_leaf.then(|| key.clone()),
+                });
+            }
+            siblings = &mut siblings.last_mut().expect("a header was just pushed").children;
+        }
+    }
+    roots
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PivotResult {
+    pub data: Vec<HashMap<String, serde_json::Value>>,
+    pub column_headers: Vec<Vec<String>>, // Multi-level column headers
+    // The column headers as nested cells: column field labels from the outermost
+    // field inwards, then the measures
+    pub column_header_tree: Vec<HeaderNode>,
+    pub row_headers: Vec<String>,
+    // Key in each data row for every leaf column, in header order
+    pub column_keys: Vec<String>,
+    // The column keys holding per-row totals across the column fields
+    pub total_column_keys: Vec<String>,
+    // Measures aggregated over every row, keyed like the data rows
+    pub grand_total: Option<HashMap<String, serde_json::Value>>,
+    // Number of row fields each data row is grouped on: less than the number of
+    // row headers for subtotal rows. Empty when subtotals are off.
+    pub row_depths: Vec<usize>,
+    // Index into the request's `grouping_sets` of the set each data row is grouped
+    // on. Empty without grouping sets.
+    pub grouping_sets: Vec<usize>,
+    // Format requested for the measure behind each of `column_keys`
+    pub column_formats: Vec<Option<NumberFormat>>,
+    // Range of each of `column_keys` over the data rows, subtotals left out, for
+    // color scales
+    pub column_stats: Vec<ColumnStats>,
+    // Things the user should know about how the request was read, e.g. filter
+    // values converted to their column's type
+    pub warnings: Vec<String>,
+    // Set instead of the data when the pivot was too wide to build unconfirmed
+    pub too_wide: Option<PivotWidth>,
+    // Sample of the rows the pivot was built from; None for the full data
+    pub sample: Option<SampleSpec>,
+    // Timings and row counts, for spotting slow pivots
+    pub stats: PivotStats,
+    // Rows of the whole result; more than `data` holds when it is paged
+    pub total_rows: usize,
+    // Read from the result cache rather than computed; `stats` are from when it was
+    #[serde(default)]
+    pub cached: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PivotWidth {
+    // Estimated number of output columns
+    pub columns: usize,
+    pub limit: usize,
+}
+
+impl PivotResult {
+    // An empty result asking to confirm a pivot with this many columns
+    fn too_wide(columns: usize, limit: usize) -> Self {
+        PivotResult {
+            data: Vec::new(),
+            column_headers: Vec::new(),
+            column_header_tree: Vec::new(),
+            row_headers: Vec::new(),
+            column_keys: Vec::new(),
+            total_column_keys: Vec::new(),
+            grand_total: None,
+            row_depths: Vec::new(),
+            grouping_sets: Vec::new(),
+            column_formats: Vec::new(),
+            column_stats: Vec::new(),
+            warnings: Vec::new(),
+            too_wide: Some(PivotWidth { columns, limit }),
+            sample: None,
+            stats: PivotStats::default(),
+            total_rows: 0,
+            cached: false,
+        }
+    }
+}
+
+pub fn read_data(file_path: &str, options: &ReadOptions) -> Result<LazyFrame, DataError> {
+    let (lf, _) = scan_file(file_path, options)?;
+
+    let detected = if options.auto_parse_dates {
+        detect_date_formats(lf.clone())?
+    } else {
+        HashMap::new()
+    };
+
+    Ok(convert_date_columns(lf, options, &detected))
+}
+
+// Raw scan of the file, before any of the ReadOptions conversions are applied,
+// along with the (name in the file, loaded name) pairs of renamed columns
+fn scan_file(file_path: &str, options: &ReadOptions) -> Result<(LazyFrame, Vec<(String, String)>), DataError> {
+    if is_cloud_path(file_path) {
+        return scan_cloud_file(file_path, options);
+    }
+    if let Some(paths) = expand_glob(file_path)? {
+        return scan_files(&paths, options);
+    }
     
-    let filter_expr = match &filter.operator {
-        FilterOperator::Equal => {
-            match &filter.value {
-                serde_json::Value::String(s) => col_expr.eq(lit(s.clone())),
-                serde_json::Value::Number(n) => {
-                    if let Some(i) = n.as_i64() {
-                        col_expr.eq(lit(i))
-                    } else if let Some(f) = n.as_f64() {
-                        col_expr.eq(lit(f))
-                    } else {
-                        return Err(DataError::ProcessingError("Invalid number".to_string()));
-                    }
-                },
-                serde_json::Value::Bool(b) => col_expr.eq(lit(*b)),
-                _ => return Err(DataError::ProcessingError("Unsupported value type".to_string())),
+    let path = resolve_data_path(file_path)?;
+    if !path.is_file() {
+        return Err(DataError::FileNotFound { path: file_path.to_string() });
+    }
+    
+    let format = detect_file_format(&path)?;
+
+    // '?' in a verbatim prefix would otherwise be taken for a glob pattern
+    let glob = !is_verbatim_path(&path);
+
+    match format {
+        FileFormat::Csv => {
+            // A Parquet copy made by convert_to_parquet is much faster to scan
+            let cache = parquet_cache_path(&path, options)?;
+            if cache.is_file() {
+                let renamed = renamed_columns(&read_csv_header(&path, options)?);
+                let lf = LazyFrame::scan_parquet(&cache, ScanArgsParquet::default())
+                    .map_err(|e| DataError::ReadError(format!("{}: {}", cache.display(), e)))?;
+                return Ok((lf, renamed));
             }
+            
+            scan_csv(&path, options, glob)
         },
-        FilterOperator::NotEqual => {
-            match &filter.value {
-                serde_json::Value::String(s) => col_expr.neq(lit(s.clone())),
-                serde_json::Value::Number(n) => {
-                    if let Some(i) = n.as_i64() {
-                        col_expr.neq(lit(i))
-                    } else if let Some(f) = n.as_f64() {
-                        col_expr.neq(lit(f))
-                    } else {
-                        return Err(DataError::ProcessingError("Invalid number".to_string()));
-                    }
-                },
-                serde_json::Value::Bool(b) => col_expr.neq(lit(*b)),
-                _ => return Err(DataError::ProcessingError("Unsupported value type".to_string())),
-            }
+        FileFormat::Parquet => {
+            let args = ScanArgsParquet {
+                glob,
+                ..Default::default()
+            };
+            let lf = LazyFrame::scan_parquet(&path, args)
+                .map_err(|e| DataError::ReadError(format!("{}: {}", file_path, e)))?;
+            sanitize_frame_headers(lf, options)
         },
-        FilterOperator::GreaterThan => {
-            match &filter.value {
-                serde_json::Value::Number(n) => {
-                    if let Some(i) = n.as_i64() {
-                        col_expr.gt(lit(i))
-                    } else if let Some(f) = n.as_f64() {
-                        col_expr.gt(lit(f))
-                    } else {
-                        return Err(DataError::ProcessingError("Invalid number".to_string()));
-                    }
-                },
-                _ => return Err(DataError::ProcessingError("Value must be a number".to_string())),
-            }
+        FileFormat::Sqlite => {
+            let table = options.table.as_deref().ok_or_else(|| {
+                DataError::ReadError("Choose a table to load from the SQLite database".to_string())
+            })?;
+            // SQLite has no lazy scan in Polars, so the table is materialized up front
+            let lf = SqliteSource::open(&path)?.read_table(table)?.lazy();
+            sanitize_frame_headers(lf, options)
         },
-        FilterOperator::LessThan => {
-            match &filter.value {
-                serde_json::Value::Number(n) => {
-                    if let Some(i) = n.as_i64() {
-                        col_expr.lt(lit(i))
-                    } else if let Some(f) = n.as_f64() {
-                        col_expr.lt(lit(f))
-                    } else {
-                        return Err(DataError::ProcessingError("Invalid number".to_string()));
-                    }
-                },
-                _ => return Err(DataError::ProcessingError("Value must be a number".to_string())),
-            }
+        FileFormat::Json => {
+            // A single JSON array has to be parsed whole
+            let file = File::open(&path)
+                .map_err(|e| DataError::ReadError(format!("{}: {}", file_path, e)))?;
+            let df = JsonReader::new(file)
+                .finish()
+                .map_err(|e| DataError::ReadError(format!("{}: {}", file_path, e)))?;
+            sanitize_frame_headers(df.lazy(), options)
         },
-        FilterOperator::GreaterThanOrEqual => {
-            match &filter.value {
-                serde_json::Value::Number(n) => {
-                    if let Some(i) = n.as_i64() {
-                        col_expr.gt_eq(lit(i))
-                    } else if let Some(f) = n.as_f64() {
-                        col_expr.gt_eq(lit(f))
-                    } else {
-                        return Err(DataError::ProcessingError("Invalid number".to_string()));
-                    }
-                },
-                _ => return Err(DataError::ProcessingError("Value must be a number".to_string())),
-            }
+        FileFormat::NdJson => {
+            let lf = LazyJsonLineReader::new(&path)
+                .finish()
+                .map_err(|e| DataError::ReadError(format!("{}: {}", file_path, e)))?;
+            sanitize_frame_headers(lf, options)
         },
-        FilterOperator::LessThanOrEqual => {
-            match &filter.value {
-                serde_json::Value::Number(n) => {
-                    if let Some(i) = n.as_i64() {
-                        col_expr.lt_eq(lit(i))
-                    } else if let Some(f) = n.as_f64() {
-                        col_expr.lt_eq(lit(f))
-                    } else {
-                        return Err(DataError::ProcessingError("Invalid number".to_string()));
-                    }
-                },
-                _ => return Err(DataError::ProcessingError("Value must be a number".to_string())),
-            }
+        FileFormat::Excel => {
+            let lf = if options.append_sheets {
+                read_all_excel_sheets(&path)?
+            } else {
+                read_excel_sheet(&path, options.sheet.as_deref())?.lazy()
+            };
+            sanitize_frame_headers(lf, options)
         },
-        FilterOperator::In => {
-            match &filter.value {
-                serde_json::Value::Array(arr) => {
-                    if arr.is_empty() {
-                        return Err(DataError::ProcessingError("Empty array in IN filter".to_string()));
-                    }
-                    
-                    // Create a disjunction of equality expressions
-                    let mut expr_list = Vec::new();
-                    
-                    for val in arr {
-                        match val {
-                            serde_json::Value::String(s) => {
-                                expr_list.push(col_expr.clone().eq(lit(s.clone())));
-                            },
-                            serde_json::Value::Number(n) => {
-                                if n.is_i64() {
-                                    if let Some(num) = n.as_i64() {
-                                        expr_list.push(col_expr.clone().eq(lit(num)));
-                                    }
-                                } else if let Some(num) = n.as_f64() {
-                                    expr_list.push(col_expr.clone().eq(lit(num)));
-                                }
-                            },
-                            serde_json::Value::Bool(b) => {
-                                expr_list.push(col_expr.clone().eq(lit(*b)));
-                            },
-                            _ => continue, // Skip non-primitive values
-                        }
-                    }
-                    
-                    if expr_list.is_empty() {
-                        return Err(DataError::ProcessingError("No valid values in IN filter".to_string()));
-                    }
-                    
-                    // Combine all equality expressions with OR
-                    let mut final_expr = expr_list.remove(0);
-                    for expr in expr_list {
-                        final_expr = final_expr.or(expr);
-                    }
-                    
-                    final_expr
-                },
-                _ => return Err(DataError::ProcessingError("Value must be an array".to_string())),
-            }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileFormat {
+    Csv,
+    Parquet,
+    Sqlite,
+    Json,
+    NdJson,
+    Excel,
+}
+
+const FORMAT_SNIFF_BYTES: usize = 4096;
+
+// Format of a local file, from its extension or, for files without a known one
+// (e.g. `data` or `export.tmp`), from its first bytes
+pub fn detect_file_format(path: &Path) -> Result<FileFormat, DataError> {
+    let extension = path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    
+    match extension.as_deref() {
+        Some("csv") => return Ok(FileFormat::Csv),
+        Some("parquet") => return Ok(FileFormat::Parquet),
+        Some("sqlite" | "sqlite3" | "db") => return Ok(FileFormat::Sqlite),
+        Some("json") => return Ok(FileFormat::Json),
+        Some("ndjson" | "jsonl") => return Ok(FileFormat::NdJson),
+        Some("xlsx" | "xlsm" | "xlsb" | "xls" | "ods") => return Ok(FileFormat::Excel),
+        _ => {},
+    }
+    
+    let mut head = Vec::with_capacity(FORMAT_SNIFF_BYTES);
+    File::open(path)
+        .and_then(|file| file.take(FORMAT_SNIFF_BYTES as u64).read_to_end(&mut head))
+        .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?;
+    
+    sniff_file_format(&head).ok_or_else(|| {
+        DataError::UnsupportedFormat(format!(
+            "Could not tell the format of {}; expected CSV, Parquet, SQLite, JSON or Excel",
+            path.display()
+        ))
+    })
+}
+
+fn sniff_file_format(head: &[u8]) -> Option<FileFormat> {
+    if head.starts_with(b"PAR1") {
+        return Some(FileFormat::Parquet);
+    }
+    if head.starts_with(b"SQLite format 3\0") {
+        return Some(FileFormat::Sqlite);
+    }
+    // Zip container (xlsx, ods) or OLE compound file (xls)
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"\xD0\xCF\x11\xE0") {
+        return Some(FileFormat::Excel);
+    }
+    
+    // Anything else has to be text
+    if head.is_empty() || head.contains(&0) {
+        return None;
+    }
+    
+    let text = head.strip_prefix("\u{feff}".as_bytes()).unwrap_or(head);
+    match text.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') => Some(FileFormat::Json),
+        Some(b'{') => Some(FileFormat::NdJson),
+        _ => Some(FileFormat::Csv),
+    }
+}
+
+const SHEET_COLUMN: &str = "sheet";
+
+fn open_workbook(path: &Path) -> Result<calamine::Sheets<BufReader<File>>, DataError> {
+    calamine::open_workbook_auto(path)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))
+}
+
+pub fn list_excel_sheets(file_path: &str) -> Result<Vec<String>, DataError> {
+    Ok(open_workbook(&resolve_data_path(file_path)?)?.sheet_names())
+}
+
+// One worksheet as a DataFrame, taking its first row as the header
+fn read_excel_sheet(path: &Path, sheet: Option<&str>) -> Result<DataFrame, DataError> {
+    let mut workbook = open_workbook(path)?;
+    let sheet = match sheet {
+        Some(sheet) => sheet.to_string(),
+        None => workbook.sheet_names()
+            .into_iter()
+            .next()
+            .ok_or_else(|| DataError::ReadError(format!("{} has no worksheets", path.display())))?,
+    };
+    
+    sheet_frame(&mut workbook, &sheet)
+}
+
+// Every worksheet stacked into one frame. Sheets are matched by column name, so a
+// column missing from one month's sheet is simply null there.
+fn read_all_excel_sheets(path: &Path) -> Result<LazyFrame, DataError> {
+    let mut workbook = open_workbook(path)?;
+    
+    let mut frames = Vec::new();
+    for sheet in workbook.sheet_names() {
+        let df = sheet_frame(&mut workbook, &sheet)?;
+        if df.width() == 0 {
+            continue;
+        }
+        frames.push(df.lazy().with_column(lit(sheet.as_str()).alias(SHEET_COLUMN)));
+    }
+    
+    if frames.is_empty() {
+        return Err(DataError::ReadError(format!("{} has no data in any worksheet", path.display())));
+    }
+    
+    let args = UnionArgs {
+        to_supertypes: true,
+        ..Default::default()
+    };
+    concat_lf_diagonal(frames, args).map_err(|e| DataError::ReadError(e.to_string()))
+}
+
+fn sheet_frame(workbook: &mut calamine::Sheets<BufReader<File>>, sheet: &str) -> Result<DataFrame, DataError> {
+    let range = workbook.worksheet_range(sheet)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", sheet, e)))?;
+    let mut rows = range.rows();
+    let Some(header) = rows.next() else {
+        return Ok(DataFrame::empty());
+    };
+    
+    let names: Vec<String> = header.iter()
+        .enumerate()
+        .map(|(i, cell)| match cell {
+            calamine::Data::Empty => format!("column_{}", i + 1),
+            cell => cell.to_string(),
+        })
+        .collect();
+    let names = dedupe_column_names(&names);
+    
+    let mut columns: Vec<Vec<AnyValue<'static>>> = vec![Vec::new(); names.len()];
+    for row in rows {
+        for (i, values) in columns.iter_mut().enumerate() {
+            values.push(row.get(i).map(excel_value).unwrap_or(AnyValue::Null));
+        }
+    }
+    
+    frame_from_columns(&names, &columns)
+}
+
+fn excel_value(cell: &calamine::Data) -> AnyValue<'static> {
+    match cell {
+        calamine::Data::Int(v) => AnyValue::Int64(*v),
+        calamine::Data::Float(v) => AnyValue::Float64(*v),
+        calamine::Data::String(v) => AnyValue::StringOwned(v.as_str().into()),
+        calamine::Data::Bool(v) => AnyValue::Boolean(*v),
+        // Excel stores dates as datetimes; a midnight time means a plain date
+        calamine::Data::DateTime(v) => match v.as_datetime() {
+            Some(datetime) if datetime.time() == chrono::NaiveTime::MIN => date_value(datetime.date()),
+            Some(datetime) => datetime_value(datetime),
+            None => AnyValue::Null,
+        },
+        calamine::Data::DateTimeIso(v) | calamine::Data::DurationIso(v) => {
+            AnyValue::StringOwned(v.as_str().into())
         },
+        calamine::Data::Error(_) | calamine::Data::Empty => AnyValue::Null,
+    }
+}
+
+// A data path whose file name holds `*` or `?` wildcards, e.g. `sales_2024_*.csv`,
+// stands for every matching file in its directory, in name order
+fn expand_glob(file_path: &str) -> Result<Option<Vec<PathBuf>>, DataError> {
+    if is_url(file_path) {
+        return Ok(None);
+    }
+    
+    let path = normalize_path(file_path);
+    // The verbatim prefix itself contains a '?'
+    let Some(pattern) = path.file_name().and_then(|name| name.to_str()) else {
+        return Ok(None);
+    };
+    if !pattern.contains(['*', '?']) {
+        return Ok(None);
+    }
+    
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
     };
+    let entries = std::fs::read_dir(&dir)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", dir.display(), e)))?;
+    
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| DataError::ReadError(e.to_string()))?;
+        let matches = entry.file_name()
+            .to_str()
+            .map(|name| wildcard_match(pattern, name))
+            .unwrap_or(false);
+        if matches && entry.path().is_file() {
+            paths.push(entry.path());
+        }
+    }
+    
+    if paths.is_empty() {
+        return Err(DataError::ReadError(format!("No files match {}", file_path)));
+    }
+    paths.sort();
+    Ok(Some(paths))
+}
+
+// `*` matches any run of characters, `?` exactly one
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last star swallow one more character and retry
+            p = star + 1;
+            n = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Local files behind a data path: every match of a glob, or the one file
+fn local_paths(file_path: &str) -> Result<Vec<PathBuf>, DataError> {
+    match expand_glob(file_path)? {
+        Some(paths) => Ok(paths),
+        None => Ok(vec![resolve_data_path(file_path)?]),
+    }
+}
+
+// Scan several files as one dataset. Columns are matched by name; a column missing
+// from some files is null there, and differing types are widened to a common one.
+fn scan_files(paths: &[PathBuf], options: &ReadOptions) -> Result<(LazyFrame, Vec<(String, String)>), DataError> {
+    let mut frames = Vec::with_capacity(paths.len());
+    let mut renamed: Vec<(String, String)> = Vec::new();
+    
+    for path in paths {
+        let (lf, file_renamed) = scan_file(&path.display().to_string(), options)?;
+        frames.push(lf);
+        for pair in file_renamed {
+            if !renamed.contains(&pair) {
+                renamed.push(pair);
+            }
+        }
+    }
+    
+    let args = UnionArgs {
+        to_supertypes: true,
+        ..Default::default()
+    };
+    let lf = concat_lf_diagonal(frames, args)
+        .map_err(|e| DataError::ReadError(e.to_string()))?;
+    Ok((lf, renamed))
+}
+
+const CLOUD_SCHEMES: [&str; 6] = ["s3://", "az://", "abfs://", "abfss://", "gs://", "gcs://"];
+
+fn is_cloud_path(file_path: &str) -> bool {
+    let lower = file_path.to_lowercase();
+    CLOUD_SCHEMES.iter().any(|scheme| lower.starts_with(scheme))
+}
+
+// Objects in S3, Azure Blob Storage and Google Cloud Storage are scanned in place by
+// Polars' cloud reader, with credentials from the cloud settings
+fn scan_cloud_file(file_path: &str, options: &ReadOptions) -> Result<(LazyFrame, Vec<(String, String)>), DataError> {
+    let key = file_path.split(['?', '#']).next().unwrap_or(file_path);
+    let extension = Path::new(key)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .ok_or_else(|| DataError::UnsupportedFormat("Object key has no extension".to_string()))?
+        .to_lowercase();
+    
+    let cloud_options = Some(cloud::cloud_options(file_path));
+    let lf = match extension.as_str() {
+        "parquet" => LazyFrame::scan_parquet(file_path, ScanArgsParquet { cloud_options, ..Default::default() })
+            .map_err(|e| DataError::ReadError(format!("{}: {}", file_path, e)))?,
+        "csv" => {
+            let infer_schema_length = match options.infer_schema_length {
+                Some(InferSchemaLength::Rows(rows)) => Some(rows),
+                Some(InferSchemaLength::Full) => None,
+                None => Some(DEFAULT_INFER_SCHEMA_LENGTH),
+            };
+            
+            LazyCsvReader::new(file_path)
+                .with_has_header(true)
+                .with_infer_schema_length(infer_schema_length)
+                .with_cloud_options(cloud_options)
+                .finish()
+                .map_err(|e| DataError::ReadError(format!("{}: {}", file_path, e)))?
+        },
+        _ => return Err(DataError::UnsupportedFormat(format!("Unsupported file format in cloud storage: {}", extension))),
+    };
+    
+    // The header can't be read ahead of the scan here, so it is cleaned up afterwards
+    sanitize_frame_headers(lf, options)
+}
+
+// Scan a CSV file, loading renamed headers under their new names
+fn scan_csv(path: &Path, options: &ReadOptions, glob: bool) -> Result<(LazyFrame, Vec<(String, String)>), DataError> {
+    let quote = csv_quote_char(options)?;
+    let unescaped;
+    let path = match ascii_char(options.escape_char, "Escape")? {
+        Some(escape) if escape != quote => {
+            unescaped = unescaped_csv(path, quote, escape)?;
+            unescaped.as_path()
+        },
+        _ => path,
+    };
+    
+    let infer_schema_length = match options.infer_schema_length {
+        Some(InferSchemaLength::Rows(rows)) => Some(rows),
+        Some(InferSchemaLength::Full) => None,
+        None => Some(DEFAULT_INFER_SCHEMA_LENGTH),
+    };
+
+    // LazyCsvReader is in the prelude
+    let reader = LazyCsvReader::new(path)
+        .with_has_header(true)
+        .with_glob(glob)
+        .with_infer_schema_length(infer_schema_length)
+        .with_quote_char(Some(quote))
+        .with_comment_prefix(options.comment_prefix.as_deref());
+
+    // Headers that aren't valid UTF-8 are decoded anyway when sanitizing,
+    // so the values shouldn't fail the load either
+    let reader = if options.sanitize_headers {
+        reader.with_encoding(CsvEncoding::LossyUtf8)
+    } else {
+        reader
+    };
+    
+    // Load renamed headers (e.g. deduplicated ones) under their new names
+    let header = read_csv_header(path, options)?;
+    let renamed = renamed_columns(&header);
+    let reader = if renamed.is_empty() {
+        reader
+    } else {
+        let names: Vec<String> = header.into_iter().map(|(_, name)| name).collect();
+        reader
+            .with_schema_modify(move |schema| Ok(rename_schema(schema, &names)))
+            .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?
+    };
+    
+    let lf = reader.finish()
+        .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?;
+    Ok((lf, renamed))
+}
+
+fn ascii_char(value: Option<char>, what: &str) -> Result<Option<u8>, DataError> {
+    match value {
+        Some(c) if c.is_ascii() => Ok(Some(c as u8)),
+        Some(c) => Err(DataError::ReadError(format!("{} character must be ASCII, got '{}'", what, c))),
+        None => Ok(None),
+    }
+}
+
+fn csv_quote_char(options: &ReadOptions) -> Result<u8, DataError> {
+    Ok(ascii_char(options.quote_char, "Quote")?.unwrap_or(b'"'))
+}
+
+// How much of a CSV is unescaped at a time
+const UNESCAPE_CHUNK_BYTES: usize = 1 << 20;
+
+// Polars only understands quotes escaped by doubling them, so files that escape
+// them with another character (e.g. \") are rewritten to that form first. The
+// rewritten copy is kept in the temp directory until the source changes, when
+// the copies of its older versions are removed.
+fn unescaped_csv(path: &Path, quote: u8, escape: u8) -> Result<PathBuf, DataError> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?;
+    
+    let mut source = DefaultHasher::new();
+    path.hash(&mut source);
+    
+    let mut version = DefaultHasher::new();
+    metadata.modified().ok().hash(&mut version);
+    metadata.len().hash(&mut version);
+    (quote, escape).hash(&mut version);
+    
+    let dir = std::env::temp_dir().join("turbopivot").join("unescaped");
+    let target = dir.join(format!("{:016x}_{:016x}.csv", source.finish(), version.finish()));
+    if target.is_file() {
+        return Ok(target);
+    }
+    
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", dir.display(), e)))?;
+    let mut file = File::open(path)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?;
+    let partial = target.with_extension("part");
+    let mut out = File::create(&partial)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", partial.display(), e)))?;
+    let write_error = |e: std::io::Error| DataError::ReadError(format!("{}: {}", partial.display(), e));
+    
+    // The state carries over between chunks, so an escape can end one and its
+    // character start the next
+    let mut chunk = vec![0u8; UNESCAPE_CHUNK_BYTES];
+    let mut unescaped = Vec::with_capacity(UNESCAPE_CHUNK_BYTES * 2);
+    let mut in_quotes = false;
+    let mut escaped = false;
+    
+    loop {
+        let read = match file.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(DataError::ReadError(format!("{}: {}", path.display(), e))),
+        };
+        
+        unescaped.clear();
+        for &byte in &chunk[..read] {
+            if escaped {
+                // Escapes only mean something inside quoted fields; unknown ones are kept as they are
+                escaped = false;
+                if byte == quote {
+                    unescaped.extend_from_slice(&[quote, quote]);
+                } else if byte == escape {
+                    unescaped.push(escape);
+                } else {
+                    unescaped.extend_from_slice(&[escape, byte]);
+                }
+            } else if in_quotes && byte == escape {
+                escaped = true;
+            } else {
+                if byte == quote {
+                    in_quotes = !in_quotes;
+                }
+                unescaped.push(byte);
+            }
+        }
+        out.write_all(&unescaped).map_err(write_error)?;
+    }
+    
+    if escaped {
+        out.write_all(&[escape]).map_err(write_error)?;
+    }
+    out.flush().map_err(write_error)?;
+    drop(out);
+    std::fs::rename(&partial, &target)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", target.display(), e)))?;
+    
+    remove_stale_copies(&dir, &target);
+    Ok(target)
+}
+
+// Removes the copies in `dir` made from older versions of the same source as
+// `copy`, whose names share the part before the underscore
+fn remove_stale_copies(dir: &Path, copy: &Path) {
+    let copy_name = copy.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let Some((source_key, _)) = copy_name.split_once('_') else {
+        return;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    
+    let prefix = format!("{}_", source_key);
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with(&prefix) && name != copy_name {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+// Where the Parquet copy of a CSV lives: named after the source path, with a
+// version part covering everything that changes how the CSV parses
+fn parquet_cache_path(path: &Path, options: &ReadOptions) -> Result<PathBuf, DataError> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?;
+    let modified = metadata.modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|since| since.as_nanos())
+        .unwrap_or_default();
+    
+    let mut source = DefaultHasher::new();
+    path.hash(&mut source);
+    
+    let mut version = DefaultHasher::new();
+    modified.hash(&mut version);
+    metadata.len().hash(&mut version);
+    csv_parse_key(options).hash(&mut version);
+    
+    Ok(parquet_cache_dir().join(format!("{:016x}_{:016x}.parquet", source.finish(), version.finish())))
+}
+
+fn parquet_cache_dir() -> PathBuf {
+    std::env::temp_dir().join("turbopivot").join("parquet")
+}
+
+// The ReadOptions that change what scan_csv produces
+fn csv_parse_key(options: &ReadOptions) -> String {
+    format!(
+        "{:?}|{}|{:?}|{:?}|{:?}",
+        options.infer_schema_length,
+        options.sanitize_headers,
+        options.quote_char,
+        options.escape_char,
+        options.comment_prefix,
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ParquetCache {
+    pub source_path: String,
+    pub cache_path: String,
+}
+
+// Convert a CSV into the Parquet cache that later scans of it pick up. Caches
+// of older versions of the same file are removed.
+pub fn convert_to_parquet(file_path: &str, options: &ReadOptions) -> Result<ParquetCache, DataError> {
+    let path = resolve_data_path(file_path)?;
+    if detect_file_format(&path)? != FileFormat::Csv {
+        return Err(DataError::UnsupportedFormat(format!("Only CSV files can be converted: {}", file_path)));
+    }
+    
+    let cache = parquet_cache_path(&path, options)?;
+    let dir = parquet_cache_dir();
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| DataError::ProcessingError(format!("{}: {}", dir.display(), e)))?;
+    
+    // Streams the CSV through, so the file never has to fit in memory
+    let partial = cache.with_extension("part");
+    let (lf, _) = scan_csv(&path, options, !is_verbatim_path(&path))?;
+    lf.sink_parquet(&partial, ParquetWriteOptions::default())
+        .map_err(|e| DataError::ProcessingError(format!("{}: {}", file_path, e)))?;
+    std::fs::rename(&partial, &cache)
+        .map_err(|e| DataError::ProcessingError(format!("{}: {}", cache.display(), e)))?;
+    
+    remove_stale_copies(&dir, &cache);
+    
+    Ok(ParquetCache {
+        source_path: file_path.to_string(),
+        cache_path: cache.display().to_string(),
+    })
+}
+
+// A database whose tables and query results can be pulled into a DataFrame.
+// Each engine converts its own value types; everything else is shared.
+pub trait DatabaseSource {
+    fn list_tables(&mut self) -> Result<Vec<String>, DataError>;
+    fn query(&mut self, query: &str) -> Result<DataFrame, DataError>;
+
+    fn read_table(&mut self, table: &str) -> Result<DataFrame, DataError> {
+        self.query(&format!("SELECT * FROM {}", self.quote_identifier(table)))
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+// Open a database server connection, picking the engine from the URL scheme
+pub fn connect_database(connection_string: &str) -> Result<Box<dyn DatabaseSource>, DataError> {
+    let scheme = connection_string
+        .split_once("://")
+        .map(|(scheme, _)| scheme.to_lowercase())
+        .unwrap_or_default();
+
+    match scheme.as_str() {
+        "postgres" | "postgresql" => Ok(Box::new(PostgresSource::connect(connection_string)?)),
+        "mysql" | "mariadb" => Ok(Box::new(MySqlSource::connect(connection_string)?)),
+        _ => Err(DataError::UnsupportedFormat(
+            "Connection string must start with postgres://, mysql:// or mariadb://".to_string(),
+        )),
+    }
+}
+
+pub struct SqliteSource(rusqlite::Connection);
+
+impl SqliteSource {
+    pub fn open(path: &Path) -> Result<Self, DataError> {
+        rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map(SqliteSource)
+            .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))
+    }
+}
+
+impl DatabaseSource for SqliteSource {
+    fn list_tables(&mut self) -> Result<Vec<String>, DataError> {
+        let mut stmt = self.0
+            .prepare(
+                "SELECT name FROM sqlite_master \
+                 WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' \
+                 ORDER BY name",
+            )
+            .map_err(|e| DataError::ReadError(e.to_string()))?;
+
+        let tables = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| DataError::ReadError(e.to_string()))?
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| DataError::ReadError(e.to_string()))?;
+
+        Ok(tables)
+    }
+
+    fn query(&mut self, query: &str) -> Result<DataFrame, DataError> {
+        let mut stmt = self.0
+            .prepare(query)
+            .map_err(|e| DataError::ReadError(e.to_string()))?;
+
+        let names: Vec<String> = stmt.column_names().iter().map(|name| name.to_string()).collect();
+        let mut columns: Vec<Vec<AnyValue<'static>>> = vec![Vec::new(); names.len()];
+
+        let mut rows = stmt.query([]).map_err(|e| DataError::ReadError(e.to_string()))?;
+        while let Some(row) = rows.next().map_err(|e| DataError::ReadError(e.to_string()))? {
+            for (i, values) in columns.iter_mut().enumerate() {
+                let value = row.get_ref(i).map_err(|e| DataError::ReadError(e.to_string()))?;
+                values.push(match value {
+                    rusqlite::types::ValueRef::Null => AnyValue::Null,
+                    rusqlite::types::ValueRef::Integer(v) => AnyValue::Int64(v),
+                    rusqlite::types::ValueRef::Real(v) => AnyValue::Float64(v),
+                    rusqlite::types::ValueRef::Text(v) => {
+                        AnyValue::StringOwned(String::from_utf8_lossy(v).as_ref().into())
+                    },
+                    rusqlite::types::ValueRef::Blob(v) => AnyValue::BinaryOwned(v.to_vec()),
+                });
+            }
+        }
+
+        frame_from_columns(&names, &columns)
+    }
+}
+
+pub fn list_sqlite_tables(file_path: &str) -> Result<Vec<String>, DataError> {
+    SqliteSource::open(&resolve_data_path(file_path)?)?.list_tables()
+}
+
+pub struct PostgresSource(postgres::Client);
+
+impl PostgresSource {
+    pub fn connect(connection_string: &str) -> Result<Self, DataError> {
+        postgres::Client::connect(connection_string, postgres::NoTls)
+            .map(PostgresSource)
+            .map_err(|e| DataError::ReadError(e.to_string()))
+    }
+
+    fn value(row: &postgres::Row, i: usize) -> Result<AnyValue<'static>, DataError> {
+        use postgres::types::Type;
+        use rust_decimal::prelude::ToPrimitive;
+
+        let column = &row.columns()[i];
+        let read_error = |e: postgres::Error| DataError::ReadError(format!("{}: {}", column.name(), e));
+
+        let value = match *column.type_() {
+            Type::BOOL => row.try_get::<_, Option<bool>>(i).map_err(read_error)?.map(AnyValue::Boolean),
+            Type::INT2 => row.try_get::<_, Option<i16>>(i).map_err(read_error)?.map(|v| AnyValue::Int64(v as i64)),
+            Type::INT4 => row.try_get::<_, Option<i32>>(i).map_err(read_error)?.map(|v| AnyValue::Int64(v as i64)),
+            Type::INT8 => row.try_get::<_, Option<i64>>(i).map_err(read_error)?.map(AnyValue::Int64),
+            Type::FLOAT4 => row.try_get::<_, Option<f32>>(i).map_err(read_error)?.map(|v| AnyValue::Float64(v as f64)),
+            Type::FLOAT8 => row.try_get::<_, Option<f64>>(i).map_err(read_error)?.map(AnyValue::Float64),
+            Type::NUMERIC => row.try_get::<_, Option<rust_decimal::Decimal>>(i)
+                .map_err(read_error)?
+                .and_then(|v| v.to_f64())
+                .map(AnyValue::Float64),
+            Type::DATE => row.try_get::<_, Option<chrono::NaiveDate>>(i)
+                .map_err(read_error)?
+                .map(date_value),
+            Type::TIMESTAMP => row.try_get::<_, Option<chrono::NaiveDateTime>>(i)
+                .map_err(read_error)?
+                .map(datetime_value),
+            Type::TIMESTAMPTZ => row.try_get::<_, Option<chrono::DateTime<chrono::Utc>>>(i)
+                .map_err(read_error)?
+                .map(|v| datetime_value(v.naive_utc())),
+            Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => row.try_get::<_, Option<String>>(i)
+                .map_err(read_error)?
+                .map(|v| AnyValue::StringOwned(v.into())),
+            ref other => {
+                return Err(DataError::ReadError(format!(
+                    "Column {} has unsupported type {}; cast it to text in the query",
+                    column.name(),
+                    other
+                )))
+            },
+        };
+
+        Ok(value.unwrap_or(AnyValue::Null))
+    }
+}
+
+impl DatabaseSource for PostgresSource {
+    fn list_tables(&mut self) -> Result<Vec<String>, DataError> {
+        let rows = self.0
+            .query(
+                "SELECT table_schema || '.' || table_name FROM information_schema.tables \
+                 WHERE table_schema NOT IN ('pg_catalog', 'information_schema') \
+                 ORDER BY 1",
+                &[],
+            )
+            .map_err(|e| DataError::ReadError(e.to_string()))?;
+
+        Ok(rows.iter().map(|row| row.get::<_, String>(0)).collect())
+    }
+
+    fn query(&mut self, query: &str) -> Result<DataFrame, DataError> {
+        // Prepare first so an empty result still knows its columns
+        let stmt = self.0.prepare(query).map_err(|e| DataError::ReadError(e.to_string()))?;
+        let rows = self.0.query(&stmt, &[]).map_err(|e| DataError::ReadError(e.to_string()))?;
+
+        let names: Vec<String> = stmt.columns().iter().map(|column| column.name().to_string()).collect();
+        let mut columns: Vec<Vec<AnyValue<'static>>> = vec![Vec::with_capacity(rows.len()); names.len()];
+        for row in &rows {
+            for (i, values) in columns.iter_mut().enumerate() {
+                values.push(Self::value(row, i)?);
+            }
+        }
+
+        frame_from_columns(&names, &columns)
+    }
+
+    // Names from list_tables are "schema.table", with any further dots part of
+    // the table name, so only the first one splits it
+    fn quote_identifier(&self, name: &str) -> String {
+        let quote = |part: &str| format!("\"{}\"", part.replace('"', "\"\""));
+        match name.split_once('.') {
+            Some((schema, table)) => format!("{}.{}", quote(schema), quote(table)),
+            None => quote(name),
+        }
+    }
+}
+
+pub struct MySqlSource(mysql::Conn);
+
+impl MySqlSource {
+    pub fn connect(connection_string: &str) -> Result<Self, DataError> {
+        // The driver only knows the mysql:// scheme, which MariaDB speaks too
+        let url = match connection_string.split_once("://") {
+            Some((_, rest)) => format!("mysql://{}", rest),
+            None => connection_string.to_string(),
+        };
+
+        let opts = mysql::Opts::from_url(&url).map_err(|e| DataError::ReadError(e.to_string()))?;
+        mysql::Conn::new(opts)
+            .map(MySqlSource)
+            .map_err(|e| DataError::ReadError(e.to_string()))
+    }
+
+    fn value(value: &mysql::Value, column_type: mysql::consts::ColumnType) -> AnyValue<'static> {
+        use mysql::consts::ColumnType;
+
+        match value {
+            mysql::Value::NULL => AnyValue::Null,
+            mysql::Value::Int(v) => AnyValue::Int64(*v),
+            mysql::Value::UInt(v) => i64::try_from(*v)
+                .map(AnyValue::Int64)
+                .unwrap_or(AnyValue::Float64(*v as f64)),
+            mysql::Value::Float(v) => AnyValue::Float64(*v as f64),
+            mysql::Value::Double(v) => AnyValue::Float64(*v),
+            // DECIMAL values arrive as their text representation
+            mysql::Value::Bytes(bytes) => {
+                let text = String::from_utf8_lossy(bytes);
+                match column_type {
+                    ColumnType::MYSQL_TYPE_DECIMAL | ColumnType::MYSQL_TYPE_NEWDECIMAL => text
+                        .parse::<f64>()
+                        .map(AnyValue::Float64)
+                        .unwrap_or(AnyValue::Null),
+                    _ => AnyValue::StringOwned(text.as_ref().into()),
+                }
+            },
+            mysql::Value::Date(year, month, day, hour, minute, second, micros) => {
+                let date = chrono::NaiveDate::from_ymd_opt(*year as i32, *month as u32, *day as u32);
+                match (column_type, date) {
+                    (_, None) => AnyValue::Null,
+                    (ColumnType::MYSQL_TYPE_DATE | ColumnType::MYSQL_TYPE_NEWDATE, Some(date)) => date_value(date),
+                    (_, Some(date)) => date
+                        .and_hms_micro_opt(*hour as u32, *minute as u32, *second as u32, *micros)
+                        .map(datetime_value)
+                        .unwrap_or(AnyValue::Null),
+                }
+            },
+            mysql::Value::Time(negative, days, hours, minutes, seconds, _) => {
+                let hours = *days * 24 + *hours as u32;
+                let sign = if *negative { "-" } else { "" };
+                AnyValue::StringOwned(format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds).into())
+            },
+        }
+    }
+}
+
+impl DatabaseSource for MySqlSource {
+    fn list_tables(&mut self) -> Result<Vec<String>, DataError> {
+        use mysql::prelude::Queryable;
+
+        // Listed across databases like the Postgres schemas, so a connection
+        // string without a database still has tables to load
+        self.0
+            .query(
+                "SELECT CONCAT(table_schema, '.', table_name) FROM information_schema.tables \
+                 WHERE table_schema NOT IN ('mysql', 'information_schema', 'performance_schema', 'sys') \
+                 ORDER BY 1",
+            )
+            .map_err(|e| DataError::ReadError(e.to_string()))
+    }
+
+    fn query(&mut self, query: &str) -> Result<DataFrame, DataError> {
+        use mysql::prelude::Queryable;
+
+        // The binary protocol returns typed values instead of text for everything
+        let result = self.0
+            .exec_iter(query, ())
+            .map_err(|e| DataError::ReadError(e.to_string()))?;
+
+        let result_columns = result.columns();
+        let result_columns = result_columns.as_ref();
+        let names: Vec<String> = result_columns.iter().map(|column| column.name_str().to_string()).collect();
+        let types: Vec<_> = result_columns.iter().map(|column| column.column_type()).collect();
+
+        let mut columns: Vec<Vec<AnyValue<'static>>> = vec![Vec::new(); names.len()];
+        for row in result {
+            let row = row.map_err(|e| DataError::ReadError(e.to_string()))?;
+            for (i, values) in columns.iter_mut().enumerate() {
+                values.push(match row.as_ref(i) {
+                    Some(value) => Self::value(value, types[i]),
+                    None => AnyValue::Null,
+                });
+            }
+        }
+
+        frame_from_columns(&names, &columns)
+    }
+
+    // Names from list_tables are "database.table", split on the first dot as
+    // for Postgres; a name without one is a table of the connection's database
+    fn quote_identifier(&self, name: &str) -> String {
+        let quote = |part: &str| format!("`{}`", part.replace('`', "``"));
+        match name.split_once('.') {
+            Some((database, table)) => format!("{}.{}", quote(database), quote(table)),
+            None => quote(name),
+        }
+    }
+}
+
+fn date_value(date: chrono::NaiveDate) -> AnyValue<'static> {
+    let epoch = chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    AnyValue::Date((date - epoch).num_days() as i32)
+}
+
+fn datetime_value(datetime: chrono::NaiveDateTime) -> AnyValue<'static> {
+    AnyValue::Datetime(datetime.and_utc().timestamp_micros(), TimeUnit::Microseconds, &None)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DatabaseExtract {
+    // Parquet snapshot of the query result, usable as a PivotRequest data_path
+    pub data_path: String,
+    pub rows: usize,
+    pub columns: Vec<String>,
+}
+
+// Run a query against a database server and snapshot the result into `cache_dir`
+pub fn extract_database_query(
+    connection_string: &str,
+    query: &str,
+    cache_dir: &Path,
+) -> Result<DatabaseExtract, DataError> {
+    let df = connect_database(connection_string)?.query(query)?;
+    write_extract(df, connection_string, query, cache_dir)
+}
+
+// Snapshot a whole table, named as list_database_tables gives it, into `cache_dir`
+pub fn extract_database_table(
+    connection_string: &str,
+    table: &str,
+    cache_dir: &Path,
+) -> Result<DatabaseExtract, DataError> {
+    let df = connect_database(connection_string)?.read_table(table)?;
+    write_extract(df, connection_string, table, cache_dir)
+}
+
+pub fn list_database_tables(connection_string: &str) -> Result<Vec<String>, DataError> {
+    connect_database(connection_string)?.list_tables()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnpivotRequest {
+    pub data_path: String,
+    pub read_options: Option<ReadOptions>,
+    // Wide columns melted into rows, e.g. one column per month. The other
+    // columns are repeated on every melted row.
+    pub columns: Vec<String>,
+    // Name of the column holding the melted column names; "variable" when not set
+    pub variable_name: Option<String>,
+    // Name of the column holding their values; "value" when not set
+    pub value_name: Option<String>,
+}
+
+// Melt wide columns into variable/value rows and snapshot the long-format
+// result into `cache_dir`, ready to be pivoted
+pub fn unpivot(request: &UnpivotRequest, cache_dir: &Path) -> Result<DatabaseExtract, DataError> {
+    if request.columns.is_empty() {
+        return Err(DataError::ProcessingError("Select at least one column to unpivot".to_string()));
+    }
+    
+    let lf = read_data(&request.data_path, &request.read_options.clone().unwrap_or_default())?;
+    let schema = lf.schema().map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    if let Some(missing) = request.columns.iter().find(|name| !schema.contains(name)) {
+        return Err(DataError::ProcessingError(format!("Column '{}' not found", missing)));
+    }
+    
+    let id_vars = schema.iter_names()
+        .filter(|name| !request.columns.iter().any(|column| column == name.as_str()))
+        .map(|name| name.as_str().into())
+        .collect();
+    let args = MeltArgs {
+        id_vars,
+        value_vars: request.columns.iter().map(|name| name.as_str().into()).collect(),
+        variable_name: Some(request.variable_name.as_deref().unwrap_or("variable").into()),
+        value_name: Some(request.value_name.as_deref().unwrap_or("value").into()),
+        streamable: true,
+    };
+    let df = lf.melt(args)
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    
+    let description = format!("unpivot {:?} as {:?}/{:?}", request.columns, request.variable_name, request.value_name);
+    write_extract(df, &request.data_path, &description, cache_dir)
+}
+
+// Write a query result to Parquet, named after the source and query so
+// re-running the same query replaces its previous snapshot
+fn write_extract(
+    mut df: DataFrame,
+    source: &str,
+    query: &str,
+    cache_dir: &Path,
+) -> Result<DatabaseExtract, DataError> {
+    std::fs::create_dir_all(cache_dir)
+        .map_err(|e| DataError::ProcessingError(format!("{}: {}", cache_dir.display(), e)))?;
+    
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    query.hash(&mut hasher);
+    let path = cache_dir.join(format!("query_{:016x}.parquet", hasher.finish()));
+    
+    let file = File::create(&path)
+        .map_err(|e| DataError::ProcessingError(format!("{}: {}", path.display(), e)))?;
+    ParquetWriter::new(file)
+        .finish(&mut df)
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    
+    Ok(DatabaseExtract {
+        data_path: path.display().to_string(),
+        rows: df.height(),
+        columns: df.get_column_names().iter().map(|name| name.to_string()).collect(),
+    })
+}
+
+// Build a DataFrame from row-by-row database values. Column types are not fixed
+// in SQLite, so mixed columns are widened to a common type rather than rejected.
+fn frame_from_columns(names: &[String], columns: &[Vec<AnyValue<'static>>]) -> Result<DataFrame, DataError> {
+    let series = names.iter()
+        .zip(columns)
+        .map(|(name, values)| Series::from_any_values(name, values, false))
+        .collect::<PolarsResult<Vec<Series>>>()
+        .map_err(|e| DataError::ReadError(e.to_string()))?;
+
+    DataFrame::new(series).map_err(|e| DataError::ReadError(e.to_string()))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DownloadProgress {
+    pub url: String,
+    pub downloaded_bytes: u64,
+    // Unknown when the server doesn't send a Content-Length
+    pub total_bytes: Option<u64>,
+}
+
+const DOWNLOAD_CHUNK_BYTES: usize = 64 * 1024;
+const DOWNLOAD_PROGRESS_INTERVAL_BYTES: u64 = 1024 * 1024;
+// How long a downloaded copy is used before the server is asked whether it changed
+const DOWNLOAD_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+fn is_url(file_path: &str) -> bool {
+    let lower = file_path.to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+// Local file behind a data path. URLs are served from the download cache,
+// fetching them first if they haven't been downloaded yet.
+pub fn resolve_data_path(file_path: &str) -> Result<PathBuf, DataError> {
+    if is_url(file_path) {
+        download_url(file_path, false, |_| {})
+    } else {
+        Ok(normalize_path(file_path))
+    }
+}
+
+// CSV export endpoint for a Google Sheets link, keeping the tab (gid) it points at.
+// Handles both share links (/d/<id>/edit) and published ones (/d/e/<id>/pubhtml).
+fn google_sheets_csv_url(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let (host, path) = rest.split_once('/')?;
+    if !host.eq_ignore_ascii_case("docs.google.com") {
+        return None;
+    }
+    
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    let segments: Vec<&str> = path.split('/').collect();
+    let gid = url.split(['?', '#', '&'])
+        .find_map(|part| part.strip_prefix("gid="))
+        .map(|gid| format!("&gid={}", gid))
+        .unwrap_or_default();
+    
+    match segments.as_slice() {
+        ["spreadsheets", "d", "e", id, ..] => Some(format!(
+            "https://docs.google.com/spreadsheets/d/e/{}/pub?output=csv{}",
+            id, gid
+        )),
+        ["spreadsheets", "d", id, ..] => Some(format!(
+            "https://docs.google.com/spreadsheets/d/{}/export?format=csv{}",
+            id, gid
+        )),
+        _ => None,
+    }
+}
+
+// Downloads live in the temp directory under a name derived from the URL, keeping
+// the extension so the existing scan path can tell the format. Links without one
+// are sniffed like any other extensionless file.
+fn download_cache_path(url: &str) -> PathBuf {
+    let url_path = url.split(['?', '#']).next().unwrap_or(url);
+    let extension = url_path
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_lowercase());
+
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    let name = match extension {
+        Some(extension) => format!("{:016x}.{}", hasher.finish(), extension),
+        None => format!("{:016x}", hasher.finish()),
+    };
+    std::env::temp_dir().join("turbopivot").join("downloads").join(name)
+}
+
+// Fetch `url` into the download cache, reporting progress as the body arrives.
+// A cached copy is reused for DOWNLOAD_CACHE_TTL, and after that for as long as
+// the server says it hasn't changed since; `refresh` always fetches it again.
+pub fn download_url(
+    url: &str,
+    refresh: bool,
+    mut on_progress: impl FnMut(DownloadProgress),
+) -> Result<PathBuf, DataError> {
+    let sheet_export = google_sheets_csv_url(url);
+    let path = match &sheet_export {
+        Some(export) => download_cache_path(export).with_extension("csv"),
+        None => download_cache_path(url),
+    };
+    // The copy keeps the time it was downloaded, so the data only looks modified
+    // when it was; the last time the server said it hadn't changed is kept apart
+    let checked = path.with_extension("checked");
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    let cached_at = modified(&path).filter(|_| !refresh);
+    let fresh = |at: Option<SystemTime>| at.is_some_and(|at| at.elapsed().is_ok_and(|age| age < DOWNLOAD_CACHE_TTL));
+    if cached_at.is_some() && (fresh(cached_at) || fresh(modified(&checked))) {
+        return Ok(path);
+    }
+    
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| DataError::ReadError(format!("{}: {}", dir.display(), e)))?;
+    }
+    
+    let mut call = ureq::get(sheet_export.as_deref().unwrap_or(url));
+    if let Some(at) = cached_at {
+        let at: chrono::DateTime<chrono::Utc> = at.into();
+        call = call.set("If-Modified-Since", &at.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+    }
+    let response = call.call()
+        .map_err(|e| DataError::ReadError(format!("{}: {}", url, e)))?;
+    // Unchanged: the cached copy is good for another DOWNLOAD_CACHE_TTL
+    if response.status() == 304 {
+        File::create(&checked)
+            .map_err(|e| DataError::ReadError(format!("{}: {}", checked.display(), e)))?;
+        return Ok(path);
+    }
+    
+    // Sheets that aren't shared answer with a sign-in page instead of the CSV
+    if sheet_export.is_some() && response.content_type().contains("html") {
+        return Err(DataError::ReadError(format!(
+            "{}: the sheet isn't visible without signing in; share it with \"Anyone with the link\" or publish it to the web",
+            url
+        )));
+    }
+    let total_bytes = response.header("Content-Length").and_then(|len| len.parse::<u64>().ok());
+    
+    // Write next to the final file and rename at the end so an interrupted
+    // download is never mistaken for a cached one
+    let partial = path.with_extension("part");
+    let mut file = File::create(&partial)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", partial.display(), e)))?;
+    let mut reader = response.into_reader();
+    let mut buffer = vec![0; DOWNLOAD_CHUNK_BYTES];
+    let mut downloaded_bytes = 0;
+    let mut reported_bytes = 0;
+    
+    loop {
+        let read = reader.read(&mut buffer)
+            .map_err(|e| DataError::ReadError(format!("{}: {}", url, e)))?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read])
+            .map_err(|e| DataError::ReadError(format!("{}: {}", partial.display(), e)))?;
+        
+        downloaded_bytes += read as u64;
+        if downloaded_bytes - reported_bytes >= DOWNLOAD_PROGRESS_INTERVAL_BYTES {
+            reported_bytes = downloaded_bytes;
+            on_progress(DownloadProgress {
+                url: url.to_string(),
+                downloaded_bytes,
+                total_bytes,
+            });
+        }
+    }
+    drop(file);
+    
+    std::fs::rename(&partial, &path)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?;
+    on_progress(DownloadProgress {
+        url: url.to_string(),
+        downloaded_bytes,
+        total_bytes,
+    });
+    
+    Ok(path)
+}
+
+// Windows only opens paths longer than MAX_PATH through the verbatim (\\?\) prefix,
+// which for network shares takes the \\?\UNC\server\share form
+#[cfg(windows)]
+pub fn normalize_path(file_path: &str) -> PathBuf {
+    const MAX_PATH: usize = 260;
+    
+    if file_path.starts_with(r"\\?\") || file_path.len() < MAX_PATH {
+        return PathBuf::from(file_path);
+    }
+    
+    // Verbatim paths are not normalized by Windows, so only backslashes are accepted
+    let path = file_path.replace('/', r"\");
+    if let Some(share) = path.strip_prefix(r"\\") {
+        PathBuf::from(format!(r"\\?\UNC\{}", share))
+    } else if Path::new(&path).is_absolute() {
+        PathBuf::from(format!(r"\\?\{}", path))
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+#[cfg(not(windows))]
+pub fn normalize_path(file_path: &str) -> PathBuf {
+    PathBuf::from(file_path)
+}
+
+fn is_verbatim_path(path: &Path) -> bool {
+    path.to_str().map(|p| p.starts_with(r"\\?\")).unwrap_or(false)
+}
+
+// Header of a CSV file as (name in the file, name the column is loaded under) pairs
+fn read_csv_header(path: &Path, options: &ReadOptions) -> Result<Vec<(String, String)>, DataError> {
+    let sanitize = options.sanitize_headers;
+    let quote = csv_quote_char(options)? as char;
+    let comment_prefix = options.comment_prefix.as_deref().filter(|prefix| !prefix.is_empty());
+    
+    let file = File::open(path)
+        .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?;
+    let mut reader = BufReader::new(file);
+    
+    let mut names = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = Vec::new();
+    
+    // A quoted header name may span several physical lines
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line)
+            .map_err(|e| DataError::ReadError(e.to_string()))?;
+        if read == 0 {
+            break;
+        }
+        
+        // Sanitizing falls back to Latin-1 so e.g. "Région" saved by Excel stays readable
+        let text = match std::str::from_utf8(&line) {
+            Ok(text) => text.to_string(),
+            Err(_) if sanitize => line.iter().map(|&b| b as char).collect(),
+            Err(_) => String::from_utf8_lossy(&line).into_owned(),
+        };
+        
+        // Comment lines before the header are skipped, like Polars does
+        if let Some(prefix) = comment_prefix {
+            if !in_quotes && names.is_empty() && field.is_empty()
+                && text.trim_start_matches('\u{feff}').starts_with(prefix)
+            {
+                continue;
+            }
+        }
+        
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                c if c == quote && in_quotes && chars.peek() == Some(&quote) => {
+                    field.push(quote);
+                    chars.next();
+                },
+                c if c == quote => in_quotes = !in_quotes,
+                ',' if !in_quotes => names.push(std::mem::take(&mut field)),
+                '\r' | '\n' if !in_quotes => {},
+                _ => field.push(c),
+            }
+        }
+        
+        if !in_quotes {
+            break;
+        }
+    }
+    names.push(field);
+    
+    if let Some(first) = names.first_mut() {
+        *first = first.trim_start_matches('\u{feff}').to_string();
+    }
+    
+    let unique = if sanitize {
+        sanitize_column_names(&names)
+    } else {
+        dedupe_column_names(&names)
+    };
+    Ok(names.into_iter().zip(unique).collect())
+}
+
+// Cleaned-up headers: no BOMs or line breaks, single spaces, no surrounding
+// whitespace, never empty and never repeated
+fn sanitize_column_names(names: &[String]) -> Vec<String> {
+    let cleaned: Vec<String> = names.iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let name = name
+                .replace('\u{feff}', "")
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ");
+            if name.is_empty() {
+                format!("column_{}", i + 1)
+            } else {
+                name
+            }
+        })
+        .collect();
+    
+    dedupe_column_names(&cleaned)
+}
+
+fn renamed_columns(header: &[(String, String)]) -> Vec<(String, String)> {
+    header.iter()
+        .filter(|(original, name)| original != name)
+        .cloned()
+        .collect()
+}
+
+// Sources with typed schemas (Parquet, databases) are renamed after the scan
+fn sanitize_frame_headers(
+    mut lf: LazyFrame,
+    options: &ReadOptions,
+) -> Result<(LazyFrame, Vec<(String, String)>), DataError> {
+    if !options.sanitize_headers {
+        return Ok((lf, Vec::new()));
+    }
+    
+    let names: Vec<String> = lf.schema()
+        .map_err(|e| DataError::ReadError(e.to_string()))?
+        .iter_names()
+        .map(|name| name.to_string())
+        .collect();
+    let header: Vec<(String, String)> = names.iter()
+        .cloned()
+        .zip(sanitize_column_names(&names))
+        .collect();
+    let renamed = renamed_columns(&header);
+    
+    if renamed.is_empty() {
+        return Ok((lf, renamed));
+    }
+    let (existing, new): (Vec<String>, Vec<String>) = renamed.iter().cloned().unzip();
+    Ok((lf.rename(existing, new), renamed))
+}
+
+// Give repeated names a numeric suffix: Region, Region_1, Region_2, ...
+fn dedupe_column_names(names: &[String]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    
+    names.iter()
+        .map(|name| {
+            let mut candidate = name.clone();
+            let mut suffix = 1;
+            while seen.contains(&candidate) {
+                candidate = format!("{}_{}", name, suffix);
+                suffix += 1;
+            }
+            seen.insert(candidate.clone());
+            candidate
+        })
+        .collect()
+}
+
+// Rename the inferred schema positionally, keeping the inferred dtypes
+fn rename_schema(schema: Schema, names: &[String]) -> Schema {
+    if schema.len() != names.len() {
+        return schema;
+    }
+    
+    schema.iter()
+        .zip(names)
+        .map(|((_, dtype), name)| Field::new(name, dtype.clone()))
+        .collect()
+}
+
+// Convert string columns into real Date/Datetime columns. User-supplied formats
+// take precedence over detected ones.
+fn convert_date_columns(
+    lf: LazyFrame,
+    options: &ReadOptions,
+    detected: &HashMap<String, String>,
+) -> LazyFrame {
+    let mut formats = detected.clone();
+    if let Some(date_formats) = &options.date_formats {
+        formats.extend(date_formats.clone());
+    }
+
+    if formats.is_empty() {
+        return lf;
+    }
+
+    let exprs: Vec<Expr> = formats
+        .iter()
+        .map(|(column, format)| parse_date_expr(col(column), format, true))
+        .collect();
+
+    lf.with_columns(exprs)
+}
+
+fn parse_date_expr(expr: Expr, format: &str, strict: bool) -> Expr {
+    let strptime_options = StrptimeOptions {
+        format: Some(format.to_string()),
+        strict,
+        exact: true,
+        cache: true,
+    };
+
+    // Formats carrying a time component produce a Datetime, everything else a Date
+    if format_has_time(format) {
+        expr.str().to_datetime(None, None, strptime_options, lit("raise"))
+    } else {
+        expr.str().to_date(strptime_options)
+    }
+}
+
+// Find string columns whose sampled values all parse with one of the common
+// date formats, returning the first format that matches for each column
+fn detect_date_formats(mut lf: LazyFrame) -> Result<HashMap<String, String>, DataError> {
+    let schema = lf.schema()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+
+    let string_columns: Vec<String> = schema.iter()
+        .filter(|(_, dtype)| **dtype == DataType::String)
+        .map(|(name, _)| name.to_string())
+        .collect();
+
+    let mut detected = HashMap::new();
+    if string_columns.is_empty() {
+        return Ok(detected);
+    }
+
+    let sample = lf
+        .select(string_columns.iter().map(|name| col(name)).collect::<Vec<Expr>>())
+        .limit(DATE_DETECTION_SAMPLE_ROWS)
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+
+    for name in &string_columns {
+        let values = sample.column(name)
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+        let non_null = values.len() - values.null_count();
+        if non_null == 0 {
+            continue;
+        }
+
+        // Try every candidate in one pass; a non-strict parse yields nulls for misses
+        let attempts = sample.clone()
+            .lazy()
+            .select(
+                DATE_FORMAT_CANDIDATES.iter()
+                    .map(|format| parse_date_expr(col(name), format, false).alias(format))
+                    .collect::<Vec<Expr>>(),
+            )
+            .collect()
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+
+        let matching_format = DATE_FORMAT_CANDIDATES.iter().find(|format| {
+            attempts.column(format)
+                .map(|parsed| parsed.len() - parsed.null_count() == non_null)
+                .unwrap_or(false)
+        });
+
+        if let Some(format) = matching_format {
+            detected.insert(name.clone(), format.to_string());
+        }
+    }
+
+    Ok(detected)
+}
+
+fn format_has_time(format: &str) -> bool {
+    ["%H", "%I", "%M", "%S", "%T", "%R", "%f", "%p", "%s", "%c"]
+        .iter()
+        .any(|spec| format.contains(spec))
+}
+
+pub fn get_schema(file_path: &str, options: &ReadOptions) -> Result<DatasetSchema, DataError> {
+    let (raw, renamed) = scan_file(file_path, options)?;
+    let detected = detect_date_formats(raw.clone())?;
+
+    let auto_detected = if options.auto_parse_dates {
+        detected.clone()
+    } else {
+        HashMap::new()
+    };
+    let mut lf = convert_date_columns(raw, options, &auto_detected);
+
+    let schema = lf.schema()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+
+    // Loaded name -> name in the file, for headers that had to be renamed
+    let renamed: HashMap<String, String> = renamed
+        .into_iter()
+        .map(|(original, name)| (name, original))
+        .collect();
+
+    let columns = schema.iter()
+        .map(|(name, dtype)| ColumnInfo {
+            name: name.to_string(),
+            dtype: dtype.to_string(),
+            detected_date_format: detected.get(name.as_str()).cloned(),
+            original_name: renamed.get(name.as_str()).cloned(),
+            metadata: None,
+        })
+        .collect();
+
+    // Cloud objects are typed by their extension alone
+    let format = if is_cloud_path(file_path) {
+        None
+    } else {
+        // Glob matches are reported by their first file
+        Some(detect_file_format(&local_paths(file_path)?[0])?)
+    };
+    
+    Ok(DatasetSchema { columns, format })
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileStats {
+    pub rows: usize,
+    pub columns: usize,
+    // Total size of the files on disk; None for cloud objects
+    pub size_bytes: Option<u64>,
+}
+
+// Size of a dataset without loading it, so the UI can warn before pivoting a
+// huge file. Parquet row counts come from the file footers; other formats are
+// counted by a scan that parses nothing but the line breaks where it can.
+pub fn file_stats(file_path: &str, options: &ReadOptions) -> Result<FileStats, DataError> {
+    let paths = if is_cloud_path(file_path) { Vec::new() } else { local_paths(file_path)? };
+    let size_bytes = if paths.is_empty() {
+        None
+    } else {
+        let sizes = paths.iter()
+            .map(|path| std::fs::metadata(path).map(|metadata| metadata.len()))
+            .collect::<Result<Vec<u64>, _>>()
+            .map_err(|e| DataError::ReadError(format!("{}: {}", file_path, e)))?;
+        Some(sizes.into_iter().sum())
+    };
+    
+    let all_parquet = !paths.is_empty()
+        && paths.iter().all(|path| matches!(detect_file_format(path), Ok(FileFormat::Parquet)));
+    let (mut lf, _) = scan_file(file_path, options)?;
+    let columns = lf.schema()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?
+        .len();
+    
+    let rows = if all_parquet {
+        parquet_rows(&paths)?
+    } else {
+        lf.select([len()])
+            .collect()
+            .and_then(|df| df.column("len")?.u32().map(|len| len.get(0).unwrap_or(0) as usize))
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?
+    };
+    
+    Ok(FileStats { rows, columns, size_bytes })
+}
+
+fn parquet_rows(paths: &[PathBuf]) -> Result<usize, DataError> {
+    let mut rows = 0;
+    for path in paths {
+        let file = File::open(path)
+            .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?;
+        rows += ParquetReader::new(file)
+            .num_rows()
+            .map_err(|e| DataError::ReadError(format!("{}: {}", path.display(), e)))?;
+    }
+    Ok(rows)
+}
+
+// Rows of local Parquet data from its footers, without reading it; None for
+// anything else
+fn footer_rows(file_path: &str) -> Option<usize> {
+    if file_path.is_empty() || is_cloud_path(file_path) || is_url(file_path) {
+        return None;
+    }
+    let paths = local_paths(file_path).ok()?;
+    if !paths.iter().all(|path| matches!(detect_file_format(path), Ok(FileFormat::Parquet))) {
+        return None;
+    }
+    parquet_rows(&paths).ok()
+}
+
+pub fn get_column_names(file_path: &str, options: &ReadOptions) -> Result<Vec<String>, DataError> {
+    // Make lf mutable
+    let mut lf = read_data(file_path, options)?;
+    
+    // Then fetch just the schema
+    let schema = lf.schema()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    
+    // Extract field names from the schema
+    Ok(schema.iter_names().map(|name| name.to_string()).collect())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ValueCount {
+    pub value: serde_json::Value,
+    pub count: u64,
+    // Share of all rows, from 0 to 100
+    pub percent: f64,
+}
+
+// The distinct values of a column with their row counts, most frequent first.
+// Nulls count as a value of their own.
+pub fn value_counts(
+    file_path: &str,
+    options: &ReadOptions,
+    column: &str,
+    limit: Option<usize>,
+) -> Result<Vec<ValueCount>, DataError> {
+    const COUNT: &str = "__count";
+    
+    let counts = read_data(file_path, options)?
+        .group_by([col(column)])
+        .agg([len().alias(COUNT)])
+        .sort(
+            vec![COUNT.to_string(), column.to_string()],
+            SortMultipleOptions::default()
+                .with_order_descending_multi([true, false])
+                .with_nulls_last(true),
+        )
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    let row_counts = counts.column(COUNT)
+        .and_then(|counts| counts.u32().cloned())
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    let total: u64 = row_counts.into_no_null_iter().map(|count| count as u64).sum();
+    let counts = match limit {
+        Some(limit) => counts.head(Some(limit)),
+        None => counts,
+    };
+    
+    let values = counts.column(column).map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    (0..counts.height())
+        .map(|i| {
+            let count = row_counts.get(i).unwrap_or(0) as u64;
+            Ok(ValueCount {
+                value: json_value(values.get(i).map_err(|e| DataError::ProcessingError(e.to_string()))?),
+                count,
+                percent: if total == 0 { 0.0 } else { count as f64 * 100.0 / total as f64 },
+            })
+        })
+        .collect()
+}
+
+// The filter with its value converted to the column's type when the JSON type
+// doesn't match, e.g. "42" for an integer column or 2100 for zip codes stored as
+// text, with a warning saying so. None when nothing needed converting.
+fn coerce_filter(filter: &FilterCondition, dtype: &DataType) -> Option<(FilterCondition, String)> {
+    let convert = |value: &serde_json::Value| -> Option<serde_json::Value> {
+        match (value, dtype) {
+            (serde_json::Value::String(s), dtype) if dtype.is_numeric() => {
+                let s = s.trim();
+                s.parse::<i64>()
+                    .map(serde_json::Value::from)
+                    .ok()
+                    .or_else(|| s.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(serde_json::Value::Number))
+            },
+            (serde_json::Value::String(s), DataType::Boolean) => {
+                s.trim().to_lowercase().parse::<bool>().ok().map(serde_json::Value::Bool)
+            },
+            (serde_json::Value::Number(n), DataType::String) => Some(serde_json::Value::String(n.to_string())),
+            _ => None,
+        }
+    };
+    
+    let mut coerced = filter.clone();
+    let mut changed = false;
+    let mut coerce = |value: &mut serde_json::Value| {
+        if let Some(converted) = convert(value) {
+            *value = converted;
+            changed = true;
+        }
+    };
+    match &mut coerced.operator {
+        FilterOperator::Equal
+        | FilterOperator::NotEqual
+        | FilterOperator::GreaterThan
+        | FilterOperator::LessThan
+        | FilterOperator::GreaterThanOrEqual
+        | FilterOperator::LessThanOrEqual => coerce(&mut coerced.value),
+        FilterOperator::In => {
+            if let serde_json::Value::Array(values) = &mut coerced.value {
+                values.iter_mut().for_each(&mut coerce);
+            }
+        },
+        FilterOperator::Between { low, high, .. } => {
+            coerce(low);
+            coerce(high);
+        },
+        // Text, null, relative date and top filters take no value to convert
+        _ => {},
+    }
+    
+    if !changed {
+        return None;
+    }
+    let warning = format!(
+        "Filter on '{}' was converted to the column's {} type: {}",
+        filter.column,
+        dtype,
+        coerced.describe()
+    );
+    Some((coerced, warning))
+}
+
+fn apply_filter(lf: LazyFrame, filter: &FilterCondition) -> Result<LazyFrame, DataError> {
+    if let FilterOperator::Top { count, measure, bottom } = &filter.operator {
+        return keep_top_values(lf, &filter.column, measure, *count, *bottom);
+    }
+    Ok(lf.filter(build_filter_expr(filter)?))
+}
+
+// Keeps the rows of the `count` values of `field` whose aggregate is largest,
+// or smallest when `bottom` is set
+fn keep_top_values(
+    lf: LazyFrame,
+    field: &str,
+    measure: &ValueWithAggregation,
+    count: usize,
+    bottom: bool,
+) -> Result<LazyFrame, DataError> {
+    let ranked = lf.clone()
+        .filter(col(field).is_not_null())
+        .group_by([col(field)])
+        .agg([measure.to_expr()?])
+        .sort(
+            vec![measure.output_name(), field.to_string()],
+            SortMultipleOptions::default()
+                .with_order_descending_multi([!bottom, false])
+                .with_nulls_last(true)
+                .with_maintain_order(true),
+        )
+        .limit(count as IdxSize)
+        .select([col(field)]);
+    
+    Ok(lf.join(ranked, [col(field)], [col(field)], JoinArgs::new(JoinType::Inner)))
+}
+
+// Literal an ordering comparison or range bound is made against: a number, or
+// an ISO 8601 date ("2024-01-01") or datetime ("2024-01-01T08:30:00") for
+// date columns
+fn comparison_literal(value: &serde_json::Value) -> Result<Expr, DataError> {
+    match value {
+        serde_json::Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Ok(lit(i))
+            } else if let Some(f) = n.as_f64() {
+                Ok(lit(f))
+            } else {
+                Err(DataError::ProcessingError("Invalid number".to_string()))
+            }
+        },
+        serde_json::Value::String(s) => {
+            if let Ok(date) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+                return Ok(lit(date));
+            }
+            ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+                .iter()
+                .find_map(|format| chrono::NaiveDateTime::parse_from_str(s, format).ok())
+                .map(lit)
+                .ok_or_else(|| DataError::ProcessingError(format!(
+                    "'{}' is not a number or an ISO 8601 date",
+                    s
+                )))
+        },
+        _ => Err(DataError::ProcessingError("Value must be a number or an ISO 8601 date".to_string())),
+    }
+}
+
+fn build_filter_expr(filter: &FilterCondition) -> Result<Expr, DataError> {
+    let col_expr = col(&filter.column);
+    // Text comparisons run on lowercased values when case is ignored
+    let text_col = if filter.case_insensitive {
+        col_expr.clone().cast(DataType::String).str().to_lowercase()
+    } else {
+        col_expr.clone()
+    };
+    let text = |s: &String| if filter.case_insensitive { s.to_lowercase() } else { s.clone() };
+    
+    let filter_expr = match &filter.operator {
+        FilterOperator::Equal => {
+            match &filter.value {
+                serde_json::Value::String(s) => text_col.eq(lit(text(s))),
+                serde_json::Value::Number(n) => {
+                    if let Some(i) = n.as_i64() {
+                        col_expr.eq(lit(i))
+                    } else if let Some(f) = n.as_f64() {
+                        col_expr.eq(lit(f))
+                    } else {
+                        return Err(DataError::ProcessingError("Invalid number".to_string()));
+                    }
+                },
+                serde_json::Value::Bool(b) => col_expr.eq(lit(*b)),
+                _ => return Err(DataError::ProcessingError("Unsupported value type".to_string())),
+            }
+        },
+        FilterOperator::NotEqual => {
+            match &filter.value {
+                serde_json::Value::String(s) => text_col.neq(lit(text(s))),
+                serde_json::Value::Number(n) => {
+                    if let Some(i) = n.as_i64() {
+                        col_expr.neq(lit(i))
+                    } else if let Some(f) = n.as_f64() {
+                        col_expr.neq(lit(f))
+                    } else {
+                        return Err(DataError::ProcessingError("Invalid number".to_string()));
+                    }
+                },
+                serde_json::Value::Bool(b) => col_expr.neq(lit(*b)),
+                _ => return Err(DataError::ProcessingError("Unsupported value type".to_string())),
+            }
+        },
+        FilterOperator::GreaterThan => col_expr.gt(comparison_literal(&filter.value)?),
+        FilterOperator::LessThan => col_expr.lt(comparison_literal(&filter.value)?),
+        FilterOperator::GreaterThanOrEqual => col_expr.gt_eq(comparison_literal(&filter.value)?),
+        FilterOperator::LessThanOrEqual => col_expr.lt_eq(comparison_literal(&filter.value)?),
+        FilterOperator::In => {
+            match &filter.value {
+                serde_json::Value::Array(arr) => {
+                    if arr.is_empty() {
+                        return Err(DataError::ProcessingError("Empty array in IN filter".to_string()));
+                    }
+                    
+                    // Create a disjunction of equality expressions
+                    let mut expr_list = Vec::new();
+                    
+                    for val in arr {
+                        match val {
+                            serde_json::Value::String(s) => {
+                                expr_list.push(text_col.clone().eq(lit(text(s))));
+                            },
+                            serde_json::Value::Number(n) => {
+                                if n.is_i64() {
+                                    if let Some(num) = n.as_i64() {
+                                        expr_list.push(col_expr.clone().eq(lit(num)));
+                                    }
+                                } else if let Some(num) = n.as_f64() {
+                                    expr_list.push(col_expr.clone().eq(lit(num)));
+                                }
+                            },
+                            serde_json::Value::Bool(b) => {
+                                expr_list.push(col_expr.clone().eq(lit(*b)));
+                            },
+                            _ => continue, // Skip non-primitive values
+                        }
+                    }
+                    
+                    if expr_list.is_empty() {
+                        return Err(DataError::ProcessingError("No valid values in IN filter".to_string()));
+                    }
+                    
+                    // Combine all equality expressions with OR
+                    let mut final_expr = expr_list.remove(0);
+                    for expr in expr_list {
+                        final_expr = final_expr.or(expr);
+                    }
+                    
+                    final_expr
+                },
+                _ => return Err(DataError::ProcessingError("Value must be an array".to_string())),
+            }
+        },
+        FilterOperator::Between { low, high, inclusive } => {
+            let (low, high) = (comparison_literal(low)?, comparison_literal(high)?);
+            if *inclusive {
+                col_expr.clone().gt_eq(low).and(col_expr.lt_eq(high))
+            } else {
+                col_expr.clone().gt(low).and(col_expr.lt(high))
+            }
+        },
+        FilterOperator::InLastDays { .. } | FilterOperator::MonthToDate { .. } | FilterOperator::YearToDate { .. } => {
+            let (start, end) = relative_date_range(&filter.operator)?;
+            let date = col_expr.cast(DataType::Date);
+            date.clone().gt_eq(lit(start)).and(date.lt_eq(lit(end)))
+        },
+        FilterOperator::Top { .. } => {
+            return Err(DataError::ProcessingError(
+                "Top filters rank whole groups and can only filter the pivot's rows".to_string(),
+            ));
+        },
+        FilterOperator::Expression(expression) => {
+            polars::sql::sql_expr(expression).map_err(|e| DataError::ProcessingError(format!(
+                "Invalid filter expression '{}': {}", expression, e
+            )))?
+        },
+        FilterOperator::IsNull => col_expr.is_null(),
+        FilterOperator::IsNotNull => col_expr.is_not_null(),
+        FilterOperator::Matches => {
+            let pattern = match &filter.value {
+                serde_json::Value::String(s) => s.clone(),
+                _ => return Err(DataError::ProcessingError("Pattern must be a string".to_string())),
+            };
+            // Polars would only report a bad pattern once the query runs
+            regex::Regex::new(&pattern).map_err(|e| DataError::ProcessingError(format!(
+                "Invalid pattern '{}' for column '{}': {}",
+                pattern, filter.column, e
+            )))?;
+            let pattern = if filter.case_insensitive { format!("(?i){}", pattern) } else { pattern };
+            col_expr.cast(DataType::String).str().contains(lit(pattern), true)
+        },
+        FilterOperator::Contains | FilterOperator::StartsWith | FilterOperator::EndsWith => {
+            let text = match &filter.value {
+                serde_json::Value::String(s) => lit(text(s)),
+                // The filter box turns digits into numbers, e.g. a "404" status prefix
+                serde_json::Value::Number(n) => lit(n.to_string()),
+                _ => return Err(DataError::ProcessingError("Value must be a string".to_string())),
+            };
+            let col_text = text_col.cast(DataType::String).str();
+            match &filter.operator {
+                FilterOperator::Contains => col_text.contains_literal(text),
+                FilterOperator::StartsWith => col_text.starts_with(text),
+                _ => col_text.ends_with(text),
+            }
+        },
+    };
+    
+    Ok(filter_expr)
+}
+
+pub fn generate_pivot(request: PivotRequest, options: &ExecutionOptions) -> Result<PivotResult, DataError> {
+    pivot_response(compute_pivot(&request, options))
+}
+
+// Pivot over data already read, e.g. a dataset kept open between requests: the
+// result rows, and everything else about the result with `data` left empty, to
+// be sent with `paged_response` or `arrow_response`. A pivot too wide to build
+// gives no rows and a result asking to confirm it.
+pub fn pivot_parts(
+    lf: LazyFrame,
+    request: &PivotRequest,
+    options: &ExecutionOptions,
+) -> Result<(DataFrame, PivotResult), DataError> {
+    match compute_pivot_on(lf, request, options) {
+        Err(DataError::TooManyColumns { columns, limit }) => Ok((DataFrame::empty(), PivotResult::too_wide(columns, limit))),
+        frame => frame?.into_parts(),
+    }
+}
+
+// Fills in the rows of a result from `pivot_parts`: all of them, or the first
+// page with the rest returned for later pages when the request has a page size
+pub fn paged_response(
+    df: DataFrame,
+    mut result: PivotResult,
+    request: &PivotRequest,
+) -> Result<(PivotResult, Option<PivotPages>), DataError> {
+    request.report(PivotStage::Serializing, 90);
+    let started = Instant::now();
+    
+    let pages = match request.page_size {
+        Some(page_size) if result.too_wide.is_none() => {
+            let pages = PivotPages { df };
+            result.data = pages.page(0, page_size)?.data;
+            Some(pages)
+        },
+        _ => {
+            result.data = df_to_json_rows(df).map_err(|e| DataError::ProcessingError(e.to_string()))?;
+            None
+        },
+    };
+    result.stats.serialize_ms = started.elapsed().as_millis() as u64;
+    
+    tracing::debug!(rows = result.data.len(), ms = result.stats.serialize_ms, "Serialized pivot result");
+    Ok((result, pages))
+}
+
+// The full rows of a paged result
+pub struct PivotPages {
+    df: DataFrame,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PivotPage {
+    pub offset: usize,
+    pub data: Vec<HashMap<String, serde_json::Value>>,
+    pub total_rows: usize,
+}
+
+impl PivotPages {
+    // Up to `limit` rows from `offset`, keyed like `PivotResult::data`
+    pub fn page(&self, offset: usize, limit: usize) -> Result<PivotPage, DataError> {
+        let data = df_to_json_rows(self.df.slice(offset as i64, limit))
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+        Ok(PivotPage {
+            offset,
+            data,
+            total_rows: self.df.height(),
+        })
+    }
+}
+
+// Turns a pivot that is too wide into a result the UI can ask to confirm
+fn pivot_response(frame: Result<PivotFrame, DataError>) -> Result<PivotResult, DataError> {
+    match frame {
+        Err(DataError::TooManyColumns { columns, limit }) => Ok(PivotResult::too_wide(columns, limit)),
+        frame => frame?.into_result(),
+    }
+}
+
+// The groups one level below the row group at `path`, e.g. the countries of
+// ["Europe"], for drilling into a tree without computing every level up front
+pub fn expand_group(
+    lf: LazyFrame,
+    mut request: PivotRequest,
+    path: Vec<String>,
+    options: &ExecutionOptions,
+) -> Result<PivotResult, DataError> {
+    let rows = if request.transpose { &request.columns } else { &request.rows };
+    if path.len() >= rows.len() {
+        return Err(DataError::ProcessingError(
+            "The group is already at the innermost row level".to_string(),
+        ));
+    }
+    
+    request.expand_path = Some(path);
+    pivot_response(compute_pivot_on(lf, &request, options))
+}
+
+// Run the aggregation and reshape of a request without serializing the result
+pub fn compute_pivot(request: &PivotRequest, options: &ExecutionOptions) -> Result<PivotFrame, DataError> {
+    // Read the data as a LazyFrame
+    let lf = read_data(&request.data_path, &request.read_options.clone().unwrap_or_default())?;
+    compute_pivot_on(lf, request, options)
+}
+
+fn compute_pivot_on(lf: LazyFrame, request: &PivotRequest, options: &ExecutionOptions) -> Result<PivotFrame, DataError> {
+    let budgeted = PivotRequest {
+        result_budget: options.result_budget_bytes.or_else(default_memory_budget),
+        ..request.clone()
+    };
+    let request = &budgeted;
+    
+    match needs_streaming(&request.data_path, options)? {
+        Engine::InMemory => return compute_pivot_from(lf, request),
+        Engine::OverBudget(warning) => {
+            let mut frame = compute_pivot_from(lf, request)?;
+            frame.warnings.push(warning);
+            return Ok(frame);
+        },
+        Engine::Streaming => {},
+    }
+    
+    // Only the aggregates are held in memory; the reshape works on those
+    let streamed = PivotRequest {
+        streaming: true,
+        ..request.clone()
+    };
+    let mut frame = compute_pivot_from(lf.with_streaming(true), &streamed)?;
+    for val_with_agg in request.values.iter().filter(|v| !v.aggregation.streams()) {
+        frame.warnings.push(format!(
+            "{:?} of {} can't be streamed, so the whole dataset is loaded to compute it",
+            val_with_agg.aggregation, val_with_agg.field
+        ));
+    }
+    Ok(frame)
+}
+
+// Rough in-memory size of a file once loaded; Parquet is compressed on disk
+fn estimate_loaded_bytes(path: &Path) -> Option<u64> {
+    let file_size = std::fs::metadata(path).ok()?.len();
+    
+    let factor = match detect_file_format(path).ok()? {
+        FileFormat::Parquet => 4,
+        _ => 1,
+    };
+    Some(file_size * factor)
+}
+
+// Rough in-memory size of the local files behind a data path
+pub fn estimated_size(file_path: &str) -> Option<u64> {
+    local_paths(file_path).ok()?.iter().map(|path| estimate_loaded_bytes(path)).sum()
+}
+
+// Latest modification time of the local files behind a data path; None for
+// cloud paths, which can't be checked cheaply
+pub fn data_modified(file_path: &str) -> Option<SystemTime> {
+    if is_cloud_path(file_path) {
+        return None;
+    }
+    local_paths(file_path).ok()?
+        .iter()
+        .map(|path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok())
+        .collect::<Option<Vec<SystemTime>>>()?
+        .into_iter()
+        .max()
+}
+
+// Latest modification time and total size of the local files behind a data
+// path, which change whenever the data does; None for cloud paths
+pub fn data_fingerprint(file_path: &str) -> Option<(SystemTime, u64)> {
+    let modified = data_modified(file_path)?;
+    let size = local_paths(file_path).ok()?
+        .iter()
+        .map(|path| std::fs::metadata(path).map(|metadata| metadata.len()).ok())
+        .sum::<Option<u64>>()?;
+    Some((modified, size))
+}
+
+// Half the memory currently available, when no budget is set, so a dataset
+// larger than RAM streams instead of getting the app killed
+fn default_memory_budget() -> Option<u64> {
+    let mut system = sysinfo::System::new();
+    system.refresh_memory();
+    match system.available_memory() {
+        0 => None,
+        available => Some(available / 2),
+    }
+}
+
+// How a pivot runs given the memory budget
+enum Engine {
+    InMemory,
+    Streaming,
+    // Over the default budget on a source that can't stream; it runs in memory
+    // anyway, with this warning
+    OverBudget(String),
+}
+
+// Whether the pivot has to run on the streaming engine to stay within the memory
+// budget. Sources that can't stream fail instead, with what the user can do about
+// it, when the user set the budget; over the default budget they only get a warning.
+fn needs_streaming(file_path: &str, options: &ExecutionOptions) -> Result<Engine, DataError> {
+    // Cloud objects can't be sized up front, nor their globs expanded locally
+    if is_cloud_path(file_path) {
+        return Ok(Engine::InMemory);
+    }
+    let Some(budget) = options.memory_budget_bytes.or_else(default_memory_budget) else {
+        return Ok(Engine::InMemory);
+    };
+    
+    let paths = local_paths(file_path)?;
+    let Some(estimated) = paths.iter().map(|path| estimate_loaded_bytes(path)).sum::<Option<u64>>() else {
+        return Ok(Engine::InMemory);
+    };
+    
+    if estimated <= budget {
+        return Ok(Engine::InMemory);
+    }
+    
+    let can_stream = paths.iter().all(|path| matches!(
+        detect_file_format(path),
+        Ok(FileFormat::Csv | FileFormat::Parquet | FileFormat::NdJson)
+    ));
+    if can_stream {
+        return Ok(Engine::Streaming);
+    }
+    if options.memory_budget_bytes.is_none() {
+        return Ok(Engine::OverBudget(format!(
+            "The data takes about {} MB in memory, more than half the memory free; export it to CSV or Parquet so it can be streamed",
+            estimated / (1024 * 1024)
+        )));
+    }
+    
+    Err(DataError::MemoryBudgetExceeded {
+        estimated_mb: estimated / (1024 * 1024),
+        budget_mb: budget / (1024 * 1024),
+        suggestions: vec![
+            "Export the data to CSV or Parquet so it can be streamed.".to_string(),
+            "Raise the memory budget in the settings.".to_string(),
+        ],
+    })
+}
+
+// Draft pivot over the first rows of the file that gives up once its time budget is spent
+pub fn preview_pivot(lf: LazyFrame, mut request: PivotRequest, options: &PreviewOptions) -> Result<PivotResult, DataError> {
+    let max_rows = options.max_rows.unwrap_or(DEFAULT_PREVIEW_ROWS);
+    let budget = Duration::from_millis(options.time_budget_ms.unwrap_or(DEFAULT_PREVIEW_BUDGET_MS));
+    let cancel = Arc::new(AtomicBool::new(false));
+    request.set_cancel_flag(cancel.clone());
+    
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let result = compute_pivot_from(lf.limit(max_rows as IdxSize), &request);
+        let result = pivot_response(result);
+        // The receiver is gone if the budget ran out first
+        let _ = sender.send(result);
+    });
+    
+    receiver.recv_timeout(budget).unwrap_or_else(|_| {
+        // Stops the abandoned preview at its next stage rather than letting it run on
+        cancel.store(true, Ordering::Relaxed);
+        Err(DataError::ProcessingError(format!(
+            "Preview did not finish within {} ms",
+            budget.as_millis()
+        )))
+    })
+}
+
+fn compute_pivot_from(mut lf: LazyFrame, request: &PivotRequest) -> Result<PivotFrame, DataError> {
+    let transposed;
+    let request = if request.transpose {
+        transposed = request.transposed();
+        &transposed
+    } else {
+        request
+    };
+    
+    // Bins that depend on the data are fixed from the unfiltered rows, so they
+    // don't move as filters change
+    request.report(PivotStage::Reading, 0);
+    let started = Instant::now();
+    lf = project_columns(lf, request)?;
+    if let Some(sample) = &request.sample {
+        lf = sample_rows(lf, sample)?;
+    }
+    let (derived, bins) = derived_fields(lf, request)?;
+    lf = derived;
+    check_columns_exist(&mut lf, request)?;
+    let read_ms = started.elapsed().as_millis() as u64;
+    let resolved;
+    let request = match bins {
+        Some(bins) => {
+            resolved = PivotRequest {
+                numeric_bins: Some(bins),
+                ..request.clone()
+            };
+            &resolved
+        },
+        None => request,
+    };
+    
+    request.check_cancelled()?;
+    request.report(PivotStage::Filtering, 10);
+    let started = Instant::now();
+    let (filtered, warnings) = apply_filters(lf, request)?;
+    lf = filtered;
+    
+    let labelled;
+    let request = match &request.null_groups {
+        Some(NullGroups::Exclude) => {
+            for field in request.rows.iter().chain(&request.columns) {
+                lf = lf.filter(col(field).is_not_null());
+            }
+            request
+        },
+        Some(NullGroups::Label(label)) => {
+            let schema = lf.schema().map_err(|e| DataError::ProcessingError(e.to_string()))?;
+            let mut resolved_dtypes = request.resolved_dtypes.clone();
+            for field in request.rows.iter().chain(&request.columns) {
+                match schema.get(field) {
+                    Some(DataType::String) | None => {},
+                    Some(dtype) => {
+                        resolved_dtypes.insert(field.clone(), dtype.clone());
+                    },
+                }
+                lf = lf.with_column(col(field).cast(DataType::String).fill_null(lit(label.clone())));
+            }
+            labelled = PivotRequest {
+                resolved_dtypes,
+                ..request.clone()
+            };
+            &labelled
+        },
+        None => request,
+    };
+    
+    // The top groups are picked from the filtered rows and listed largest first
+    let ranked;
+    let request = match (&request.top_rows, request.rows.first()) {
+        (Some(top), Some(field)) => {
+            let (top_lf, order) = keep_top_rows(lf, request, top)?;
+            lf = top_lf;
+            let mut resolved_orders = request.resolved_orders.clone();
+            resolved_orders.insert(field.clone(), order);
+            ranked = PivotRequest {
+                resolved_orders,
+                ..request.clone()
+            };
+            &ranked
+        },
+        _ => request,
+    };
+    
+    // Selected cells are matched on their labels as shown, "Other" included
+    if let Some(slices) = &request.slice_filter {
+        lf = apply_slice_filter(lf, request, slices)?;
+    }
+    
+    if let Some(conditions) = &request.measure_filters {
+        request.check_cancelled()?;
+        lf = apply_measure_filters(lf, request, conditions)?;
+    }
+    let filter_ms = started.elapsed().as_millis() as u64;
+    
+    request.check_cancelled()?;
+    let check_width = !request.confirm_wide && !request.columns.is_empty();
+    if check_width || request.result_budget.is_some() {
+        let counts = count_labels(&lf, request)?;
+        if check_width {
+            check_pivot_width(request, counts.columns)?;
+        }
+        if let Some(budget) = request.result_budget {
+            check_result_size(&lf, request, &counts, budget)?;
+        }
+    }
+    
+    request.report(PivotStage::Aggregating, 20);
+    let mut frame = pivot_levels(lf, request)?;
+    request.report(PivotStage::Reshaping, 85);
+    if let Some(conditions) = &request.result_filters {
+        frame = filter_result_rows(frame, conditions)?;
+    }
+    frame.warnings = warnings;
+    frame.sample = request.sample.clone();
+    frame.stats = PivotStats {
+        read_ms,
+        filter_ms,
+        input_rows: if request.sample.is_some() { None } else { footer_rows(&request.data_path) },
+        ..frame.stats
+    };
+    Ok(frame)
+}
+
+// Keeps the result rows, subtotals included, whose cells pass all `conditions`
+fn filter_result_rows(frame: PivotFrame, conditions: &[FilterCondition]) -> Result<PivotFrame, DataError> {
+    const KEEP: &str = "__keep";
+    
+    let mut keep = lit(true);
+    for condition in conditions {
+        if !frame.column_keys.contains(&condition.column) && !frame.row_headers.contains(&condition.column) {
+            return Err(DataError::ProcessingError(format!(
+                "Result filter column '{}' is not in the pivot",
+                condition.column
+            )));
+        }
+        keep = keep.and(build_filter_expr(condition)?);
+    }
+    
+    let mask = frame.df.clone()
+        .lazy()
+        .select([keep.fill_null(lit(false)).alias(KEEP)])
+        .collect()
+        .and_then(|df| df.column(KEEP)?.bool().cloned())
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    let kept = |tags: Vec<usize>| -> Vec<usize> {
+        tags.into_iter()
+            .zip(mask.into_no_null_iter())
+            .filter_map(|(tag, keep)| keep.then_some(tag))
+            .collect()
+    };
+    
+    Ok(PivotFrame {
+        df: frame.df.filter(&mask).map_err(|e| DataError::ProcessingError(e.to_string()))?,
+        row_depths: kept(frame.row_depths),
+        grouping_sets: kept(frame.grouping_sets),
+        ..frame
+    })
+}
+
+// The source rows deduplicated and with the date groupings and numeric bins
+// added, along with the bins resolved to fixed edges
+fn derived_fields(
+    mut lf: LazyFrame,
+    request: &PivotRequest,
+) -> Result<(LazyFrame, Option<Vec<NumericBinning>>), DataError> {
+    if let Some(keys) = &request.distinct_on {
+        let subset = if keys.is_empty() { None } else { Some(keys.clone()) };
+        lf = lf.unique_stable(subset, UniqueKeepStrategy::First);
+    }
+    
+    if let Some(groupings) = &request.date_groupings {
+        let exprs = groupings.iter().map(DateGrouping::to_expr).collect::<Result<Vec<Expr>, _>>()?;
+        lf = lf.with_columns(exprs);
+    }
+    
+    let Some(bins) = &request.numeric_bins else {
+        return Ok((lf, None));
+    };
+    let bins = bins.iter()
+        .map(|binning| binning.resolve(&lf))
+        .collect::<Result<Vec<_>, _>>()?;
+    lf = lf.with_columns(bins.iter().map(NumericBinning::to_expr).collect::<Vec<Expr>>());
+    Ok((lf, Some(bins)))
+}
+
+// Only the columns the request reads, so a pivot over a few columns of a wide
+// file doesn't decode the rest. Names missing from the data are left to
+// check_columns_exist; requests that can read any column keep them all.
+fn project_columns(mut lf: LazyFrame, request: &PivotRequest) -> Result<LazyFrame, DataError> {
+    let Some(referenced) = request.referenced_columns() else {
+        return Ok(lf);
+    };
+    let schema = lf.schema().map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    if referenced.len() >= schema.len() {
+        return Ok(lf);
+    }
+    
+    let columns: Vec<Expr> = schema.iter_names()
+        .filter(|name| referenced.contains(name.as_str()))
+        .map(|name| col(name))
+        .collect();
+    tracing::debug!(read = columns.len(), of = schema.len(), "Projected pivot columns");
+    Ok(lf.select(columns))
+}
+
+// Fails with the first field the request names that isn't in the data, derived
+// fields included, before Polars fails on it with a less helpful message
+fn check_columns_exist(lf: &mut LazyFrame, request: &PivotRequest) -> Result<(), DataError> {
+    let schema = lf.schema().map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    let measures = request.values.iter()
+        .filter(|v| !matches!(v.aggregation, AggregationType::Custom(_) | AggregationType::Calculated(_)))
+        .map(|v| &v.field);
+    let filters = request.filters.iter()
+        .flatten()
+        .filter(|filter| !matches!(filter.operator, FilterOperator::Expression(_)))
+        .map(|filter| &filter.column);
+    
+    match request.rows.iter().chain(&request.columns).chain(measures).chain(filters).find(|field| schema.get(field).is_none()) {
+        Some(field) => Err(DataError::ColumnNotFound { column: field.clone() }),
+        None => Ok(()),
+    }
+}
+
+// Applies the request's filters in order, with a warning for each filter whose
+// value had to be converted to its column's type
+fn apply_filters(mut lf: LazyFrame, request: &PivotRequest) -> Result<(LazyFrame, Vec<String>), DataError> {
+    let mut warnings = Vec::new();
+    let Some(filters) = &request.filters else {
+        return Ok((lf, warnings));
+    };
+    
+    let schema = lf.schema().map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    for filter in filters {
+        let filter = match schema.get(&filter.column).and_then(|dtype| coerce_filter(filter, dtype)) {
+            Some((coerced, warning)) => {
+                warnings.push(warning);
+                coerced
+            },
+            None => filter.clone(),
+        };
+        lf = apply_filter(lf, &filter)?;
+    }
+    Ok((lf, warnings))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PivotPlan {
+    // Query plan of the main aggregation as Polars runs it, and as written
+    pub optimized: String,
+    pub unoptimized: String,
+    // Whether the filters reached the scan, so rows are skipped while reading
+    pub predicate_pushdown: bool,
+    // Whether only the columns the pivot uses are read
+    pub projection_pushdown: bool,
+}
+
+// Query plan of the request's aggregation on the innermost row level. Subtotals,
+// totals and the reshape run more queries of the same shape; top rows and
+// measure filters are left out.
+pub fn explain_pivot(mut lf: LazyFrame, request: &PivotRequest) -> Result<PivotPlan, DataError> {
+    let transposed;
+    let request = if request.transpose {
+        transposed = request.transposed();
+        &transposed
+    } else {
+        request
+    };
+    
+    lf = project_columns(lf, request)?;
+    if let Some(sample) = &request.sample {
+        lf = sample_rows(lf, sample)?;
+    }
+    let (derived, _) = derived_fields(lf, request)?;
+    let (filtered, _) = apply_filters(derived, request)?;
+    let plan = aggregate_plan(filtered, request, &request.rows)?;
+    
+    let optimized = plan.explain(true).map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    let unoptimized = plan.explain(false).map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    // Scans list the columns they read ("PROJECT 3/12 COLUMNS", "*" for all)
+    // and the predicate applied while reading ("SELECTION: None" for none)
+    let predicate_pushdown = optimized.lines().any(|line| {
+        line.trim_start().starts_with("SELECTION:") && !line.contains("SELECTION: None")
+    });
+    let projection_pushdown = optimized.lines().any(|line| {
+        line.trim_start().starts_with("PROJECT ") && !line.contains("PROJECT */")
+    });
+    
+    Ok(PivotPlan {
+        optimized,
+        unoptimized,
+        predicate_pushdown,
+        projection_pushdown,
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FilterCount {
+    // Rows left by the filters
+    pub matching: u64,
+    // Rows before filtering
+    pub total: u64,
+}
+
+// How many rows the request's filters keep, without aggregating anything
+pub fn filtered_row_count(lf: LazyFrame, request: &PivotRequest) -> Result<FilterCount, DataError> {
+    let (lf, _) = derived_fields(lf, request)?;
+    let (filtered, _) = apply_filters(lf.clone(), request)?;
+    
+    let count = |lf: LazyFrame| -> Result<u64, DataError> {
+        lf.select([len()])
+            .collect()
+            .and_then(|df| df.column("len")?.u32().map(|len| len.get(0).unwrap_or(0) as u64))
+            .map_err(|e| DataError::ProcessingError(e.to_string()))
+    };
+    Ok(FilterCount {
+        matching: count(filtered)?,
+        total: count(lf)?,
+    })
+}
+
+// The pivot of the prepared rows at the levels the request asks for
+fn pivot_levels(mut lf: LazyFrame, request: &PivotRequest) -> Result<PivotFrame, DataError> {
+    if let Some(path) = &request.expand_path {
+        // Only the branch's rows, grouped one level below it
+        for (field, label) in request.rows.iter().zip(path) {
+            lf = lf.filter(col(field).cast(DataType::String).eq(lit(label.clone())));
+        }
+        return level_frame(lf, request, &request.rows[..=path.len()]);
+    }
+    
+    if let Some(sets) = &request.grouping_sets {
+        return grouping_sets_frame(lf, request, sets);
+    }
+    
+    let depth = request.rows.len();
+    let mut frame = level_frame(lf.clone(), request, &request.rows)?;
+    
+    if request.show_subtotals && depth > 1 {
+        let mut subtotals = Vec::with_capacity(depth - 1);
+        for outer in 1..depth {
+            request.report(PivotStage::Aggregating, 20 + (60 * outer / depth) as u8);
+            let level = level_frame(lf.clone(), request, &request.rows[..outer])?;
+            frame.stats.add_stages(&level.stats);
+            subtotals.push((outer, level.df));
+        }
+        frame = add_subtotals(frame, subtotals, request)?;
+    }
+    
+    if request.show_row_totals && depth > 0 {
+        let totals = level_frame(lf, request, &[])?;
+        frame.stats.add_stages(&totals.stats);
+        frame.grand_total = Some(totals.df.select(frame.df.get_column_names())
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?);
+    }
+    
+    Ok(frame)
+}
+
+// Keeps the rows of the groups whose aggregated measures pass `conditions`
+fn apply_measure_filters(
+    lf: LazyFrame,
+    request: &PivotRequest,
+    conditions: &[FilterCondition],
+) -> Result<LazyFrame, DataError> {
+    let keys = if request.rows.is_empty() { &request.columns } else { &request.rows };
+    if keys.is_empty() {
+        return Err(DataError::ProcessingError(
+            "Measure filters need a row or column field to group on".to_string(),
+        ));
+    }
+    
+    // The raw aggregates of each group, without display modes
+    let grouping = PivotRequest {
+        rows: keys.clone(),
+        columns: Vec::new(),
+        values: request.values.iter()
+            .map(|v| ValueWithAggregation { display_mode: None, ..v.clone() })
+            .collect(),
+        collapse: None,
+        densify: false,
+        ..request.clone()
+    };
+    let mut groups = aggregate(lf.clone(), &grouping, &grouping.rows)?.lazy();
+    for condition in conditions {
+        groups = groups.filter(build_filter_expr(condition)?);
+    }
+    
+    // Group keys are unique, so an inner join only drops rows
+    let key_exprs: Vec<Expr> = keys.iter().map(|field| col(field)).collect();
+    Ok(lf.join(
+        groups.select(key_exprs.clone()),
+        key_exprs.clone(),
+        key_exprs,
+        JoinArgs {
+            join_nulls: true,
+            ..JoinArgs::new(JoinType::Inner)
+        },
+    ))
+}
+
+// Turns the selected cells into conditions on the row and column fields,
+// matching labels the way they are shown, nulls as "null"
+fn apply_slice_filter(lf: LazyFrame, request: &PivotRequest, slices: &SliceFilter) -> Result<LazyFrame, DataError> {
+    let mut selected = lit(false);
+    for cell in &slices.cells {
+        if cell.row.len() > request.rows.len() || cell.column.len() > request.columns.len() {
+            return Err(DataError::ProcessingError(format!(
+                "Selection {:?} / {:?} has more labels than the pivot has fields",
+                cell.row, cell.column
+            )));
+        }
+        
+        let labels = request.rows.iter().zip(&cell.row)
+            .chain(request.columns.iter().zip(&cell.column));
+        let mut matches = lit(true);
+        for (field, label) in labels {
+            let shown = col(field).cast(DataType::String).fill_null(lit("null"));
+            matches = matches.and(shown.eq(lit(label.clone())));
+        }
+        selected = selected.or(matches);
+    }
+    
+    Ok(lf.filter(if slices.keep { selected } else { selected.not() }))
+}
+
+// Distinct label combinations of the row fields and of the column fields
+struct LabelCounts {
+    // Estimated with HyperLogLog, as it only sizes the result
+    rows: usize,
+    columns: usize,
+}
+
+// Counts the label combinations in one pass over the data
+fn count_labels(lf: &LazyFrame, request: &PivotRequest) -> Result<LabelCounts, DataError> {
+    let combination = |fields: &[String]| {
+        let labels: Vec<Expr> = fields.iter()
+            .map(|field| col(field).cast(DataType::String).fill_null(lit("\u{0}")))
+            .collect();
+        concat_str(labels, "\u{1f}", false)
+    };
+    let mut exprs = Vec::new();
+    if !request.rows.is_empty() {
+        exprs.push(combination(&request.rows).approx_n_unique().cast(DataType::UInt64).alias("rows"));
+    }
+    if request.densify {
+        // Densified, every combination of the labels of the column fields gets a column
+        for (i, field) in request.columns.iter().enumerate() {
+            exprs.push(col(field).n_unique().cast(DataType::UInt64).alias(&format!("columns_{}", i)));
+        }
+    } else if !request.columns.is_empty() {
+        exprs.push(combination(&request.columns).n_unique().cast(DataType::UInt64).alias("columns"));
+    }
+    if exprs.is_empty() {
+        return Ok(LabelCounts { rows: 1, columns: 1 });
+    }
+    
+    let counts = lf.clone()
+        .select(exprs)
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    let count = |name: &str| -> Result<usize, DataError> {
+        match counts.column(name) {
+            Ok(column) => column.u64()
+                .map(|values| values.get(0).unwrap_or(0) as usize)
+                .map_err(|e| DataError::ProcessingError(e.to_string())),
+            Err(_) => Ok(1),
+        }
+    };
+    let columns = if request.densify {
+        (0..request.columns.len()).try_fold(1usize, |product, i| {
+            Ok::<usize, DataError>(product.saturating_mul(count(&format!("columns_{}", i))?))
+        })?
+    } else {
+        count("columns")?
+    };
+    Ok(LabelCounts {
+        rows: count("rows")?,
+        columns,
+    })
+}
+
+// Bytes a value of the type takes in a result, guessing for text
+fn dtype_width(dtype: &DataType) -> u64 {
+    match dtype {
+        DataType::Boolean | DataType::Int8 | DataType::UInt8 => 1,
+        DataType::Int16 | DataType::UInt16 => 2,
+        DataType::Int32 | DataType::UInt32 | DataType::Float32 | DataType::Date => 4,
+        DataType::String => 32,
+        _ => 8,
+    }
+}
+
+// Fails before any aggregation when the pivoted result, estimated as its
+// approximate rows times the width of its columns, would take more than `budget` bytes
+fn check_result_size(lf: &LazyFrame, request: &PivotRequest, counts: &LabelCounts, budget: u64) -> Result<(), DataError> {
+    let schema = lf.schema().map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    let (rows, combinations) = (counts.rows, counts.columns);
+    
+    let label_width: u64 = request.rows.iter()
+        .map(|field| schema.get(field).map_or(8, dtype_width))
+        .sum();
+    // Measures are mostly 8-byte numbers
+    let measure_width = 8 * (combinations * request.values.len()) as u64;
+    let estimated = rows as u64 * (label_width + measure_width);
+    
+    if estimated > budget {
+        return Err(DataError::ResultTooLarge {
+            estimated_mb: estimated / (1024 * 1024),
+            limit_mb: budget / (1024 * 1024),
+        });
+    }
+    Ok(())
+}
+
+// Fails before any aggregation when the distinct column label combinations
+// would spread the measures over more than MAX_PIVOT_COLUMNS columns
+fn check_pivot_width(request: &PivotRequest, combinations: usize) -> Result<(), DataError> {
+    let columns = combinations * request.values.len();
+    if columns > MAX_PIVOT_COLUMNS {
+        return Err(DataError::TooManyColumns {
+            columns,
+            limit: MAX_PIVOT_COLUMNS,
+        });
+    }
+    Ok(())
+}
+
+// Marks the rows of the top groups while keep_top_rows relabels the others
+const TOP_ROW_COLUMN: &str = "__top_row";
+
+// Relabel every group of the outermost row field outside the top `count` by the
+// ranking measure as the "Other" group, so the aggregation folds them together.
+// Returns the relabelled rows and the label order, largest group first. The
+// field is only turned into text when some groups are folded, and the "Other"
+// label is numbered, e.g. "Other (2)", when a top group is already called that.
+fn keep_top_rows(lf: LazyFrame, request: &PivotRequest, top: &TopRows) -> Result<(LazyFrame, Vec<String>), DataError> {
+    let field = &request.rows[0];
+    let measure = request.values.get(top.measure.unwrap_or(0)).ok_or_else(|| {
+        DataError::ProcessingError("Top rows are ranked by a measure that isn't in the pivot".to_string())
+    })?;
+    
+    // Rank on the raw aggregates of the outermost field alone
+    let ranking_request = PivotRequest {
+        rows: vec![field.clone()],
+        columns: Vec::new(),
+        values: request.values.iter()
+            .map(|v| ValueWithAggregation { display_mode: None, ..v.clone() })
+            .collect(),
+        collapse: None,
+        ..request.clone()
+    };
+    let groups = aggregate(lf.clone(), &ranking_request, &ranking_request.rows)?
+        .lazy()
+        .filter(col(field).is_not_null())
+        .select([col(field), col(&measure.output_name())])
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    let ranked = sort_by_measure(groups.clone().lazy(), &measure.output_name(), &[field.clone()])
+        .limit(top.count as IdxSize)
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    
+    let labels: Vec<String> = ranked.column(field)
+        .and_then(|labels| labels.cast(&DataType::String))
+        .and_then(|labels| Ok(labels.str()?.into_no_null_iter().map(|l| l.to_string()).collect()))
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    if groups.height() <= labels.len() {
+        return Ok((lf, labels));
+    }
+    
+    let requested = top.other_label.clone().unwrap_or_else(|| OTHER_LABEL.to_string());
+    let other_label = (1..)
+        .map(|n| if n == 1 { requested.clone() } else { format!("{} ({})", requested, n) })
+        .find(|label| !labels.contains(label))
+        .unwrap_or(requested);
+    
+    let top_groups = ranked.lazy().select([col(field), lit(true).alias(TOP_ROW_COLUMN)]);
+    let lf = lf
+        .join(top_groups, [col(field)], [col(field)], JoinArgs::new(JoinType::Left))
+        .with_column(
+            when(col(field).is_null().or(col(TOP_ROW_COLUMN).is_not_null()))
+                .then(col(field).cast(DataType::String))
+                .otherwise(lit(other_label.clone()))
+                .alias(field),
+        )
+        .drop([TOP_ROW_COLUMN]);
+    
+    let mut order = labels;
+    order.push(other_label);
+    Ok((lf, order))
+}
+
+// The pivot of one level of the row hierarchy, grouped on the `grouped` row
+// fields with the others left null. Totals are aggregated from the filtered rows
+// again rather than summed from the cells, so Mean, Median, Count Distinct, ...
+// come out right.
+fn level_frame(lf: LazyFrame, request: &PivotRequest, grouped: &[String]) -> Result<PivotFrame, DataError> {
+    request.check_cancelled()?;
+    let started = Instant::now();
+    let agg_df = aggregate(lf.clone(), request, grouped)?;
+    let aggregate_ms = started.elapsed().as_millis() as u64;
+    request.check_cancelled()?;
+    let started = Instant::now();
+    let mut frame = reshape(agg_df, request)?;
+    frame.stats.aggregate_ms = aggregate_ms;
+    frame.stats.reshape_ms = started.elapsed().as_millis() as u64;
+    
+    if !request.show_column_totals || request.columns.is_empty() {
+        return Ok(frame);
+    }
+    
+    request.check_cancelled()?;
+    let without_columns = PivotRequest {
+        columns: Vec::new(),
+        ..request.clone()
+    };
+    let started = Instant::now();
+    let totals = aggregate(lf, &without_columns, grouped)?;
+    frame.stats.aggregate_ms += started.elapsed().as_millis() as u64;
+    add_column_totals(frame, totals, request)
+}
+
+const GROUPING_SET_COLUMN: &str = "__grouping_set";
+
+// One block of rows per grouping set, in the order the sets are listed. The
+// filtered rows are read once and shared by all the sets.
+fn grouping_sets_frame(lf: LazyFrame, request: &PivotRequest, sets: &[Vec<String>]) -> Result<PivotFrame, DataError> {
+    if sets.is_empty() {
+        return Err(DataError::ProcessingError("Grouping sets need at least one set".to_string()));
+    }
+    if let Some(field) = sets.iter().flatten().find(|field| !request.rows.contains(field)) {
+        return Err(DataError::ProcessingError(format!(
+            "Grouping set field '{}' is not a row field",
+            field
+        )));
+    }
+    
+    // The filtered rows are read once for all the sets, unless they don't fit in memory
+    let lf = if request.streaming {
+        lf
+    } else {
+        lf.collect()
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?
+            .lazy()
+    };
+    
+    let mut frames = Vec::with_capacity(sets.len());
+    let mut layout = None;
+    let mut stats = PivotStats::default();
+    for (index, set) in sets.iter().enumerate() {
+        let frame = level_frame(lf.clone(), request, set)?;
+        stats.add_stages(&frame.stats);
+        frames.push(frame.df.lazy().with_column(lit(index as u32).alias(GROUPING_SET_COLUMN)));
+        layout.get_or_insert((frame.column_headers, frame.column_keys, frame.total_column_keys, frame.column_formats));
+    }
+    let (column_headers, column_keys, total_column_keys, column_formats) =
+        layout.expect("there is at least one grouping set");
+    
+    let args = UnionArgs {
+        to_supertypes: true,
+        ..Default::default()
+    };
+    let mut df = concat_lf_diagonal(frames, args)
+        .and_then(|lf| lf.collect())
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    
+    let grouping_sets = df
+        .drop_in_place(GROUPING_SET_COLUMN)
+        .and_then(|sets| sets.u32().map(|sets| sets.into_no_null_iter().map(|s| s as usize).collect::<Vec<usize>>()))
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    
+    Ok(PivotFrame {
+        df,
+        column_headers,
+        row_headers: request.rows.clone(),
+        column_keys,
+        total_column_keys,
+        grand_total: None,
+        row_depths: Vec::new(),
+        grouping_sets,
+        grouping_set_depths: sets.iter().map(|set| set.len()).collect(),
+        column_formats,
+        warnings: Vec::new(),
+        sample: None,
+        stats,
+    })
+}
+
+const ROW_DEPTH_COLUMN: &str = "__row_depth";
+
+// Interleave the subtotal rows with the detail rows, each one following the
+// last row of its group, and record how many row fields every row is grouped on
+fn add_subtotals(
+    frame: PivotFrame,
+    subtotals: Vec<(usize, DataFrame)>,
+    request: &PivotRequest,
+) -> Result<PivotFrame, DataError> {
+    let depth = request.rows.len();
+    let mut frames = vec![frame.df.lazy().with_column(lit(depth as u32).alias(ROW_DEPTH_COLUMN))];
+    for (outer, df) in subtotals {
+        frames.push(df.lazy().with_column(lit(outer as u32).alias(ROW_DEPTH_COLUMN)));
+    }
+    
+    let args = UnionArgs {
+        to_supertypes: true,
+        ..Default::default()
+    };
+    
+    // Null labels sort last, which puts a subtotal after its group; a deeper
+    // subtotal comes before the one of its enclosing group
+    let mut by: Vec<Expr> = request.rows.iter().flat_map(|field| request.label_sort_keys(field)).collect();
+    let mut descending = vec![false; by.len()];
+    by.push(col(ROW_DEPTH_COLUMN));
+    descending.push(true);
+    
+    let mut df = concat_lf_diagonal(frames, args)
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?
+        .sort_by_exprs(
+            by,
+            SortMultipleOptions::default()
+                .with_order_descending_multi(descending)
+                .with_nulls_last(true)
+                .with_maintain_order(true),
+        )
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    
+    let row_depths = df
+        .drop_in_place(ROW_DEPTH_COLUMN)
+        .and_then(|depths| depths.u32().map(|depths| depths.into_no_null_iter().map(|d| d as usize).collect::<Vec<usize>>()))
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
     
-    Ok(lf.filter(filter_expr))
+    Ok(PivotFrame {
+        df,
+        row_depths,
+        ..frame
+    })
 }
 
-pub fn generate_pivot(request: PivotRequest) -> Result<PivotResult, DataError> {
-    // Read the data as a LazyFrame
-    let mut lf = read_data(&request.data_path)?;
+// Group the filtered rows and compute every measure. Only the first `depth` row
+// fields are grouped on; the inner ones are left null, so `depth` 0 aggregates
+// each column group over all rows.
+fn aggregate(lf: LazyFrame, request: &PivotRequest, grouped: &[String]) -> Result<DataFrame, DataError> {
+    let agg_df = aggregate_plan(lf, request, grouped)?
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
     
-    // Apply filters if they exist
-    if let Some(filters) = &request.filters {
-        for filter in filters {
-            lf = apply_filter(lf, filter)?;
-        }
+    let mut group_cols = request.rows.clone();
+    group_cols.extend(request.columns.clone());
+    let agg_df = if request.densify {
+        densify(agg_df, request)?
+    } else {
+        agg_df
+    };
+    // Groups that keep their order of appearance are only sorted on fixed orders
+    if request.maintain_order {
+        group_cols.retain(|field| request.label_order(field).is_some());
     }
+    let agg_df = sort_by_labels(agg_df, &group_cols, request)?;
+    let agg_df = apply_display_modes(agg_df, request)?;
     
+    tracing::debug!(shape = ?agg_df.shape(), grouped = ?grouped, "Aggregated");
+    Ok(agg_df)
+}
+
+// The group by behind `aggregate`, before it is collected
+fn aggregate_plan(mut lf: LazyFrame, request: &PivotRequest, grouped: &[String]) -> Result<LazyFrame, DataError> {
     // Combine rows and columns for groupby
     let mut group_cols = request.rows.clone();
     group_cols.extend(request.columns.clone());
     
     // Create groupby expressions and aggregation expressions
-    let group_exprs: Vec<Expr> = group_cols.iter().map(|s| col(s)).collect();
-    let agg_exprs: Vec<Expr> = request.values
+    let mut group_exprs: Vec<Expr> = group_cols.iter().map(|s| col(s)).collect();
+    if let Some(collapse) = &request.collapse {
+        group_exprs = collapse_group_exprs(group_exprs, request, collapse);
+    }
+    if request.rows.iter().any(|field| !grouped.contains(field)) {
+        // Nulls of the field's own type, so every level stacks into the same columns
+        let schema = lf.schema().map_err(|e| DataError::ProcessingError(e.to_string()))?;
+        for (field, expr) in request.rows.iter().zip(group_exprs.iter_mut()).filter(|(field, _)| !grouped.contains(field)) {
+            let dtype = schema.get(field).cloned().unwrap_or(DataType::String);
+            *expr = lit(NULL).cast(dtype).alias(field);
+        }
+    }
+    let (calculated, aggregated): (Vec<&ValueWithAggregation>, Vec<&ValueWithAggregation>) = request.values
         .iter()
-        .map(|val_with_agg| {
-            let field_col = col(&val_with_agg.field);
-            let agg_name = format!(
-                "{}_{}",
-                match val_with_agg.aggregation {
-                    AggregationType::Sum => "sum",
-                    AggregationType::Mean => "mean",
-                    AggregationType::Count => "count",
-                    AggregationType::Min => "min",
-                    AggregationType::Max => "max",
-                    AggregationType::First => "first",
-                    AggregationType::Last => "last",
-                    AggregationType::Median => "median",
-                    AggregationType::Std => "std",
-                    AggregationType::Var => "var",
-                },
-                val_with_agg.field
-            );
-            
-            match val_with_agg.aggregation {
-                AggregationType::Sum => field_col.sum().alias(&agg_name),
-                AggregationType::Mean => field_col.mean().alias(&agg_name),
-                AggregationType::Count => field_col.count().alias(&agg_name),
-                AggregationType::Min => field_col.min().alias(&agg_name),
-                AggregationType::Max => field_col.max().alias(&agg_name),
-                AggregationType::First => field_col.first().alias(&agg_name),
-                AggregationType::Last => field_col.last().alias(&agg_name),
-                AggregationType::Median => field_col.median().alias(&agg_name),
-                AggregationType::Std => field_col.std(1).alias(&agg_name),
-                AggregationType::Var => field_col.var(1).alias(&agg_name),
-            }
-        })
-        .collect();
+        .partition(|val_with_agg| matches!(val_with_agg.aggregation, AggregationType::Calculated(_)));
+    let agg_exprs: Vec<Expr> = aggregated
+        .iter()
+        .map(|val_with_agg| val_with_agg.to_expr())
+        .collect::<Result<_, _>>()?;
     
     // Execute the query to get the initial aggregated DataFrame
-    let agg_df = lf
-        .group_by(group_exprs)
-        .agg(agg_exprs)
-        .collect()
-        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
-    
-    println!("Aggregated DataFrame: {:?}", agg_df);
+    let mut agg_lf = if group_exprs.is_empty() {
+        lf.select(agg_exprs)
+    } else if request.maintain_order {
+        lf.group_by_stable(group_exprs).agg(agg_exprs)
+    } else {
+        lf.group_by(group_exprs).agg(agg_exprs)
+    };
     
+    // Calculated measures see the aggregates, so ratios come from the group totals.
+    // Each one can use those listed before it.
+    for val_with_agg in calculated {
+        agg_lf = agg_lf.with_column(val_with_agg.to_expr()?);
+    }
+    Ok(agg_lf)
+}
+
+// Spread the aggregated measures over the column fields
+fn reshape(agg_df: DataFrame, request: &PivotRequest) -> Result<PivotFrame, DataError> {
     // Transform the data using the actual pivot functionality
     if request.columns.is_empty() {
         // No need to pivot if there are no column fields
-        let data = df_to_json_rows(agg_df).map_err(|e| DataError::ProcessingError(e.to_string()))?;
+        let compares_columns = request.values.iter()
+            .any(|v| v.display_mode.as_ref().map(DisplayMode::compares_columns).unwrap_or(false));
+        if compares_columns {
+            return Err(DataError::ProcessingError(
+                "Comparing with other columns needs at least one column field".to_string(),
+            ));
+        }
         
         let value_headers = request.values.iter()
-            .map(|v| format!("{}_{}", 
-                match v.aggregation {
-                    AggregationType::Sum => "sum",
-                    AggregationType::Mean => "mean",
-                    AggregationType::Count => "count",
-                    AggregationType::Min => "min",
-                    AggregationType::Max => "max",
-                    AggregationType::First => "first", 
-                    AggregationType::Last => "last",
-                    AggregationType::Median => "median",
-                    AggregationType::Std => "std",
-                    AggregationType::Var => "var",
-                }, 
-                v.field
-            ))
+            .map(|v| v.output_name())
             .collect::<Vec<String>>();
         
-        Ok(PivotResult {
-            data,
-            column_headers: vec![value_headers],
-            row_headers: request.rows,
+        Ok(PivotFrame {
+            df: agg_df,
+            column_headers: vec![value_headers.clone()],
+            row_headers: request.rows.clone(),
+            column_keys: value_headers,
+            total_column_keys: Vec::new(),
+            grand_total: None,
+            row_depths: Vec::new(),
+            grouping_sets: Vec::new(),
+            grouping_set_depths: Vec::new(),
+            column_formats: request.values.iter().map(|v| v.format.clone()).collect(),
+            warnings: Vec::new(),
+            sample: None,
+            stats: PivotStats::default(),
         })
     } else {
-        // We need to pivot the DataFrame
-        let val_with_agg = &request.values[0]; // Using just the first value for simplicity
-        let agg_col_name = format!(
-            "{}_{}",
-            match val_with_agg.aggregation {
-                AggregationType::Sum => "sum",
-                AggregationType::Mean => "mean",
-                AggregationType::Count => "count",
-                AggregationType::Min => "min",
-                AggregationType::Max => "max",
-                AggregationType::First => "first",
-                AggregationType::Last => "last",
-                AggregationType::Median => "median",
-                AggregationType::Std => "std",
-                AggregationType::Var => "var",
-            },
-            val_with_agg.field
-        );
+        // Reshape the aggregated groups into one column per label combination and
+        // measure. The headers get a level per column field, with a measure level
+        // below them when there are several measures.
+        let multiple = request.values.len() > 1;
+        let row_exprs: Vec<Expr> = request.rows.iter().map(|s| col(s)).collect();
         
-        // Map our aggregation type to PivotAgg
-        let pivot_agg = match val_with_agg.aggregation {
-            AggregationType::Sum => PivotAgg::Sum,
-            AggregationType::Mean => PivotAgg::Mean,
-            AggregationType::Count => PivotAgg::Count,
-            AggregationType::Min => PivotAgg::Min,
-            AggregationType::Max => PivotAgg::Max,
-            AggregationType::First => PivotAgg::First,
-            AggregationType::Last => PivotAgg::Last,
-            AggregationType::Median => PivotAgg::Median,
-            // For Std and Var, use First since they don't have direct equivalents
-            AggregationType::Std => PivotAgg::First,
-            AggregationType::Var => PivotAgg::First,
-        };
-        
-        // REVERSED pivot parameters:
-        let pivoted = pivot(
-            &agg_df,
-            // Use columns (processing methods) as the index instead of rows
-            request.columns.iter().map(|s| s.as_str()).collect::<Vec<&str>>(), 
-            // Use rows (countries) as the columns instead of columns
-            Some(request.rows.iter().map(|s| s.as_str()).collect::<Vec<&str>>()), 
-            Some(vec![agg_col_name.as_str()]), // values
-            false, // maintain_order
-            Some(pivot_agg),
-            None,  // separator
-        )
-        .map_err(|e| DataError::ProcessingError(format!("Pivot error: {}", e)))?;
-        
-        println!("Pivoted DataFrame: {:?}", pivoted);
-        
-        // Extract column headers from the pivoted DataFrame
-        let all_columns = pivoted.get_column_names();
-        println!("All columns: {:?}", all_columns);
-        
-        // We know the row identifier column(s) from the request
-        let row_columns = request.rows.clone();
-        
-        // The remaining columns in the pivoted dataframe are the "value" columns
-        // These will typically be combinations of the column values
-        let value_columns: Vec<String> = all_columns.iter()
-            .filter(|&name| !row_columns.contains(&name.to_string()))
-            .map(|s| s.to_string())
+        // Pivoted columns come out in the order their labels first appear, so the
+        // groups are put in column order first and the row order restored after.
+        // Each group is pivoted on one key joining the labels of all column fields.
+        request.check_column_key_template()?;
+        let (column_keys_exprs, descending) = request.column_sort_exprs();
+        let label_exprs: Vec<Expr> = request.columns.iter()
+            .map(|field| col(field).cast(DataType::String).fill_null(lit("null")))
             .collect();
+        let by_columns = agg_df.clone()
+            .lazy()
+            .sort_by_exprs(
+                column_keys_exprs,
+                SortMultipleOptions::default()
+                    .with_order_descending_multi(descending)
+                    .with_nulls_last(true)
+                    .with_maintain_order(true),
+            )
+            .with_column(concat_str(label_exprs, request.column_separator(), false).alias(COLUMN_KEY_COLUMN))
+            .collect()
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?;
         
-        println!("Row columns: {:?}", row_columns);
-        println!("Value columns: {:?}", value_columns);
+        let combinations = column_combinations(&by_columns, &request.columns)?;
         
-        // Create column headers structure for frontend
-        let column_headers = vec![value_columns.clone()];
+        // One pivot moves every measure into place. Its columns are renamed by
+        // name rather than position, as their order isn't the column order.
+        let pivoted = pivot_measures(&by_columns, request)?;
+        let mut measure_keys = Vec::new();
+        let mut pivot_names = Vec::new();
+        for val_with_agg in &request.values {
+            let mut keys = Vec::with_capacity(combinations.len());
+            for (key, _) in &combinations {
+                let name = pivoted_column_name(request, val_with_agg, key);
+                if pivoted.get_column_index(&name).is_none() {
+                    return Err(DataError::ProcessingError(format!("Pivot produced no column {}", name)));
+                }
+                pivot_names.push(name);
+                keys.push(request.column_key(val_with_agg, key));
+            }
+            measure_keys.push((val_with_agg, keys));
+        }
+        let all_keys: Vec<String> = measure_keys.iter().flat_map(|(_, keys)| keys.clone()).collect();
         
-        // Now we need to convert the pivoted DataFrame to rows
-        let mut data = Vec::new();
+        let mut pivoted = pivoted.lazy().rename(pivot_names, all_keys);
+        for (val_with_agg, keys) in &measure_keys {
+            pivoted = apply_column_display_mode(pivoted, val_with_agg, keys)?;
+        }
+        let measure_keys: Vec<(String, Option<NumberFormat>, Vec<String>)> = measure_keys.into_iter()
+            .map(|(val_with_agg, keys)| (val_with_agg.output_name(), val_with_agg.format.clone(), keys))
+            .collect();
         
-        // Each row in the DataFrame represents one entry by row values
-        for i in 0..pivoted.height() {
-            let mut row_map = HashMap::new();
-            
-            // First, add the row identifier columns
-            for row_col in &row_columns {
-                if let Ok(col) = pivoted.column(row_col) {
-                    let value = match col.get(i) {
-                        Ok(AnyValue::String(s)) => serde_json::Value::String(s.to_string()),
-                        Ok(AnyValue::Int32(v)) => serde_json::Value::Number(serde_json::Number::from(v)),
-                        Ok(AnyValue::Int64(v)) => {
-                            if v > i64::pow(2, 53) || v < -i64::pow(2, 53) {
-                                serde_json::Value::String(v.to_string())
-                            } else {
-                                serde_json::Value::Number(serde_json::Number::from_f64(v as f64).unwrap())
-                            }
-                        },
-                        Ok(AnyValue::Float64(v)) => {
-                            if let Some(num) = serde_json::Number::from_f64(v) {
-                                serde_json::Value::Number(num)
-                            } else {
-                                serde_json::Value::Null
-                            }
-                        },
-                        _ => serde_json::Value::String(format!("{:?}", col.get(i))),
-                    };
-                    
-                    row_map.insert(row_col.clone(), value);
+        // Leaf columns run through the label combinations, with every measure
+        // under each one
+        let mut column_headers = vec![Vec::new(); request.columns.len() + multiple as usize];
+        let mut column_keys = Vec::new();
+        let mut column_formats = Vec::new();
+        for (position, (_, labels)) in combinations.iter().enumerate() {
+            for (name, format, keys) in &measure_keys {
+                column_keys.push(keys[position].clone());
+                column_formats.push(format.clone());
+                for (level, label) in labels.iter().enumerate() {
+                    column_headers[level].push(label.clone());
                 }
-            }
-            
-            // Then, add all value columns
-            for value_col in &value_columns {
-                if let Ok(col) = pivoted.column(value_col) {
-                    let value = match col.get(i) {
-                        Ok(AnyValue::Float64(v)) => {
-                            if let Some(num) = serde_json::Number::from_f64(v) {
-                                serde_json::Value::Number(num)
-                            } else {
-                                serde_json::Value::Null
-                            }
-                        },
-                        Ok(AnyValue::Int32(v)) => serde_json::Value::Number(serde_json::Number::from(v)),
-                        Ok(AnyValue::Int64(v)) => {
-                            if v > i64::pow(2, 53) || v < -i64::pow(2, 53) {
-                                serde_json::Value::String(v.to_string())
-                            } else {
-                                serde_json::Value::Number(serde_json::Number::from_f64(v as f64).unwrap())
-                            }
-                        },
-                        Ok(AnyValue::Null) => serde_json::Value::Null,
-                        _ => serde_json::Value::String(format!("{:?}", col.get(i))),
-                    };
-                    
-                    // Use the aggregation type from the request to form the key prefix
-                    let agg_prefix = match &request.values[0].aggregation {
-                        AggregationType::Sum => "sum",
-                        AggregationType::Mean => "mean",
-                        AggregationType::Count => "count",
-                        AggregationType::Min => "min",
-                        AggregationType::Max => "max",
-                        AggregationType::First => "first",
-                        AggregationType::Last => "last",
-                        AggregationType::Median => "median",
-                        AggregationType::Std => "std",
-                        AggregationType::Var => "var",
-                    };
-                    
-                    // When we have column features, the frontend is still expecting the
-                    // aggregation prefix in the key
-                    let key = format!("{}_{}", agg_prefix, value_col);
-                    row_map.insert(key, value);
+                if multiple {
+                    column_headers[labels.len()].push(name.clone());
                 }
             }
-            
-            data.push(row_map);
         }
         
-        println!("Final data (rows: {}): {:?}", data.len(), data);
+        let mut combined = pivoted;
+        if !request.rows.is_empty() {
+            let row_order = agg_df.lazy()
+                .select(row_exprs.clone())
+                .unique_stable(None, UniqueKeepStrategy::First)
+                .with_row_index(ROW_ORDER_COLUMN, None);
+            combined = combined
+                .join(
+                    row_order,
+                    row_exprs.clone(),
+                    row_exprs,
+                    JoinArgs {
+                        join_nulls: true,
+                        ..JoinArgs::new(JoinType::Left)
+                    },
+                )
+                .sort([ROW_ORDER_COLUMN], SortMultipleOptions::default())
+                .drop([ROW_ORDER_COLUMN]);
+        }
+        
+        let df = combined
+            .select(request.rows.iter().chain(&column_keys).map(|name| col(name)).collect::<Vec<Expr>>())
+            .collect()
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+        
+        tracing::debug!(shape = ?df.shape(), "Pivoted");
         
         // Correct structure for frontend
-        Ok(PivotResult {
-            data,
+        Ok(PivotFrame {
+            df,
             column_headers,
-            row_headers: request.rows,
+            row_headers: request.rows.clone(),
+            column_keys,
+            total_column_keys: Vec::new(),
+            grand_total: None,
+            row_depths: Vec::new(),
+            grouping_sets: Vec::new(),
+            grouping_set_depths: Vec::new(),
+            column_formats,
+            warnings: Vec::new(),
+            sample: None,
+            stats: PivotStats::default(),
+        })
+    }
+}
+
+const TOTAL_LABEL: &str = "Total";
+const ROW_ORDER_COLUMN: &str = "__row_order";
+// Labels of all column fields joined into the one column that gets pivoted
+const COLUMN_KEY_COLUMN: &str = "__column_key";
+// Index of the pivot when there are no row fields to index it on
+const SINGLE_ROW_COLUMN: &str = "__row";
+// Between the measure and the column key in the names Polars gives pivoted
+// columns when several measures are pivoted at once
+const PIVOT_NAME_SEPARATOR: &str = "_";
+
+// Name Polars gives the pivoted column of a measure for one column key: the key
+// alone for a single measure, "{measure}_{key}" for several
+fn pivoted_column_name(request: &PivotRequest, val_with_agg: &ValueWithAggregation, key: &str) -> String {
+    if request.values.len() > 1 {
+        format!("{}{}{}", val_with_agg.output_name(), PIVOT_NAME_SEPARATOR, key)
+    } else {
+        key.to_string()
+    }
+}
+
+// Join the per-row totals of every measure onto a pivoted frame, each one placed
+// after the columns of its measure under a "Total" header
+fn add_column_totals(frame: PivotFrame, totals: DataFrame, request: &PivotRequest) -> Result<PivotFrame, DataError> {
+    let names: Vec<String> = request.values.iter().map(|v| v.output_name()).collect();
+    let keys: Vec<String> = names.iter().map(|name| format!("total_{}", name)).collect();
+    
+    // The totals go after the pivoted columns under one Total header, with the
+    // measure level, if any, below it
+    let multiple = request.values.len() > 1;
+    let leaf = frame.column_headers.len() - 1;
+    let mut column_headers = frame.column_headers;
+    let mut column_keys = frame.column_keys;
+    let mut column_formats = frame.column_formats;
+    for ((name, key), val_with_agg) in names.iter().zip(&keys).zip(&request.values) {
+        column_keys.push(key.clone());
+        column_formats.push(val_with_agg.format.clone());
+        for (level, headers) in column_headers.iter_mut().enumerate() {
+            headers.push(match level {
+                0 => TOTAL_LABEL.to_string(),
+                _ if multiple && level == leaf => name.clone(),
+                _ => String::new(),
+            });
+        }
+    }
+    
+    let row_exprs: Vec<Expr> = request.rows.iter().map(|s| col(s)).collect();
+    let totals = totals.lazy().rename(names.clone(), keys.clone());
+    let df = if row_exprs.is_empty() {
+        frame.df.lazy().cross_join(totals, None)
+    } else {
+        frame.df.lazy().join(
+            totals,
+            row_exprs.clone(),
+            row_exprs,
+            JoinArgs {
+                join_nulls: true,
+                ..JoinArgs::new(JoinType::Left)
+            },
+        )
+    }
+    .select(request.rows.iter().chain(&column_keys).map(|name| col(name)).collect::<Vec<Expr>>())
+    .collect()
+    .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    
+    Ok(PivotFrame {
+        df,
+        column_headers,
+        row_headers: frame.row_headers,
+        column_keys,
+        total_column_keys: keys,
+        grand_total: frame.grand_total,
+        row_depths: frame.row_depths,
+        grouping_sets: frame.grouping_sets,
+        grouping_set_depths: frame.grouping_set_depths,
+        column_formats,
+        warnings: frame.warnings,
+        sample: frame.sample,
+        stats: frame.stats,
+    })
+}
+
+// Pivots every measure over the joined column labels in one reshape. The
+// group_by already produced exactly one aggregated value per cell, so the pivot
+// only has to move it into place; aggregating again would turn counts into 1s
+// and couldn't express Std/Var at all.
+fn pivot_measures(agg_df: &DataFrame, request: &PivotRequest) -> Result<DataFrame, DataError> {
+    let names: Vec<String> = request.values.iter().map(|v| v.output_name()).collect();
+    
+    // Polars needs an index to pivot on, so without row fields every group goes
+    // into the one row of a constant index
+    let single_row;
+    let (agg_df, index) = if request.rows.is_empty() {
+        single_row = agg_df.clone()
+            .lazy()
+            .with_column(lit(0u32).alias(SINGLE_ROW_COLUMN))
+            .collect()
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+        (&single_row, vec![SINGLE_ROW_COLUMN])
+    } else {
+        (agg_df, request.rows.iter().map(|s| s.as_str()).collect::<Vec<&str>>())
+    };
+    
+    let mut pivoted = pivot(
+        agg_df,
+        // One output column per joined column label and measure
+        vec![COLUMN_KEY_COLUMN],
+        // One output row per row group
+        Some(index),
+        Some(names.iter().map(|s| s.as_str()).collect::<Vec<&str>>()),
+        // Sorting the columns by label would put "10" before "2"; the columns are
+        // picked out by name afterwards in the order the request asks for
+        false,
+        Some(PivotAgg::First),
+        Some(PIVOT_NAME_SEPARATOR),
+    )
+    .map_err(|e| DataError::ProcessingError(format!("Pivot error: {}", e)))?;
+    if request.rows.is_empty() {
+        pivoted = pivoted.drop(SINGLE_ROW_COLUMN)
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    }
+    
+    tracing::trace!(rows = ?request.rows, columns = pivoted.width(), "Pivoted measures");
+    
+    Ok(pivoted)
+}
+
+// The distinct label combinations of the column fields in pivot order, each as
+// its pivot key with the label of every column field. Fails when two
+// combinations join into the same key, e.g. ("a_b", "c") and ("a", "b_c"), or a
+// null label and the label "null", as their cells would be merged.
+fn column_combinations(by_columns: &DataFrame, fields: &[String]) -> Result<Vec<(String, Vec<String>)>, DataError> {
+    let distinct = by_columns.clone()
+        .lazy()
+        .select(fields.iter()
+            .map(|field| col(field).cast(DataType::String))
+            .chain([col(COLUMN_KEY_COLUMN)])
+            .collect::<Vec<Expr>>())
+        .unique_stable(None, UniqueKeepStrategy::First)
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))?;
+    
+    let strings = |series: &Series| -> Result<Vec<Option<String>>, DataError> {
+        series.str()
+            .map(|values| values.into_iter().map(|v| v.map(|v| v.to_string())).collect())
+            .map_err(|e| DataError::ProcessingError(e.to_string()))
+    };
+    let columns = distinct.get_columns();
+    let keys = strings(&columns[fields.len()])?;
+    let labels = columns[..fields.len()].iter()
+        .map(strings)
+        .collect::<Result<Vec<Vec<Option<String>>>, DataError>>()?;
+    let combination = |i: usize| -> Vec<Option<String>> {
+        labels.iter().map(|field| field[i].clone()).collect()
+    };
+    
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let mut combinations = Vec::with_capacity(keys.len());
+    for (i, key) in keys.iter().enumerate() {
+        let key = key.as_deref().unwrap_or_default();
+        if let Some(&first) = seen.get(key) {
+            let shown = |labels: Vec<Option<String>>| {
+                labels.iter()
+                    .map(|label| label.as_ref().map_or("null".to_string(), |label| format!("{:?}", label)))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            };
+            return Err(DataError::ProcessingError(format!(
+                "Column labels ({}) and ({}) both make the column key '{}'; choose a column separator that isn't in any label, or label nulls differently",
+                shown(combination(first)),
+                shown(combination(i)),
+                key
+            )));
+        }
+        seen.insert(key, i);
+        
+        let shown_labels = combination(i).into_iter()
+            .map(|label| label.unwrap_or_else(|| "null".to_string()))
+            .collect();
+        combinations.push((key.to_string(), shown_labels));
+    }
+    Ok(combinations)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NamedPivot {
+    pub name: String,
+    pub request: PivotRequest,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExportProgress {
+    pub index: usize,
+    pub total: usize,
+    pub name: String,
+    pub path: String,
+}
+
+// Re-run every pivot and write each one as `<name>.csv` into `output_dir`,
+// reporting after each file is written. Pivots whose names make the same file
+// name get a numbered suffix, "Sales (2).csv", rather than overwrite each other.
+pub fn export_pivots(
+    pivots: &[NamedPivot],
+    output_dir: &str,
+    options: &ExecutionOptions,
+    mut on_progress: impl FnMut(ExportProgress),
+) -> Result<Vec<String>, DataError> {
+    let dir = normalize_path(output_dir);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| DataError::ProcessingError(format!("{}: {}", output_dir, e)))?;
+    
+    let mut written = Vec::with_capacity(pivots.len());
+    // Lowercased, as file names are case-insensitive on Windows and macOS
+    let mut used_stems = HashSet::new();
+    for (index, named) in pivots.iter().enumerate() {
+        let mut df = compute_pivot(&named.request, options)?.stacked()?;
+        
+        let base = export_file_stem(&named.name);
+        let mut stem = base.clone();
+        let mut copy = 1;
+        while !used_stems.insert(stem.to_lowercase()) {
+            copy += 1;
+            stem = format!("{} ({})", base, copy);
+        }
+        let path = dir.join(format!("{}.csv", stem));
+        let mut file = File::create(&path)
+            .map_err(|e| DataError::ProcessingError(format!("{}: {}", path.display(), e)))?;
+        CsvWriter::new(&mut file)
+            .include_header(true)
+            .finish(&mut df)
+            .map_err(|e| DataError::ProcessingError(format!("{}: {}", path.display(), e)))?;
+        
+        let path = path.display().to_string();
+        on_progress(ExportProgress {
+            index,
+            total: pivots.len(),
+            name: named.name.clone(),
+            path: path.clone(),
+        });
+        written.push(path);
+    }
+    
+    Ok(written)
+}
+
+// Pivot names come from the user; keep them usable as file names on every platform
+fn export_file_stem(name: &str) -> String {
+    let stem: String = name.trim()
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    
+    if stem.is_empty() {
+        "pivot".to_string()
+    } else {
+        stem
+    }
+}
+
+// Group keys with the inner labels of collapsed groups masked out, so every
+// record under a collapsed group lands in a single subtotal
+fn collapse_group_exprs(group_exprs: Vec<Expr>, request: &PivotRequest, collapse: &CollapseState) -> Vec<Expr> {
+    let mut masks: HashMap<String, Expr> = HashMap::new();
+    
+    for (fields, paths) in [(&request.rows, &collapse.rows), (&request.columns, &collapse.columns)] {
+        for path in paths {
+            // Collapsing the innermost level (or deeper) leaves nothing to hide
+            if path.is_empty() || path.len() >= fields.len() {
+                continue;
+            }
+            
+            let in_group = fields.iter()
+                .zip(path)
+                .map(|(field, label)| col(field).cast(DataType::String).eq(lit(label.clone())))
+                .reduce(|acc, cond| acc.and(cond))
+                .unwrap();
+            
+            for field in &fields[path.len()..] {
+                let mask = match masks.remove(field) {
+                    Some(mask) => mask.or(in_group.clone()),
+                    None => in_group.clone(),
+                };
+                masks.insert(field.clone(), mask);
+            }
+        }
+    }
+    
+    request.rows.iter()
+        .chain(&request.columns)
+        .zip(group_exprs)
+        .map(|(field, expr)| match masks.remove(field) {
+            Some(mask) => when(mask).then(lit(NULL)).otherwise(expr).alias(field),
+            None => expr,
         })
+        .collect()
+}
+
+// Add every combination of the labels seen for each column field to every row
+// group, so e.g. a region without sales in March still gets a March cell. Row
+// groups stay the ones seen, so no (Asia, France) row is made up.
+fn densify(agg_df: DataFrame, request: &PivotRequest) -> Result<DataFrame, DataError> {
+    if request.columns.is_empty() {
+        return Ok(agg_df);
+    }
+    
+    let mut grid: Option<LazyFrame> = None;
+    if !request.rows.is_empty() {
+        grid = Some(agg_df.clone()
+            .lazy()
+            .select(request.rows.iter().map(|field| col(field)).collect::<Vec<Expr>>())
+            .unique_stable(None, UniqueKeepStrategy::First));
+    }
+    for field in &request.columns {
+        let labels = agg_df.clone()
+            .lazy()
+            .select([col(field)])
+            .unique_stable(None, UniqueKeepStrategy::First);
+        grid = Some(match grid {
+            None => labels,
+            Some(grid) => grid.cross_join(labels, None),
+        });
+    }
+    let Some(grid) = grid else {
+        return Ok(agg_df);
+    };
+    
+    let keys: Vec<Expr> = request.rows.iter().chain(&request.columns).map(|s| col(s)).collect();
+    let mut lf = grid.join(
+        agg_df.lazy(),
+        keys.clone(),
+        keys,
+        JoinArgs {
+            join_nulls: true,
+            ..JoinArgs::new(JoinType::Left)
+        },
+    );
+    if let Some(value) = request.fill_value {
+        lf = lf.with_columns(
+            request.values.iter()
+                .map(|v| col(&v.output_name()).fill_null(lit(value)))
+                .collect::<Vec<Expr>>(),
+        );
+    }
+    
+    lf.collect().map_err(|e| DataError::ProcessingError(e.to_string()))
+}
+
+// Order groups by their labels so results don't depend on the group_by's hash order
+fn sort_by_labels(df: DataFrame, labels: &[String], request: &PivotRequest) -> Result<DataFrame, DataError> {
+    if labels.is_empty() {
+        return Ok(df);
+    }
+    
+    df.lazy()
+        .sort_by_exprs(
+            labels.iter().flat_map(|field| request.label_sort_keys(field)).collect::<Vec<Expr>>(),
+            SortMultipleOptions::default()
+                .with_nulls_last(true)
+                .with_maintain_order(true),
+        )
+        .collect()
+        .map_err(|e| DataError::ProcessingError(e.to_string()))
+}
+
+// Largest measure first, with the row labels breaking ties so equal values
+// always come back in the same order
+fn sort_by_measure(lf: LazyFrame, measure: &str, rows: &[String]) -> LazyFrame {
+    let mut by = vec![measure.to_string()];
+    by.extend(rows.iter().cloned());
+    
+    let descending = by.iter().map(|name| name == measure).collect::<Vec<bool>>();
+    
+    lf.sort(
+        by,
+        SortMultipleOptions::default()
+            .with_order_descending_multi(descending)
+            .with_nulls_last(true)
+            .with_maintain_order(true),
+    )
+}
+
+// Replace aggregated measures with their display-mode representation
+fn apply_display_modes(agg_df: DataFrame, request: &PivotRequest) -> Result<DataFrame, DataError> {
+    if request.values.iter().all(|v| v.display_mode.is_none()) {
+        return Ok(agg_df);
+    }
+    
+    // The parent level of a cell: every row field but the innermost, plus the column fields
+    let mut parent_exprs: Vec<Expr> = request.rows
+        .iter()
+        .take(request.rows.len().saturating_sub(1))
+        .map(|s| col(s))
+        .collect();
+    parent_exprs.extend(request.columns.iter().map(|s| col(s)));
+    
+    let mut lf = agg_df.lazy();
+    
+    // Walk the measures backwards so the first cumulative measure decides the final row order
+    for val_with_agg in request.values.iter().rev() {
+        let name = val_with_agg.output_name();
+        
+        match val_with_agg.display_mode {
+            Some(DisplayMode::CumulativeShare) => {
+                let measure = col(&name).cast(DataType::Float64);
+                let running = measure.clone().cum_sum(false);
+                let total = measure.sum();
+                let share = if parent_exprs.is_empty() {
+                    running / total
+                } else {
+                    running.over(parent_exprs.clone()) / total.over(parent_exprs.clone())
+                };
+                
+                // Running sums follow frame order, so put the largest groups first
+                lf = sort_by_measure(lf, &name, &request.rows)
+                    .with_column(share.alias(&name));
+            },
+            // Compared across the pivoted columns, see apply_column_display_mode
+            Some(_) => {},
+            None => {},
+        }
+    }
+    
+    lf.collect().map_err(|e| DataError::ProcessingError(e.to_string()))
+}
+
+// Display modes that compare each pivoted cell with the other columns of the
+// measure in the same row (`keys`, in column order)
+fn apply_column_display_mode(lf: LazyFrame, val_with_agg: &ValueWithAggregation, keys: &[String]) -> Result<LazyFrame, DataError> {
+    let exprs = match &val_with_agg.display_mode {
+        Some(DisplayMode::DifferenceFromPrevious) => previous_column_changes(keys, false),
+        Some(DisplayMode::PercentDifferenceFromPrevious) => previous_column_changes(keys, true),
+        Some(DisplayMode::RankInRow { ties }) => row_ranks(keys, *ties)?,
+        _ => return Ok(lf),
+    };
+    
+    Ok(lf.with_columns(exprs))
+}
+
+fn previous_column_changes(keys: &[String], percent: bool) -> Vec<Expr> {
+    let mut exprs = Vec::with_capacity(keys.len());
+    if let Some(first) = keys.first() {
+        exprs.push(lit(NULL).cast(DataType::Float64).alias(first));
+    }
+    for pair in keys.windows(2) {
+        let previous = col(&pair[0]).cast(DataType::Float64);
+        let change = col(&pair[1]).cast(DataType::Float64) - previous.clone();
+        let expr = if percent {
+            // No percentage of an empty or zero cell
+            when(previous.clone().eq(lit(0.0)))
+                .then(lit(NULL))
+                .otherwise(change / previous)
+        } else {
+            change
+        };
+        exprs.push(expr.alias(&pair[1]));
+    }
+    exprs
+}
+
+// Rank of every cell among the row's columns, built by counting the cells above
+// it since Polars only ranks down a column. Empty cells are not ranked.
+fn row_ranks(keys: &[String], ties: RankTies) -> Result<Vec<Expr>, DataError> {
+    // Counting each larger value once would take a comparison with every column
+    // before it, so dense ranks come from ranking the row's cells as a list
+    if let RankTies::Dense = ties {
+        let ranks = concat_list(keys.iter().map(|key| col(key).cast(DataType::Float64)).collect::<Vec<Expr>>())
+            .map_err(|e| DataError::ProcessingError(e.to_string()))?
+            .list()
+            .eval(col("").rank(RankOptions { method: RankMethod::Dense, descending: true }, None), false);
+        return Ok(keys.iter()
+            .enumerate()
+            .map(|(i, key)| ranks.clone().list().get(lit(i as i64), false).alias(key))
+            .collect());
     }
+    
+    // 1 for each of `cells` that holds a value and satisfies `test`, summed
+    let count = |cells: Vec<Expr>| -> Expr {
+        cells.into_iter()
+            .map(|cell| cell.fill_null(lit(false)).cast(DataType::UInt32))
+            .fold(lit(0u32), |acc, cell| acc + cell)
+    };
+    
+    Ok(keys.iter()
+        .enumerate()
+        .map(|(i, key)| {
+            let value = col(key);
+            let above = |j: usize| col(&keys[j]).gt(value.clone());
+            let equal = |j: usize| col(&keys[j]).eq(value.clone());
+            let all = 0..keys.len();
+            
+            let min = lit(1u32) + count(all.clone().map(above).collect());
+            let max = count(all.clone().map(|j| above(j).or(equal(j))).collect());
+            let rank = match ties {
+                RankTies::Min => min,
+                RankTies::Max => max,
+                RankTies::Average => (min.cast(DataType::Float64) + max.cast(DataType::Float64)) / lit(2.0),
+                RankTies::Ordinal => min + count((0..i).map(equal).collect()),
+                RankTies::Dense => unreachable!("dense ranks are returned above"),
+            };
+            
+            when(value.clone().is_null())
+                .then(lit(NULL))
+                .otherwise(rank)
+                .alias(key)
+        })
+        .collect())
 }
 
 fn df_to_json_rows(df: DataFrame) -> Result<Vec<HashMap<String, serde_json::Value>>, polars::error::PolarsError> {
@@ -553,7 +5350,7 @@ fn df_to_json_rows(df: DataFrame) -> Result<Vec<HashMap<String, serde_json::Valu
                         serde_json::Value::Null
                     }
                 },
-                DataType::Float32 | DataType::Float64 => {
+                DataType::Float64 => {
                     let s = col.f64()?;
                     if let Some(v) = s.get(i) {
                         if let Some(num) = serde_json::Number::from_f64(v as f64) {
@@ -581,7 +5378,9 @@ fn df_to_json_rows(df: DataFrame) -> Result<Vec<HashMap<String, serde_json::Valu
                         serde_json::Value::Null
                     }
                 },
-                _ => serde_json::Value::String(format!("{:?}", col.get(i))),
+                // Unsigned counts, dates, ... as well as the results of Min/Max/First/Last
+                // on them
+                _ => json_value(col.get(i)?),
             };
             
             row_map.insert(col_name, value);
@@ -591,4 +5390,353 @@ fn df_to_json_rows(df: DataFrame) -> Result<Vec<HashMap<String, serde_json::Valu
     }
     
     Ok(result)
-} 
\ No newline at end of file
+}
+
+// A single cell as JSON: numbers as numbers where JSON can hold them exactly,
+// dates and times as ISO 8601 text
+fn json_value(value: AnyValue) -> serde_json::Value {
+    let big_int = |v: i128| {
+        if v > i64::pow(2, 53) as i128 || v < -i64::pow(2, 53) as i128 {
+            serde_json::Value::String(v.to_string())
+        } else {
+            serde_json::Value::from(v as i64)
+        }
+    };
+    let float = |v: f64| match serde_json::Number::from_f64(v) {
+        Some(num) => serde_json::Value::Number(num),
+        None => serde_json::Value::String(v.to_string()),
+    };
+    
+    match value {
+        AnyValue::Null => serde_json::Value::Null,
+        AnyValue::Boolean(v) => serde_json::Value::Bool(v),
+        AnyValue::String(v) => serde_json::Value::String(v.to_string()),
+        AnyValue::StringOwned(v) => serde_json::Value::String(v.to_string()),
+        AnyValue::Int8(v) => serde_json::Value::from(v),
+        AnyValue::Int16(v) => serde_json::Value::from(v),
+        AnyValue::Int32(v) => serde_json::Value::from(v),
+        AnyValue::Int64(v) => big_int(v as i128),
+        AnyValue::UInt8(v) => serde_json::Value::from(v),
+        AnyValue::UInt16(v) => serde_json::Value::from(v),
+        AnyValue::UInt32(v) => serde_json::Value::from(v),
+        AnyValue::UInt64(v) => big_int(v as i128),
+        AnyValue::Float32(v) => float(v as f64),
+        AnyValue::Float64(v) => float(v),
+        AnyValue::Date(days) => {
+            let epoch = chrono::NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+            serde_json::Value::String((epoch + chrono::Duration::days(days as i64)).to_string())
+        },
+        AnyValue::Datetime(v, unit, time_zone) => {
+            let datetime = match unit {
+                TimeUnit::Nanoseconds => Some(chrono::DateTime::from_timestamp_nanos(v)),
+                TimeUnit::Microseconds => chrono::DateTime::from_timestamp_micros(v),
+                TimeUnit::Milliseconds => chrono::DateTime::from_timestamp_millis(v),
+            };
+            let Some(datetime) = datetime else {
+                return serde_json::Value::Null;
+            };
+            // Zoned datetimes are RFC 3339 with the zone's offset at that moment,
+            // e.g. "2024-07-01T10:00:00+02:00"; naive ones keep their wall time
+            let rfc3339 = |datetime: chrono::DateTime<chrono::FixedOffset>| {
+                datetime.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+            };
+            let shown = match time_zone.as_deref() {
+                None => datetime.naive_utc().format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+                Some(zone) => match (zone.parse::<chrono_tz::Tz>(), zone.parse::<chrono::FixedOffset>()) {
+                    (Ok(zone), _) => rfc3339(datetime.with_timezone(&zone).fixed_offset()),
+                    (_, Ok(offset)) => rfc3339(datetime.with_timezone(&offset)),
+                    // A zone chrono-tz doesn't know still gets the right instant
+                    _ => rfc3339(datetime.fixed_offset()),
+                },
+            };
+            serde_json::Value::String(shown)
+        },
+        other => serde_json::Value::String(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    // Two regions over the years "2" and "10", which sort the other way round as
+    // text than as numbers. North in "2" has sales 1 and 3; in "10", 5 and a null.
+    fn sales() -> LazyFrame {
+        df! {
+            "region" => ["North", "North", "North", "North", "South", "South"],
+            "year" => ["2", "2", "10", "10", "2", "10"],
+            "sales" => [Some(1.0), Some(3.0), Some(5.0), None, Some(2.0), Some(8.0)],
+            "flag" => ["a", "b", "a", "a", "b", "b"],
+        }
+        .unwrap()
+        .lazy()
+    }
+    
+    fn request(values: serde_json::Value) -> PivotRequest {
+        serde_json::from_value(json!({
+            "rows": ["region"],
+            "columns": ["year"],
+            "values": values,
+        }))
+        .unwrap()
+    }
+    
+    fn measure(aggregation: serde_json::Value) -> serde_json::Value {
+        json!({ "field": "sales", "aggregation": aggregation })
+    }
+    
+    // The cell of the request's measure at `index` for a region and year
+    fn cell(request: &PivotRequest, index: usize, region: &str, year: &str) -> Option<f64> {
+        let frame = compute_pivot_from(sales(), request).unwrap();
+        let row = frame.df.column("region").unwrap()
+            .str().unwrap()
+            .into_iter()
+            .position(|label| label == Some(region))
+            .unwrap();
+        let key = request.column_key(&request.values[index], year);
+        frame.df.column(&key).unwrap()
+            .cast(&DataType::Float64).unwrap()
+            .f64().unwrap()
+            .get(row)
+    }
+    
+    fn assert_cell(aggregation: serde_json::Value, expected: f64) {
+        let request = request(json!([measure(aggregation.clone())]));
+        let value = cell(&request, 0, "North", "2").unwrap();
+        assert!((value - expected).abs() < 1e-9, "{}: {} where {} was expected", aggregation, value, expected);
+    }
+    
+    fn condition() -> serde_json::Value {
+        json!({ "condition": { "column": "flag", "operator": "Equal", "value": "a" } })
+    }
+    
+    #[test]
+    fn aggregates_each_cell() {
+        assert_cell(json!("Sum"), 4.0);
+        assert_cell(json!("Mean"), 2.0);
+        assert_cell(json!("GeometricMean"), 3f64.sqrt());
+        assert_cell(json!("Count"), 2.0);
+        assert_cell(json!("CountRows"), 2.0);
+        assert_cell(json!("Min"), 1.0);
+        assert_cell(json!("Max"), 3.0);
+        assert_cell(json!("First"), 1.0);
+        assert_cell(json!("Last"), 3.0);
+        assert_cell(json!("Median"), 2.0);
+        assert_cell(json!("Std"), 2f64.sqrt());
+        assert_cell(json!("Var"), 2.0);
+        assert_cell(json!("CountDistinct"), 2.0);
+        assert_cell(json!("ApproxCountDistinct"), 2.0);
+        assert_cell(json!({ "ApproxQuantile": { "quantile": 1.0 } }), 3.0);
+        assert_cell(json!({ "CountWhere": condition() }), 1.0);
+        assert_cell(json!({ "SumWhere": condition() }), 1.0);
+        assert_cell(json!({ "MeanWhere": condition() }), 1.0);
+        assert_cell(json!({ "Custom": "sum(sales) * 2" }), 8.0);
+    }
+    
+    #[test]
+    fn counts_nulls_only_for_count_rows() {
+        let count = request(json!([measure(json!("Count"))]));
+        let count_rows = request(json!([measure(json!("CountRows"))]));
+        assert_eq!(cell(&count, 0, "North", "10"), Some(1.0));
+        assert_eq!(cell(&count_rows, 0, "North", "10"), Some(2.0));
+    }
+    
+    #[test]
+    fn calculates_from_other_measures() {
+        let request = request(json!([
+            measure(json!("Sum")),
+            { "field": "double", "aggregation": { "Calculated": "sum_sales * 2" } },
+        ]));
+        assert_eq!(cell(&request, 1, "North", "2"), Some(8.0));
+        assert_eq!(cell(&request, 1, "South", "10"), Some(16.0));
+    }
+    
+    #[test]
+    fn keeps_cells_under_their_column_whatever_the_label_order() {
+        for maintain_order in [false, true] {
+            let mut request = request(json!([measure(json!("Sum")), measure(json!("Max"))]));
+            request.maintain_order = maintain_order;
+            assert_eq!(cell(&request, 0, "North", "2"), Some(4.0));
+            assert_eq!(cell(&request, 0, "North", "10"), Some(5.0));
+            assert_eq!(cell(&request, 1, "South", "2"), Some(2.0));
+            assert_eq!(cell(&request, 1, "South", "10"), Some(8.0));
+        }
+    }
+    
+    #[test]
+    fn keeps_rows_in_order_of_appearance() {
+        let lf = || df! {
+            "region" => ["South", "North", "South"],
+            "year" => ["2", "10", "10"],
+            "sales" => [1.0, 2.0, 3.0],
+        }
+        .unwrap()
+        .lazy();
+        let regions = |maintain_order: bool| -> Vec<Option<String>> {
+            let mut request = request(json!([measure(json!("Sum"))]));
+            request.maintain_order = maintain_order;
+            let frame = compute_pivot_from(lf(), &request).unwrap();
+            frame.df.column("region").unwrap()
+                .str().unwrap()
+                .into_iter()
+                .map(|label| label.map(|label| label.to_string()))
+                .collect()
+        };
+        assert_eq!(regions(false), [Some("North".to_string()), Some("South".to_string())]);
+        assert_eq!(regions(true), [Some("South".to_string()), Some("North".to_string())]);
+    }
+    
+    #[test]
+    fn pivots_columns_without_row_fields() {
+        let mut request = request(json!([measure(json!("Sum"))]));
+        request.rows = Vec::new();
+        let frame = compute_pivot_from(sales(), &request).unwrap();
+        assert_eq!(frame.df.height(), 1);
+        
+        let total = |year: &str| {
+            frame.df.column(&request.column_key(&request.values[0], year)).unwrap()
+                .cast(&DataType::Float64).unwrap()
+                .f64().unwrap()
+                .get(0)
+        };
+        assert_eq!(total("2"), Some(6.0));
+        assert_eq!(total("10"), Some(13.0));
+    }
+    
+    #[test]
+    fn transposes_rows_into_columns() {
+        let mut request = request(json!([measure(json!("Sum"))]));
+        request.columns = Vec::new();
+        request.transpose = true;
+        let frame = compute_pivot_from(sales(), &request).unwrap();
+        assert_eq!(frame.df.height(), 1);
+        
+        let transposed = request.transposed();
+        let total = |region: &str| {
+            frame.df.column(&transposed.column_key(&transposed.values[0], region)).unwrap()
+                .cast(&DataType::Float64).unwrap()
+                .f64().unwrap()
+                .get(0)
+        };
+        assert_eq!(total("North"), Some(9.0));
+        assert_eq!(total("South"), Some(10.0));
+    }
+    
+    #[test]
+    fn densifies_columns_without_making_up_row_groups() {
+        let lf = df! {
+            "region" => ["Asia", "Europe"],
+            "country" => ["Japan", "France"],
+            "year" => ["2", "10"],
+            "sales" => [1.0, 2.0],
+        }
+        .unwrap()
+        .lazy();
+        let mut request = request(json!([measure(json!("Sum"))]));
+        request.rows = vec!["region".to_string(), "country".to_string()];
+        request.densify = true;
+        request.fill_value = Some(0.0);
+        request.show_subtotals = false;
+        request.show_row_totals = false;
+        let frame = compute_pivot_from(lf, &request).unwrap();
+        
+        assert_eq!(frame.df.height(), 2);
+        let key = request.column_key(&request.values[0], "10");
+        let filled = frame.df.column(&key).unwrap()
+            .cast(&DataType::Float64).unwrap()
+            .f64().unwrap()
+            .get(0);
+        assert_eq!(filled, Some(0.0));
+    }
+    
+    #[test]
+    fn names_conditional_measures_as_written() {
+        let named = |measure: serde_json::Value| {
+            serde_json::from_value::<ValueWithAggregation>(measure).unwrap().output_name()
+        };
+        assert_eq!(named(measure(json!({ "SumWhere": condition() }))), "sum_where_sales[flag = \"a\"]");
+        assert_eq!(
+            named(json!({
+                "field": "sales",
+                "aggregation": "Sum",
+                "filter": { "column": "sales", "operator": { "Between": { "low": 1, "high": 5 } }, "value": null },
+            })),
+            "sum_sales[sales between 1 and 5]"
+        );
+    }
+    
+    #[test]
+    fn rejects_column_labels_that_join_into_the_same_key() {
+        let colliding = [
+            (vec![Some("a_b"), Some("a")], vec![Some("c"), Some("b_c")]),
+            (vec![None, Some("null")], vec![Some("c"), Some("c")]),
+        ];
+        for (outer, inner) in colliding {
+            let lf = df! {
+                "region" => ["North", "North"],
+                "outer" => outer,
+                "inner" => inner,
+                "sales" => [1.0, 2.0],
+            }
+            .unwrap()
+            .lazy();
+            let mut request = request(json!([measure(json!("Sum"))]));
+            request.columns = vec!["outer".to_string(), "inner".to_string()];
+            assert!(compute_pivot_from(lf, &request).is_err());
+        }
+    }
+    
+    #[test]
+    fn numbers_an_other_group_named_like_a_top_group() {
+        // South (10) outranks North (9), so North is folded under a label that
+        // mustn't merge it into South
+        let mut request = request(json!([measure(json!("Sum"))]));
+        request.top_rows = Some(TopRows { count: 1, measure: None, other_label: Some("South".to_string()) });
+        assert_eq!(cell(&request, 0, "South", "2"), Some(2.0));
+        assert_eq!(cell(&request, 0, "South (2)", "2"), Some(4.0));
+    }
+    
+    #[test]
+    fn ranks_tied_cells_in_a_row_by_each_tie_mode() {
+        let keys: Vec<String> = ["a", "b", "c", "d"].iter().map(|key| key.to_string()).collect();
+        let ranks = |ties: RankTies| -> Vec<Option<f64>> {
+            let df = df! {
+                "a" => [Some(5.0)],
+                "b" => [Some(3.0)],
+                "c" => [Some(5.0)],
+                "d" => [None::<f64>],
+            }
+            .unwrap();
+            let ranked = df.lazy().select(row_ranks(&keys, ties).unwrap()).collect().unwrap();
+            keys.iter()
+                .map(|key| ranked.column(key).unwrap().cast(&DataType::Float64).unwrap().f64().unwrap().get(0))
+                .collect()
+        };
+        assert_eq!(ranks(RankTies::Min), [Some(1.0), Some(3.0), Some(1.0), None]);
+        assert_eq!(ranks(RankTies::Max), [Some(2.0), Some(3.0), Some(2.0), None]);
+        assert_eq!(ranks(RankTies::Average), [Some(1.5), Some(3.0), Some(1.5), None]);
+        assert_eq!(ranks(RankTies::Ordinal), [Some(1.0), Some(3.0), Some(2.0), None]);
+        assert_eq!(ranks(RankTies::Dense), [Some(1.0), Some(2.0), Some(1.0), None]);
+    }
+    
+    #[test]
+    fn samples_the_exact_share_of_rows() {
+        for (fraction, expected) in [(0.4, 40), (0.7, 70)] {
+            let lf = df! { "value" => (0..100).collect::<Vec<i32>>() }.unwrap().lazy();
+            let sampled = sample_rows(lf, &SampleSpec::Fraction(fraction)).unwrap().collect().unwrap();
+            assert_eq!(sampled.height(), expected, "fraction {}", fraction);
+        }
+    }
+    
+    #[test]
+    fn writes_zoned_datetimes_with_their_offset() {
+        let shown = |time_zone: Option<&str>| {
+            json_value(AnyValue::Datetime(0, TimeUnit::Milliseconds, &time_zone.map(|zone| zone.to_string())))
+        };
+        assert_eq!(shown(None), json!("1970-01-01T00:00:00"));
+        assert_eq!(shown(Some("UTC")), json!("1970-01-01T00:00:00Z"));
+        assert_eq!(shown(Some("Europe/Paris")), json!("1970-01-01T01:00:00+01:00"));
+        assert_eq!(shown(Some("-05:00")), json!("1969-12-31T19:00:00-05:00"));
+    }
+}
@@ -0,0 +1,132 @@
+use polars::prelude::*;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use crate::polars_bridge::{self, DataError, ExecutionOptions, PivotRequest, PivotResult};
+
+// Results kept on disk; the least recently written are removed first
+const MAX_CACHED_RESULTS: usize = 50;
+
+// Pivot results saved in the app cache dir, so reopening a report on data that
+// hasn't changed since doesn't compute it again. Each result is a Parquet file of
+// its rows next to a JSON file of the rest, named after a hash of the request, the
+// engine options and the modification time and size of its data.
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+impl ResultCache {
+    pub fn new(dir: PathBuf) -> Self {
+        ResultCache { dir }
+    }
+
+    // The result of `request` from the cache, or from `compute` when it isn't
+    // cached, saving it for next time. Failing to read or write the cache only
+    // costs the computation.
+    pub fn pivot(
+        &self,
+        request: &PivotRequest,
+        options: &ExecutionOptions,
+        compute: impl FnOnce() -> Result<(DataFrame, PivotResult), DataError>,
+    ) -> Result<(DataFrame, PivotResult), DataError> {
+        let Some(key) = cache_key(request, options) else {
+            return compute();
+        };
+        if let Some(cached) = self.read(&key) {
+            tracing::debug!(key, "Pivot result read from cache");
+            return Ok(cached);
+        }
+
+        let (df, result) = compute()?;
+        // A pivot too wide to build is cheap to find again and may be confirmed next
+        if result.too_wide.is_none() {
+            if let Err(e) = self.write(&key, &df, &result) {
+                tracing::warn!(key, "Failed to cache pivot result: {}", e);
+            }
+        }
+        Ok((df, result))
+    }
+
+    fn read(&self, key: &str) -> Option<(DataFrame, PivotResult)> {
+        let metadata = fs::read(self.dir.join(format!("{}.json", key))).ok()?;
+        let mut result: PivotResult = serde_json::from_slice(&metadata).ok()?;
+        let file = File::open(self.dir.join(format!("{}.parquet", key))).ok()?;
+        let df = ParquetReader::new(file).finish().ok()?;
+
+        result.cached = true;
+        Some((df, result))
+    }
+
+    fn write(&self, key: &str, df: &DataFrame, result: &PivotResult) -> Result<(), String> {
+        fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+
+        let file = File::create(self.dir.join(format!("{}.parquet", key))).map_err(|e| e.to_string())?;
+        ParquetWriter::new(file)
+            .finish(&mut df.clone())
+            .map_err(|e| e.to_string())?;
+        // Written last, so a result is only read once both files are complete
+        let metadata = serde_json::to_vec(result).map_err(|e| e.to_string())?;
+        fs::write(self.dir.join(format!("{}.json", key)), metadata).map_err(|e| e.to_string())?;
+
+        self.evict();
+        Ok(())
+    }
+
+    // Removes the oldest results beyond MAX_CACHED_RESULTS
+    fn evict(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut results: Vec<(std::time::SystemTime, PathBuf)> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .filter_map(|path| Some((fs::metadata(&path).and_then(|m| m.modified()).ok()?, path)))
+            .collect();
+        if results.len() <= MAX_CACHED_RESULTS {
+            return;
+        }
+
+        results.sort();
+        for (_, path) in &results[..results.len() - MAX_CACHED_RESULTS] {
+            let _ = fs::remove_file(path);
+            let _ = fs::remove_file(path.with_extension("parquet"));
+        }
+    }
+}
+
+// Hash of the request, the engine options and the version of its data; None for
+// data that can't be checked for changes, such as cloud paths
+fn cache_key(request: &PivotRequest, options: &ExecutionOptions) -> Option<String> {
+    let (modified, size) = polars_bridge::data_fingerprint(&request.data_path)?;
+    // The page size only changes how the rows are sent, and dataset ids change
+    // from one run of the app to the next
+    let normalized = PivotRequest {
+        page_size: None,
+        dataset_id: None,
+        ..request.clone()
+    };
+    // Maps in requests are BTreeMaps, so equal requests serialize the same
+    let mut key = serde_json::to_string(&normalized).ok()?;
+    key.push_str(&format!(
+        "\n{}\n{}",
+        modified.duration_since(UNIX_EPOCH).ok()?.as_nanos(),
+        size
+    ));
+    // Whether the pivot streams, and so which warnings it gives, follows the budgets
+    key.push_str(&format!("\n{:?}\n{:?}", options.memory_budget_bytes, options.result_budget_bytes));
+    // "The last 7 days" covers different rows tomorrow
+    if request.depends_on_today() {
+        key.push_str(&format!("\n{}", chrono::Local::now().date_naive()));
+    }
+    Some(format!("{:016x}", stable_hash(key.as_bytes())))
+}
+
+// 64-bit FNV-1a. Unlike DefaultHasher it is the same in every Rust release, so
+// results cached by one build of the app are found by the next.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::polars_bridge::ExecutionOptions;
+
+// Name of the settings file in the app's config directory
+pub const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("Failed to read settings: {0}")]
+    ReadError(String),
+    #[error("Failed to write settings: {0}")]
+    WriteError(String),
+}
+
+// Engine settings chosen by the user, shared by every command
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct EngineSettings {
+    // Memory the pivot pipeline may use before falling back to streaming; half
+    // the memory available when not set
+    pub memory_budget_mb: Option<u64>,
+    // Largest pivoted result to build, in MB; half the memory available when not set
+    pub max_result_mb: Option<u64>,
+    // Threads Polars computes with; one per core when not set. Polars sizes its
+    // thread pool once, so a change takes effect the next time the app starts.
+    pub max_threads: Option<usize>,
+    // Least severe log messages written, e.g. "debug" or "turbopivot=trace";
+    // "info" when not set. Takes effect the next time the app starts.
+    pub log_level: Option<String>,
+}
+
+impl EngineSettings {
+    pub fn load(path: &Path) -> Result<Self, SettingsError> {
+        if !path.exists() {
+            return Ok(EngineSettings::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .map_err(|e| SettingsError::ReadError(e.to_string()))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| SettingsError::ReadError(e.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SettingsError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| SettingsError::WriteError(e.to_string()))?;
+        }
+
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| SettingsError::WriteError(e.to_string()))?;
+        fs::write(path, contents).map_err(|e| SettingsError::WriteError(e.to_string()))
+    }
+
+    // Caps the Polars thread pool; only works before Polars first runs, and
+    // must be called before any other thread starts, as it sets an environment
+    // variable
+    pub fn apply_thread_limit(&self) {
+        if let Some(threads) = self.max_threads.filter(|&threads| threads > 0) {
+            std::env::set_var("POLARS_MAX_THREADS", threads.to_string());
+        }
+    }
+
+    pub fn execution_options(&self) -> ExecutionOptions {
+        ExecutionOptions {
+            memory_budget_bytes: self.memory_budget_mb.map(|mb| mb * 1024 * 1024),
+            result_budget_bytes: self.max_result_mb.map(|mb| mb * 1024 * 1024),
+        }
+    }
+}
+
+// Settings held in Tauri state, written back to disk on every change
+pub struct SettingsState {
+    path: PathBuf,
+    settings: Mutex<EngineSettings>,
+}
+
+impl SettingsState {
+    pub fn load(path: PathBuf) -> Self {
+        // Fall back to defaults rather than refusing to start
+        let settings = EngineSettings::load(&path).unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            EngineSettings::default()
+        });
+
+        SettingsState {
+            path,
+            settings: Mutex::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> EngineSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn update(&self, settings: EngineSettings) -> Result<(), SettingsError> {
+        settings.save(&self.path)?;
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+}
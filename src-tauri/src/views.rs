@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+use crate::polars_bridge::{CollapseState, NamedPivot, PivotRequest};
+
+#[derive(Error, Debug)]
+pub enum ViewsError {
+    #[error("Failed to read saved views: {0}")]
+    ReadError(String),
+    #[error("Failed to write saved views: {0}")]
+    WriteError(String),
+}
+
+// Saved pivot name -> how the user left its groups
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Views {
+    pivots: HashMap<String, CollapseState>,
+    // Saved pivot name -> its request, for exporting them all at once
+    #[serde(default)]
+    saved: BTreeMap<String, PivotRequest>,
+}
+
+impl Views {
+    pub fn load(path: &Path) -> Result<Self, ViewsError> {
+        if !path.exists() {
+            return Ok(Views::default());
+        }
+
+        let contents = fs::read_to_string(path)
+            .map_err(|e| ViewsError::ReadError(e.to_string()))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| ViewsError::ReadError(e.to_string()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ViewsError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| ViewsError::WriteError(e.to_string()))?;
+        }
+
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| ViewsError::WriteError(e.to_string()))?;
+        fs::write(path, contents).map_err(|e| ViewsError::WriteError(e.to_string()))
+    }
+
+    pub fn collapse_state(&self, pivot_name: &str) -> Option<CollapseState> {
+        self.pivots.get(pivot_name).cloned()
+    }
+
+    // The saved pivots by name
+    pub fn saved_pivots(&self) -> Vec<NamedPivot> {
+        self.saved.iter()
+            .map(|(name, request)| NamedPivot { name: name.clone(), request: request.clone() })
+            .collect()
+    }
+
+    pub fn save_pivot(&mut self, pivot: NamedPivot) {
+        self.saved.insert(pivot.name, pivot.request);
+    }
+
+    // Forgets the pivot along with how its groups were left
+    pub fn delete_pivot(&mut self, pivot_name: &str) {
+        self.saved.remove(pivot_name);
+        self.pivots.remove(pivot_name);
+    }
+
+    // A fully expanded view removes the pivot's entry
+    pub fn set_collapse_state(&mut self, pivot_name: &str, state: CollapseState) {
+        if state.rows.is_empty() && state.columns.is_empty() {
+            self.pivots.remove(pivot_name);
+        } else {
+            self.pivots.insert(pivot_name.to_string(), state);
+        }
+    }
+}
+
+// Views held in Tauri state, written back to disk on every change
+pub struct ViewsState {
+    path: PathBuf,
+    views: Mutex<Views>,
+}
+
+impl ViewsState {
+    pub fn load(path: PathBuf) -> Self {
+        // Losing saved views is better than refusing to start
+        let views = Views::load(&path).unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            Views::default()
+        });
+
+        ViewsState {
+            path,
+            views: Mutex::new(views),
+        }
+    }
+
+    pub fn collapse_state(&self, pivot_name: &str) -> Option<CollapseState> {
+        self.views.lock().unwrap().collapse_state(pivot_name)
+    }
+
+    pub fn set_collapse_state(&self, pivot_name: &str, state: CollapseState) -> Result<(), ViewsError> {
+        let mut views = self.views.lock().unwrap();
+        views.set_collapse_state(pivot_name, state);
+        views.save(&self.path)
+    }
+
+    pub fn saved_pivots(&self) -> Vec<NamedPivot> {
+        self.views.lock().unwrap().saved_pivots()
+    }
+
+    pub fn save_pivot(&self, pivot: NamedPivot) -> Result<(), ViewsError> {
+        let mut views = self.views.lock().unwrap();
+        views.save_pivot(pivot);
+        views.save(&self.path)
+    }
+
+    pub fn delete_pivot(&self, pivot_name: &str) -> Result<(), ViewsError> {
+        let mut views = self.views.lock().unwrap();
+        views.delete_pivot(pivot_name);
+        views.save(&self.path)
+    }
+}
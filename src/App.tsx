@@ -1,14 +1,25 @@
 import { useState } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import "./App.css";
 import FileSelector from "./components/FileSelector";
 import PivotConfigurator from "./components/PivotConfigurator";
 import FilterConfigurator from "./components/FilterConfigurator";
 import PivotTable from "./components/PivotTable";
-import { FilterCondition, PivotRequest, PivotResult, ValueWithAggregation } from "./components/types";
+import { DatasetInfo, FilterCondition, PivotJobOutcome, PivotJobProgress, PivotPage, PivotRequest, PivotResult, SampleSpec, ValueWithAggregation } from "./components/types";
+import { errorMessage } from "./components/errors";
+import { runPivotArrow } from "./components/arrowResult";
+
+// Rows fetched at a time from a pivot result
+const PAGE_SIZE = 1000;
+// Results at least this wide load their remaining rows as Arrow in one go, as
+// JSON pages of wide rows take longer to build and parse than the pivot itself
+const ARROW_MIN_COLUMNS = 200;
+// Sample a draft pivot is built from
+const DRAFT_SAMPLE: SampleSpec = { Fraction: 0.01 };
 
 function App() {
-  const [filePath, setFilePath] = useState<string | null>(null);
+  const [datasetId, setDatasetId] = useState<number | null>(null);
   const [columns, setColumns] = useState<string[]>([]);
   const [rowFields, setRowFields] = useState<string[]>([]);
   const [columnFields, setColumnFields] = useState<string[]>([]);
@@ -17,10 +28,20 @@ function App() {
   const [pivotResult, setPivotResult] = useState<PivotResult | null>(null);
   const [isLoading, setIsLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [jobId, setJobId] = useState<number | null>(null);
+  const [progress, setProgress] = useState<PivotJobProgress | null>(null);
+  // Job whose result is shown, for fetching its other pages
+  const [resultJobId, setResultJobId] = useState<number | null>(null);
+  // Request of the result shown, for loading a wide one's rows as Arrow
+  const [resultRequest, setResultRequest] = useState<PivotRequest | null>(null);
+  const [draft, setDraft] = useState(false);
 
   // Handle file selection
-  const handleFileSelected = (path: string, columns: string[]) => {
-    setFilePath(path);
+  const handleFileSelected = (dataset: DatasetInfo, columns: string[]) => {
+    if (datasetId !== null && datasetId !== dataset.dataset_id) {
+      invoke("close_dataset", { datasetId });
+    }
+    setDatasetId(dataset.dataset_id);
     setColumns(columns);
     setPivotResult(null);
     setError(null);
@@ -43,9 +64,76 @@ function App() {
     setFilters(newFilters);
   };
 
+  // Run a pivot job and wait for its outcome; null when it was cancelled
+  const runPivotJob = async (request: PivotRequest): Promise<PivotResult | null> => {
+    // The job can finish before run_pivot has returned its id
+    const early = new Map<number, PivotJobOutcome>();
+    let started: number | null = null;
+    let finish: (outcome: PivotJobOutcome) => void = () => {};
+    const finished = new Promise<PivotJobOutcome>(resolve => { finish = resolve; });
+
+    const unlistenProgress = await listen<PivotJobProgress>("pivot://progress", (event) => {
+      if (event.payload.job_id === started) {
+        setProgress(event.payload);
+      }
+    });
+    const unlisten = await listen<PivotJobOutcome>("pivot://finished", (event) => {
+      if (started === null) {
+        early.set(event.payload.job_id, event.payload);
+      } else if (event.payload.job_id === started) {
+        finish(event.payload);
+      }
+    });
+
+    try {
+      started = await invoke("run_pivot", { request }) as number;
+      setJobId(started);
+      const outcome = early.get(started);
+      if (outcome) finish(outcome);
+
+      const { result, error } = await finished;
+      if (error) throw error;
+      if (result) setResultJobId(started);
+      return result;
+    } finally {
+      unlisten();
+      unlistenProgress();
+      setJobId(null);
+      setProgress(null);
+    }
+  };
+
+  const cancelPivot = () => {
+    if (jobId !== null) {
+      invoke("cancel_pivot", { jobId });
+    }
+  };
+
+  const loadMoreRows = async () => {
+    if (!pivotResult || resultJobId === null) return;
+
+    try {
+      // The job left the pivot in the result cache, so this only converts its rows
+      if (resultRequest && pivotResult.column_keys.length >= ARROW_MIN_COLUMNS) {
+        const { data } = await runPivotArrow(resultRequest);
+        setPivotResult({ ...pivotResult, data });
+        return;
+      }
+
+      const page = await invoke("get_pivot_page", {
+        jobId: resultJobId,
+        offset: pivotResult.data.length,
+        limit: PAGE_SIZE
+      }) as PivotPage;
+      setPivotResult({ ...pivotResult, data: [...pivotResult.data, ...page.data] });
+    } catch (err) {
+      setError(`Error loading rows: ${errorMessage(err)}`);
+    }
+  };
+
   // Generate pivot table
-  const generatePivot = async () => {
-    if (!filePath) {
+  const generatePivot = async (sample?: SampleSpec) => {
+    if (datasetId === null) {
       setError("Please select a file first");
       return;
     }
@@ -60,20 +148,34 @@ function App() {
 
     try {
       const request: PivotRequest = {
-        data_path: filePath,
+        dataset_id: datasetId,
         rows: rowFields,
         columns: columnFields,
         values: valueFields,
-        filters: filters.length > 0 ? filters : undefined
+        filters: filters.length > 0 ? filters : undefined,
+        page_size: PAGE_SIZE,
+        sample
       };
 
       console.log("Sending request:", request);
       
-      const result = await invoke("run_pivot", { request }) as PivotResult;
-      setPivotResult(result);
+      let result = await runPivotJob(request);
+      if (result?.too_wide) {
+        const { columns, limit } = result.too_wide;
+        if (!window.confirm(`This pivot would have about ${columns} columns (the limit is ${limit}). Build it anyway?`)) {
+          return;
+        }
+        request.confirm_wide = true;
+        result = await runPivotJob(request);
+      }
+      // A cancelled job leaves the previous table in place
+      if (result) {
+        setPivotResult(result);
+        setResultRequest(request);
+      }
     } catch (err) {
       console.error("Error generating pivot:", err);
-      setError(`Error generating pivot: ${err instanceof Error ? err.message : String(err)}`);
+      setError(`Error generating pivot: ${errorMessage(err)}`);
       setPivotResult(null);
     } finally {
       setIsLoading(false);
@@ -106,11 +208,30 @@ function App() {
               
               <button 
                 className="generate-button"
-                onClick={generatePivot}
+                onClick={() => generatePivot(draft ? DRAFT_SAMPLE : undefined)}
                 disabled={isLoading || valueFields.length === 0}
               >
                 Generate Pivot Table
               </button>
+
+              <label title="Build the pivot from 1% of the rows, for a quick look at a huge file">
+                <input
+                  type="checkbox"
+                  checked={draft}
+                  onChange={(e) => setDraft(e.target.checked)}
+                />
+                Draft on a sample
+              </label>
+
+              {jobId !== null && (
+                <div className="pivot-progress">
+                  <progress value={progress?.percent ?? 0} max={100} />
+                  <span>{progress?.stage ?? "Starting"}…</span>
+                  <button className="cancel-button" onClick={cancelPivot}>
+                    Cancel
+                  </button>
+                </div>
+              )}
             </>
           )}
           
@@ -122,9 +243,34 @@ function App() {
         </div>
         
         <div className="main-content">
+          {pivotResult?.sample && (
+            <div className="sample-notice">
+              Built from a sample of the rows ({"Fraction" in pivotResult.sample
+                ? `${pivotResult.sample.Fraction * 100}%`
+                : `first ${pivotResult.sample.FirstRows}`})
+              <button onClick={() => generatePivot()} disabled={isLoading}>
+                Run on full data
+              </button>
+            </div>
+          )}
+          {pivotResult?.warnings?.map((warning, index) => (
+            <div key={index} className="warning-message">{warning}</div>
+          ))}
+          {pivotResult?.stats && (
+            <div className="pivot-stats" title={
+              `Read ${pivotResult.stats.read_ms} ms, filter ${pivotResult.stats.filter_ms} ms, ` +
+              `aggregate ${pivotResult.stats.aggregate_ms} ms, reshape ${pivotResult.stats.reshape_ms} ms, ` +
+              `serialize ${pivotResult.stats.serialize_ms} ms`
+            }>
+              {pivotResult.stats.input_rows !== null && `${pivotResult.stats.input_rows} rows in, `}
+              {pivotResult.stats.output_rows} × {pivotResult.stats.output_columns} out
+              {pivotResult.cached && " (cached)"}
+            </div>
+          )}
           <PivotTable 
             result={pivotResult}
             isLoading={isLoading}
+            onLoadMore={loadMoreRows}
           />
         </div>
       </div>
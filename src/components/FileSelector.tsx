@@ -1,14 +1,32 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import { open } from "@tauri-apps/plugin-dialog";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { errorMessage } from "./errors";
+import { DatasetInfo, FileStats } from "./types";
+
+// Above this many rows a full pivot may take a while, so drafting on a sample is suggested
+const LARGE_FILE_ROWS = 50_000_000;
 
 interface FileSelectorProps {
-  onFileSelected: (path: string, columns: string[]) => void;
+  onFileSelected: (dataset: DatasetInfo, columns: string[]) => void;
   isLoading: boolean;
 }
 
 export default function FileSelector({ onFileSelected, isLoading }: FileSelectorProps) {
   const [error, setError] = useState<string | null>(null);
+  const [stats, setStats] = useState<FileStats | null>(null);
+  const [dataset, setDataset] = useState<DatasetInfo | null>(null);
+
+  // The dataset is read in the background once opened
+  useEffect(() => {
+    const unlisten = listen<DatasetInfo>("dataset://ready", (event) => {
+      setDataset((current) => current?.dataset_id === event.payload.dataset_id ? event.payload : current);
+    });
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
 
   async function selectFile() {
     try {
@@ -22,14 +40,18 @@ export default function FileSelector({ onFileSelected, isLoading }: FileSelector
       });
 
       if (selected && !Array.isArray(selected)) {
-        // Call Rust backend to get columns
+        // Start reading the data first, so it is ready by the first pivot
+        const opened = await invoke("open_dataset", { filePath: selected }) as DatasetInfo;
+        setDataset(opened);
+        setStats(null);
         const columns = await invoke("get_csv_columns", { filePath: selected }) as string[];
-        onFileSelected(selected, columns);
+        onFileSelected(opened, columns);
         setError(null);
+        setStats(await invoke("get_file_stats", { filePath: selected }) as FileStats);
       }
     } catch (err) {
       console.error("Error selecting file:", err);
-      setError(`Error loading file: ${err instanceof Error ? err.message : String(err)}`);
+      setError(`Error loading file: ${errorMessage(err)}`);
     }
   }
 
@@ -43,6 +65,19 @@ export default function FileSelector({ onFileSelected, isLoading }: FileSelector
         {isLoading ? "Loading..." : "Select CSV or Parquet File"}
       </button>
       
+      {stats && (
+        <div className="file-stats">
+          {stats.rows.toLocaleString()} rows × {stats.columns} columns
+          {stats.size_bytes != null && `, ${(stats.size_bytes / (1024 * 1024)).toFixed(1)} MB`}
+          {dataset?.loaded && ", in memory"}
+        </div>
+      )}
+      {stats && stats.rows > LARGE_FILE_ROWS && (
+        <div className="warning-message">
+          This file is large; pivots on it may take a while. Try drafting on a sample first.
+        </div>
+      )}
+      
       {error && (
         <div className="error-message">
           {error}
@@ -1,5 +1,5 @@
 import { useState } from "react";
-import { FilterCondition, FilterOperator } from "./types";
+import { BetweenOperator, FilterCondition, FilterOperator } from "./types";
 
 interface FilterConfiguratorProps {
   columns: string[];
@@ -29,13 +29,39 @@ export default function FilterConfigurator({ columns, onFiltersChange }: FilterC
     onFiltersChange(updatedFilters);
   };
 
+  const isBetween = (operator: FilterCondition["operator"]): operator is BetweenOperator =>
+    typeof operator === "object" && "Between" in operator;
+
+  // Name of the operator shown in the select, for the operators that carry fields too
+  const operatorName = (operator: FilterCondition["operator"]) =>
+    typeof operator === "string" ? operator : Object.keys(operator)[0];
+
+  const isInLastDays = (operator: FilterCondition["operator"]): operator is { InLastDays: { days: number; anchor?: string } } =>
+    typeof operator === "object" && "InLastDays" in operator;
+
   const updateFilter = (index: number, field: keyof FilterCondition, value: any) => {
     const updatedFilters = [...filters];
     
     if (field === "operator") {
-      updatedFilters[index].operator = value as FilterOperator;
+      updatedFilters[index].operator =
+        value === "Between" ? { Between: { low: null, high: null, inclusive: true } }
+        : value === "InLastDays" ? { InLastDays: { days: 7 } }
+        : value === "MonthToDate" ? { MonthToDate: {} }
+        : value === "YearToDate" ? { YearToDate: {} }
+        : value as FilterOperator;
     } else if (field === "column") {
       updatedFilters[index].column = value as string;
+    } else if (field === "case_insensitive") {
+      updatedFilters[index].case_insensitive = value as boolean;
+    } else if (field === "value" && isInLastDays(updatedFilters[index].operator)) {
+      updatedFilters[index].operator = { InLastDays: { days: Math.max(1, Number(value) || 1) } };
+    } else if (field === "value" && isBetween(updatedFilters[index].operator)) {
+      // "low, high" sets the bounds of a range
+      const [low, high] = (value as string).split(',').map(item => {
+        const bound = item?.trim() ?? "";
+        return bound !== "" && !isNaN(Number(bound)) ? Number(bound) : bound || null;
+      });
+      updatedFilters[index].operator = { Between: { low, high: high ?? null, inclusive: true } };
     } else if (field === "value") {
       // Try to parse numbers if possible
       if (!isNaN(Number(value)) && value.trim() !== "") {
@@ -102,7 +128,7 @@ export default function FilterConfigurator({ columns, onFiltersChange }: FilterC
               </select>
 
               <select
-                value={filter.operator}
+                value={operatorName(filter.operator)}
                 onChange={(e) => updateFilter(index, "operator", e.target.value)}
               >
                 <option value={FilterOperator.Equal}>Equal to</option>
@@ -112,12 +138,27 @@ export default function FilterConfigurator({ columns, onFiltersChange }: FilterC
                 <option value={FilterOperator.GreaterThanOrEqual}>Greater than or equal</option>
                 <option value={FilterOperator.LessThanOrEqual}>Less than or equal</option>
                 <option value={FilterOperator.In}>In list</option>
+                <option value={FilterOperator.Contains}>Contains</option>
+                <option value={FilterOperator.StartsWith}>Starts with</option>
+                <option value={FilterOperator.EndsWith}>Ends with</option>
+                <option value={FilterOperator.Matches}>Matches pattern</option>
+                <option value={FilterOperator.IsNull}>Is empty</option>
+                <option value={FilterOperator.IsNotNull}>Is not empty</option>
+                <option value="Between">Between</option>
+                <option value="InLastDays">In the last N days</option>
+                <option value="MonthToDate">Month to date</option>
+                <option value="YearToDate">Year to date</option>
               </select>
 
               <input
                 type="text"
                 value={
-                  Array.isArray(filter.value) 
+                  isInLastDays(filter.operator)
+                    ? String(filter.operator.InLastDays.days)
+                    : isBetween(filter.operator)
+                    ? [filter.operator.Between.low, filter.operator.Between.high]
+                        .map(bound => bound ?? "").join(", ")
+                    : Array.isArray(filter.value) 
                     ? filter.value.join(", ") 
                     : filter.value?.toString() || ""
                 }
@@ -125,10 +166,21 @@ export default function FilterConfigurator({ columns, onFiltersChange }: FilterC
                 placeholder={
                   filter.operator === FilterOperator.In 
                     ? "Value1, Value2, Value3..." 
+                    : isBetween(filter.operator)
+                    ? "From, To"
                     : "Value"
                 }
               />
 
+              <label title="Ignore case when comparing text">
+                <input
+                  type="checkbox"
+                  checked={filter.case_insensitive ?? false}
+                  onChange={(e) => updateFilter(index, "case_insensitive", e.target.checked)}
+                />
+                Aa
+              </label>
+
               <button
                 onClick={() => removeFilter(index)}
                 className="remove-filter-button"
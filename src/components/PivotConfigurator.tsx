@@ -85,7 +85,11 @@ export default function PivotConfigurator({ columns, onConfigChange }: PivotConf
                   >
                     <option value={AggregationType.Sum}>Sum</option>
                     <option value={AggregationType.Mean}>Mean</option>
-                    <option value={AggregationType.Count}>Count</option>
+                    <option value={AggregationType.GeometricMean}>Geometric Mean</option>
+                    <option value={AggregationType.Count}>Count (non-empty)</option>
+                    <option value={AggregationType.CountRows}>Count Rows</option>
+                    <option value={AggregationType.CountDistinct}>Count Distinct</option>
+                    <option value={AggregationType.ApproxCountDistinct}>Count Distinct (approximate)</option>
                     <option value={AggregationType.Min}>Min</option>
                     <option value={AggregationType.Max}>Max</option>
                     <option value={AggregationType.Median}>Median</option>
@@ -1,11 +1,13 @@
-import { PivotResult } from "./types";
+import { HeaderNode, NumberFormat, PivotResult } from "./types";
 
 interface PivotTableProps {
   result: PivotResult | null;
   isLoading: boolean;
+  // Fetches the next page of a result whose rows aren't all loaded
+  onLoadMore?: () => void;
 }
 
-export default function PivotTable({ result, isLoading }: PivotTableProps) {
+export default function PivotTable({ result, isLoading, onLoadMore }: PivotTableProps) {
   if (isLoading) {
     return <div className="loading">Generating pivot table...</div>;
   }
@@ -28,55 +30,91 @@ export default function PivotTable({ result, isLoading }: PivotTableProps) {
     return result.row_headers.map(header => String(row[header])).join('-');
   };
 
+  // One header row per tree level, each cell spanning the leaf columns below it
+  const countLeaves = (node: HeaderNode): number =>
+    node.children.length === 0 ? 1 : node.children.reduce((sum, child) => sum + countLeaves(child), 0);
+
+  const getHeaderRows = (nodes: HeaderNode[]) => {
+    const rows: { label: string; span: number }[][] = [];
+    const visit = (node: HeaderNode, depth: number) => {
+      (rows[depth] ??= []).push({ label: node.label, span: countLeaves(node) });
+      node.children.forEach(child => visit(child, depth + 1));
+    };
+    nodes.forEach(node => visit(node, 0));
+    return rows;
+  };
+
+  const formatCell = (value: any, format?: NumberFormat | null) => {
+    if (typeof value !== 'number') {
+      return value ?? '';
+    }
+    const decimals = format?.decimals;
+    return value.toLocaleString(undefined, {
+      style: format?.percent ? 'percent' : format?.currency ? 'currency' : 'decimal',
+      currency: format?.currency,
+      minimumFractionDigits: decimals,
+      maximumFractionDigits: decimals ?? 2,
+    });
+  };
+
+  const isSubtotalRow = (rowIndex: number) =>
+    (result.row_depths[rowIndex] ?? result.row_headers.length) < result.row_headers.length;
+
+  const isTotalColumn = (columnKey: string) => result.total_column_keys.includes(columnKey);
+
   return (
     <div className="pivot-table-container">
       <table className="pivot-table">
         <thead>
-          {result.column_headers.length > 0 && (
-            <tr>
+          {getHeaderRows(result.column_header_tree).map((level, levelIndex) => (
+            <tr key={levelIndex}>
               {/* Empty cell for row headers */}
               {result.row_headers.length > 0 && (
                 <th colSpan={result.row_headers.length}></th>
               )}
               
               {/* Column headers */}
-              {result.column_headers[0].map((header, index) => (
-                <th key={index}>{header}</th>
+              {level.map((header, index) => (
+                <th key={index} colSpan={header.span}>{header.label}</th>
               ))}
             </tr>
-          )}
+          ))}
         </thead>
         <tbody>
-          {result.data.map((row, _rowIndex) => (
-            <tr key={getRowKey(row)}>
+          {result.data.map((row, rowIndex) => (
+            <tr key={`${getRowKey(row)}-${rowIndex}`} className={isSubtotalRow(rowIndex) ? 'subtotal' : undefined}>
               {/* Row headers */}
               {result.row_headers.map(header => (
                 <th key={header}>{row[header]}</th>
               ))}
               
               {/* Data cells */}
-              {result.column_headers[0].map((colHeader, colIndex) => {
-                // Generate the key for this cell based on aggregation
-                const valueKeys = Object.keys(row).filter(key => 
-                  key.includes('_') && !result.row_headers.includes(key)
-                );
-                
-                // Find matching value for this column
-                const matchingKey = valueKeys.find(key => key.endsWith(colHeader));
-                const cellValue = matchingKey ? row[matchingKey] : '';
-                
-                return (
-                  <td key={colIndex}>
-                    {typeof cellValue === 'number' 
-                      ? cellValue.toLocaleString(undefined, { maximumFractionDigits: 2 })
-                      : cellValue}
-                  </td>
-                );
-              })}
+              {result.column_keys.map((columnKey, colIndex) => (
+                <td key={colIndex} className={isTotalColumn(columnKey) ? 'total' : undefined}>
+                  {formatCell(row[columnKey], result.column_formats[colIndex])}
+                </td>
+              ))}
             </tr>
           ))}
         </tbody>
+        {result.grand_total && (
+          <tfoot>
+            <tr className="grand-total">
+              {result.row_headers.length > 0 && (
+                <th colSpan={result.row_headers.length}>Total</th>
+              )}
+              {result.column_keys.map((columnKey, colIndex) => (
+                <td key={colIndex}>{formatCell(result.grand_total?.[columnKey], result.column_formats[colIndex])}</td>
+              ))}
+            </tr>
+          </tfoot>
+        )}
       </table>
+      {onLoadMore && result.data.length < result.total_rows && (
+        <button className="load-more-button" onClick={onLoadMore}>
+          Load more rows ({result.data.length} of {result.total_rows})
+        </button>
+      )}
     </div>
   );
 } 
\ No newline at end of file
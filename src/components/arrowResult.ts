@@ -0,0 +1,24 @@
+import { invoke } from "@tauri-apps/api/core";
+import { tableFromIPC } from "apache-arrow";
+import { PivotRequest, PivotResult } from "./types";
+
+// Runs the pivot through run_pivot_arrow, which sends the rows as Arrow IPC
+// after the rest of the result as JSON: [JSON length, u32 little endian][JSON][IPC]
+export async function runPivotArrow(request: PivotRequest): Promise<PivotResult> {
+  const buffer = await invoke<ArrayBuffer>("run_pivot_arrow", { request });
+  const view = new DataView(buffer);
+  const metadataLength = view.getUint32(0, true);
+
+  const metadata = new TextDecoder().decode(new Uint8Array(buffer, 4, metadataLength));
+  const result = JSON.parse(metadata) as PivotResult;
+  if (result.too_wide) {
+    return result;
+  }
+
+  const table = tableFromIPC(new Uint8Array(buffer, 4 + metadataLength));
+  // 64-bit integers decode as bigint, which the table formats as plain numbers
+  result.data = table.toArray().map(row => Object.fromEntries(
+    Object.entries(row.toJSON()).map(([key, value]) => [key, typeof value === "bigint" ? Number(value) : value])
+  ));
+  return result;
+}
@@ -0,0 +1,20 @@
+import { CommandError } from "./types";
+
+export function isCommandError(err: unknown): err is CommandError {
+  return typeof err === "object" && err !== null && "code" in err && "message" in err;
+}
+
+// Readable text for anything a command or the UI code threw
+export function errorMessage(err: unknown): string {
+  if (isCommandError(err)) {
+    switch (err.code) {
+      case "file_not_found":
+        return `${err.path ?? "The file"} no longer exists; it may have been moved or deleted`;
+      case "column_not_found":
+        return `The data has no column "${err.column}"; remove it from the pivot`;
+      default:
+        return err.message;
+    }
+  }
+  return err instanceof Error ? err.message : String(err);
+}
@@ -3,21 +3,56 @@
 export enum AggregationType {
   Sum = "Sum",
   Mean = "Mean",
+  GeometricMean = "GeometricMean",
   Count = "Count",
+  // Rows in the group, unlike Count which skips nulls
+  CountRows = "CountRows",
   Min = "Min",
   Max = "Max",
   First = "First",
   Last = "Last",
   Median = "Median",
   Std = "Std",
-  Var = "Var"
+  Var = "Var",
+  CountDistinct = "CountDistinct",
+  // Estimated distinct count, named approx_count_distinct_<field>
+  ApproxCountDistinct = "ApproxCountDistinct"
+}
+
+export enum DisplayMode {
+  CumulativeShare = "CumulativeShare",
+  DifferenceFromPrevious = "DifferenceFromPrevious",
+  PercentDifferenceFromPrevious = "PercentDifferenceFromPrevious"
+}
+
+export type RankTies = "Min" | "Max" | "Average" | "Dense" | "Ordinal";
+
+// Rank of each cell among the columns of its row, 1 being the largest
+export interface RankInRow {
+  RankInRow: { ties?: RankTies };
 }
 
 export interface ValueWithAggregation {
   field: string;
   aggregation: AggregationType;
+  display_mode?: DisplayMode | RankInRow;
+  filter?: FilterCondition;
+  // Skip by default
+  nulls?: NullPolicy;
+  format?: NumberFormat;
+}
+
+// Display format of a measure; echoed per column in PivotResult.column_formats
+export interface NumberFormat {
+  decimals?: number;
+  percent?: boolean;
+  // ISO 4217 code, e.g. "EUR"
+  currency?: string;
 }
 
+// How a measure treats null values: skip them, count them as 0, or make the cell null
+export type NullPolicy = "Skip" | "Zero" | "Propagate";
+
 export enum FilterOperator {
   Equal = "Equal",
   NotEqual = "NotEqual",
@@ -25,26 +60,429 @@ export enum FilterOperator {
   LessThan = "LessThan",
   GreaterThanOrEqual = "GreaterThanOrEqual",
   LessThanOrEqual = "LessThanOrEqual",
+  In = "In",
   Contains = "Contains",
-  In = "In"
+  StartsWith = "StartsWith",
+  EndsWith = "EndsWith",
+  // Regular expression
+  Matches = "Matches",
+  // Take no value
+  IsNull = "IsNull",
+  IsNotNull = "IsNotNull"
+}
+
+// Range condition; bounds are included unless inclusive is false
+export interface BetweenOperator {
+  Between: { low: any; high: any; inclusive?: boolean };
+}
+
+// Dates relative to anchor ("2024-06-30"), today when not set
+export type RelativeDateOperator =
+  | { InLastDays: { days: number; anchor?: string } }
+  | { MonthToDate: { anchor?: string } }
+  | { YearToDate: { anchor?: string } };
+
+// The count values of the column with the largest (bottom: smallest) measure
+export interface TopOperator {
+  Top: { count: number; measure: ValueWithAggregation; bottom?: boolean };
+}
+
+// Polars-SQL condition, e.g. "amount * quantity > 1000 AND region != 'EU'"
+export interface ExpressionOperator {
+  Expression: string;
 }
 
 export interface FilterCondition {
   column: string;
-  operator: FilterOperator;
+  operator: FilterOperator | BetweenOperator | RelativeDateOperator | TopOperator | ExpressionOperator;
   value: any;
+  // Compare text ignoring case
+  case_insensitive?: boolean;
+}
+
+export interface ReadOptions {
+  date_formats?: Record<string, string>;
+  auto_parse_dates?: boolean;
+  infer_schema_length?: { Rows: number } | "Full";
+  table?: string;
+  sanitize_headers?: boolean;
+  sheet?: string;
+  append_sheets?: boolean;
+  quote_char?: string;
+  escape_char?: string;
+  comment_prefix?: string;
+}
+
+export interface ColumnMetadata {
+  description?: string;
+  unit?: string;
+  tags?: string[];
+}
+
+export interface ColumnInfo {
+  name: string;
+  dtype: string;
+  detected_date_format: string | null;
+  original_name: string | null;
+  metadata: ColumnMetadata | null;
+}
+
+export type FileFormat = "csv" | "parquet" | "sqlite" | "json" | "ndjson" | "excel";
+
+export interface DatasetSchema {
+  columns: ColumnInfo[];
+  format?: FileFormat | null;
+}
+
+export enum DateGranularity {
+  Year = "Year",
+  Quarter = "Quarter",
+  Month = "Month",
+  Day = "Day",
+  Week = "Week",
+  Weekday = "Weekday"
 }
 
+// Adds a field of date buckets, named `{field}_{granularity}` unless `name` is set
+export interface DateGrouping {
+  field: string;
+  granularity: DateGranularity;
+  name?: string;
+  // Month (1-12) the fiscal year starts in; years and quarters become "FY2025 Q1"
+  fiscal_year_start?: number;
+}
+
+export type BinSpec =
+  | { FixedWidth: { width: number; origin?: number } }
+  | { Edges: number[] }
+  | { Quantiles: number };
+
+// Adds a field of numeric ranges such as "0–50", named `{field}_bins` unless `name` is set
+export interface NumericBinning {
+  field: string;
+  bins: BinSpec;
+  name?: string;
+}
+
+// Keeps the largest groups of the outermost row field, folding the rest into "Other"
+export interface TopRows {
+  count: number;
+  // Index into `values` of the ranking measure; the first when not set
+  measure?: number;
+  other_label?: string;
+}
+
+export type ColumnOrder = "Natural" | "Alphabetical" | "Descending";
+
+// Rows whose row or column field is null: grouped under a label such as "(blank)", or left out
+export type NullGroups = { Label: string } | "Exclude";
+
 export interface PivotRequest {
-  data_path: string;
+  // Filled in from dataset_id when that is set
+  data_path?: string;
+  // Handle from open_dataset, in place of data_path and read_options
+  dataset_id?: number | null;
   rows: string[];
   columns: string[];
   values: ValueWithAggregation[];
   filters?: FilterCondition[];
+  read_options?: ReadOptions;
+  collapse?: CollapseState;
+  show_row_totals?: boolean;
+  show_column_totals?: boolean;
+  show_subtotals?: boolean;
+  date_groupings?: DateGrouping[];
+  numeric_bins?: NumericBinning[];
+  top_rows?: TopRows;
+  column_order?: ColumnOrder;
+  // Explicit label order per field, e.g. { priority: ["Low", "Medium", "High"] }
+  sort_orders?: Record<string, string[]>;
+  // Add the label combinations that have no data, showing fill_value (e.g. 0) in their cells
+  densify?: boolean;
+  fill_value?: number;
+  null_groups?: NullGroups;
+  // Joins the labels of several column fields in a column key; "_" by default
+  column_separator?: string;
+  // Column key with {columns}, {measure} and {aggregation} placeholders,
+  // e.g. "{columns}|{measure}"
+  column_key_template?: string;
+  // Keep row groups in the order they first appear in the data instead of by label
+  maintain_order?: boolean;
+  // Build the pivot even when it is wider than the column limit
+  confirm_wide?: boolean;
+  // Row field combinations aggregated in one request, e.g. [["region"], []]
+  grouping_sets?: string[][];
+  // Swap the row and column axes of the result
+  transpose?: boolean;
+  // Deduplicate rows by these key fields (whole rows when empty) before pivoting
+  distinct_on?: string[];
+  // Conditions on aggregated measures by output name, e.g. sum_revenue > 10000
+  measure_filters?: FilterCondition[];
+  // Conditions on the pivoted columns by column key; failing rows are hidden
+  result_filters?: FilterCondition[];
+  // Table slices to hide, or to keep alone, picked from the pivot's cells
+  slice_filter?: SliceFilter;
+  // Rows sent with the result; the rest are fetched with get_pivot_page
+  page_size?: number;
+  // Pivot a sample of the rows for a quick draft of a huge file
+  sample?: SampleSpec;
+}
+
+// Row and column labels of a cell as shown, from the outermost field inwards;
+// shorter paths pick a whole group
+export interface CellSelection {
+  row?: string[];
+  column?: string[];
+}
+
+// Hides the selected slices, or keeps only them with keep
+export interface SliceFilter {
+  cells: CellSelection[];
+  keep?: boolean;
+}
+
+// Group label paths from the outermost field inwards, e.g. ["Europe", "France"]
+export interface CollapseState {
+  rows: string[][];
+  columns: string[][];
+}
+
+export interface ColumnStats {
+  min: number | null;
+  max: number | null;
+}
+
+// A column header cell spanning the leaf columns below it
+export interface HeaderNode {
+  label: string;
+  children: HeaderNode[];
+  // Data row key of a leaf column
+  key?: string | null;
 }
 
 export interface PivotResult {
   data: Record<string, any>[];
   column_headers: string[][];
+  // Column field labels from the outermost field inwards, then the measures
+  column_header_tree: HeaderNode[];
   row_headers: string[];
-} 
\ No newline at end of file
+  column_keys: string[];
+  // Column keys holding per-row totals across the column fields
+  total_column_keys: string[];
+  grand_total?: Record<string, any> | null;
+  // Row fields each data row is grouped on; lower for subtotal rows
+  row_depths: number[];
+  // Index into the request's grouping_sets of each data row's set
+  grouping_sets: number[];
+  // Format of the measure behind each column key
+  column_formats: (NumberFormat | null)[];
+  // Range of each column over the data rows, for color scales
+  column_stats: ColumnStats[];
+  // Notes on how the request was read, e.g. filter values converted to the column type
+  warnings: string[];
+  // Set instead of the data when the pivot needs confirming because of its width
+  too_wide?: PivotWidth | null;
+  // Rows of the whole result; more than data holds when it is paged
+  total_rows: number;
+  // Sample of the rows the pivot was built from; null for the full data
+  sample?: SampleSpec | null;
+  // Timings and row counts, for spotting slow pivots
+  stats: PivotStats;
+  // Read from the result cache; stats are from when it was computed
+  cached?: boolean;
+}
+
+// Milliseconds spent in each stage of a pivot and the data in and out
+export interface PivotStats {
+  read_ms: number;
+  filter_ms: number;
+  aggregate_ms: number;
+  reshape_ms: number;
+  serialize_ms: number;
+  // null when the data was streamed
+  input_rows: number | null;
+  output_rows: number;
+  output_columns: number;
+}
+
+export type PivotStage = "Reading" | "Filtering" | "Aggregating" | "Reshaping" | "Serializing";
+
+// Payload of the "pivot://progress" events of a job; percent is a rough 0-100
+export interface PivotJobProgress {
+  job_id: number;
+  stage: PivotStage;
+  percent: number;
+}
+
+// Payload of the "pivot://finished" event for the job run_pivot started;
+// neither result nor error is set when the job was cancelled
+export interface PivotJobOutcome {
+  job_id: number;
+  result: PivotResult | null;
+  error: CommandError | null;
+  cancelled: boolean;
+}
+
+// About a share of the rows (every nth row), or the first rows of the file
+export type SampleSpec = { Fraction: number } | { FirstRows: number };
+
+// Rows from offset of a paged result, from get_pivot_page
+export interface PivotPage {
+  offset: number;
+  data: Record<string, any>[];
+  total_rows: number;
+}
+
+export interface PivotWidth {
+  columns: number;
+  limit: number;
+} 
+export interface NamedPivot {
+  name: string;
+  request: PivotRequest;
+}
+
+export interface ExportProgress {
+  index: number;
+  total: number;
+  name: string;
+  path: string;
+}
+
+export interface DatabaseExtract {
+  data_path: string;
+  rows: number;
+  columns: string[];
+}
+
+// Rows a request's filters keep, from preview_filter_count
+// Query plan of a pivot's main aggregation, from explain_pivot
+export interface PivotPlan {
+  optimized: string;
+  unoptimized: string;
+  // Filters applied while reading, and only the used columns read
+  predicate_pushdown: boolean;
+  projection_pushdown: boolean;
+}
+
+export interface FilterCount {
+  matching: number;
+  total: number;
+}
+
+// A dataset kept open by open_dataset; rows is known once it is loaded in memory
+export interface DatasetInfo {
+  dataset_id: number;
+  loaded: boolean;
+  rows: number | null;
+}
+
+// One distinct value of a column from get_value_counts, most frequent first
+export interface ValueCount {
+  value: any;
+  count: number;
+  // Share of all rows, 0 to 100
+  percent: number;
+}
+
+// Melts wide columns into variable/value rows; run_unpivot answers with a DatabaseExtract
+export interface UnpivotRequest {
+  data_path: string;
+  read_options?: ReadOptions;
+  columns: string[];
+  variable_name?: string;
+  value_name?: string;
+}
+
+export interface PreviewOptions {
+  max_rows?: number;
+  time_budget_ms?: number;
+}
+
+export interface EngineSettings {
+  memory_budget_mb?: number | null;
+  // Largest pivoted result to build; half the memory available when not set
+  max_result_mb?: number | null;
+  // Threads Polars computes with, applied when the app next starts; all cores when not set
+  max_threads?: number | null;
+  // Least severe log messages written, e.g. "debug"; applied when the app next starts
+  log_level?: string | null;
+}
+
+export type AuditEvent =
+  | { kind: "FileOpened"; path: string }
+  | { kind: "DatabaseQueried"; source: string; query: string; extract_path: string }
+  | { kind: "PivotRun"; request: PivotRequest; rows: number }
+  | { kind: "ExportWritten"; pivot: string; request: PivotRequest; destination: string };
+
+export type AuditEntry = AuditEvent & { timestamp: string };
+
+export interface AuditQuery {
+  since?: string;
+  until?: string;
+  kinds?: AuditEvent["kind"][];
+  search?: string;
+  limit?: number;
+}
+
+export interface DownloadProgress {
+  url: string;
+  downloaded_bytes: number;
+  total_bytes?: number | null;
+}
+
+export interface S3Config {
+  access_key_id?: string | null;
+  secret_access_key?: string | null;
+  session_token?: string | null;
+  region?: string | null;
+  endpoint_url?: string | null;
+  // Forget the saved secrets; otherwise a secret left out keeps its saved value
+  clear_secrets?: boolean;
+}
+
+export interface AzureConfig {
+  account_name?: string | null;
+  account_key?: string | null;
+  sas_token?: string | null;
+  clear_secrets?: boolean;
+}
+
+export interface GcsConfig {
+  service_account_path?: string | null;
+}
+
+export interface FileStats {
+  rows: number;
+  columns: number;
+  // Total size of the files on disk; null for cloud objects
+  size_bytes: number | null;
+}
+
+export interface ParquetCache {
+  source_path: string;
+  cache_path: string;
+}
+
+export type ErrorCode =
+  | "file_not_found"
+  | "column_not_found"
+  | "dataset_not_open"
+  | "read_failed"
+  | "unsupported_format"
+  | "memory_budget_exceeded"
+  | "too_many_columns"
+  | "result_too_large"
+  | "cancelled"
+  | "processing_failed"
+  | "result_unavailable"
+  | "storage_failed"
+  | "internal";
+
+// What every command rejects with
+export interface CommandError {
+  code: ErrorCode;
+  message: string;
+  column?: string;
+  path?: string;
+}